@@ -1,16 +1,34 @@
 #![allow(dead_code)]
 
-use cosmic_text::{FontSystem, SwashCache};
+use cosmic_text::SwashCache;
 use image::{load_from_memory, RgbaImage};
 use log::{info, warn};
 use serde::Deserialize;
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
 
-use crate::browser_core::{BrowserCore, BrowserState};
+use crate::application::screen_diff::find_dirty_rects;
+use crate::browser_core::{BrowserCore, BrowserState, LoadingProgress};
 use crate::settings::Settings;
-use crate::ui::keyboard::{add_keyboard_overlay, KeyboardState};
-use crate::ui::topbar::{add_topbar_overlay, TopbarState};
-use crate::CANVAS_WIDTH;
+use crate::ui::go_to_page::{add_go_to_page_overlay, can_add_digit, parse_page_number};
+use crate::ui::keyboard::{
+    add_keyboard_overlay, get_key_at, get_key_char, is_within_keyboard, KeyCode, KeyboardState,
+};
+use crate::ui::loading::add_loading_progress_overlay;
+use crate::ui::progress::{get_progress_target, ProgressTarget};
+use crate::ui::table_of_contents::{
+    add_table_of_contents_overlay, get_table_of_contents_target, turn_page, TableOfContentsState,
+    TableOfContentsTarget,
+};
+use crate::ui::topbar::{
+    add_topbar_overlay, get_topbar_target, TopbarState, TopbarTarget, UrlBarContent,
+};
+use crate::{CANVAS_HEIGHT, CANVAS_WIDTH};
+
+mod screen_diff;
+
+const FONT_SIZE_STEP: i32 = 2;
+
+pub use screen_diff::DirtyRect;
 
 #[derive(Debug)]
 pub enum UserInputEvent {
@@ -19,6 +37,10 @@ pub enum UserInputEvent {
     RequestExit,
     ViewPreviousPage,
     ViewNextPage,
+    NavigateBack,
+    NavigateForward,
+    IncreaseFontSize,
+    DecreaseFontSize,
     Navigate(NavigateCommand),
     Render(RenderCommand),
 }
@@ -38,6 +60,22 @@ pub struct RenderCommand {
 #[derive(Debug)]
 pub enum OutputEvent {
     RenderFullScreen(RgbaImage),
+    /// Only parts of the screen changed since the last frame. The whole new frame is
+    /// included, but only the regions need to be redrawn.
+    RenderRegions {
+        frame: RgbaImage,
+        regions: Vec<DirtyRect>,
+        refresh_mode: RefreshMode,
+    },
+}
+
+/// How the e-ink panel should update the changed parts of the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefreshMode {
+    /// Quick and a bit ghosty, for small UI changes like typing on the keyboard
+    Fast,
+    /// Flashes to clear ghosting, for whole new pages
+    Full,
 }
 
 #[allow(dead_code)]
@@ -47,11 +85,20 @@ pub struct Application {
     pub output_tx: Sender<OutputEvent>,
     pub current_page_idx: usize,
 
-    pub font_system: FontSystem,
     pub swash_cache: SwashCache,
 
     pub topbar_state: TopbarState,
     pub keyboard_state: KeyboardState,
+    pub table_of_contents_state: TableOfContentsState,
+
+    // The text typed into the URL bar, while it's being edited
+    pub url_input: Option<String>,
+    // The page number typed into the go-to-page dialog, while it's open
+    pub page_number_input: Option<String>,
+    // The screen underneath the UI overlays, so we can redraw them on their own
+    pub current_view: Option<RgbaImage>,
+    // The last frame we sent out, so the next one only needs to update what changed
+    pub last_frame: Option<RgbaImage>,
 }
 
 impl Application {
@@ -60,7 +107,6 @@ impl Application {
         user_input_rx: Receiver<UserInputEvent>,
         output_tx: Sender<OutputEvent>,
     ) -> Self {
-        let font_system = FontSystem::new();
         let swash_cache = SwashCache::new();
 
         Self {
@@ -68,17 +114,21 @@ impl Application {
             user_input_rx,
             output_tx,
             current_page_idx: 0,
-            font_system,
             swash_cache,
             topbar_state: TopbarState::Normal,
-            keyboard_state: KeyboardState::Normal,
+            keyboard_state: KeyboardState::Hidden,
+            table_of_contents_state: TableOfContentsState::Hidden,
+            url_input: None,
+            page_number_input: None,
+            current_view: None,
+            last_frame: None,
         }
     }
 
     pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         info!("Application running");
 
-        while let Ok(input_event) = self.user_input_rx.recv() {
+        while let Some(input_event) = self.next_input_event() {
             match input_event {
                 UserInputEvent::RequestInitialPaint => {
                     info!("Requesting initial paint");
@@ -88,28 +138,12 @@ impl Application {
                             .unwrap()
                             .to_rgba8();
 
-                    self.render_screen(placeholder_view);
+                    self.render_screen(placeholder_view, RefreshMode::Full);
                 }
                 UserInputEvent::Tap { x, y } => {
                     info!("Tap event: {:?}", (x, y));
 
-                    match self.browser_core.state {
-                        BrowserState::ViewingPage {
-                            url: _,
-                            page_canvases: _,
-                        } => {
-                            if x < CANVAS_WIDTH / 3 {
-                                info!("Tap: Previous page");
-                                self.view_previous_page();
-                            } else {
-                                info!("Tap: Next page");
-                                self.view_next_page();
-                            }
-                        }
-                        _ => {
-                            info!("Ignoring tap event, not in viewing state");
-                        }
-                    };
+                    self.handle_tap(x, y);
                 }
                 UserInputEvent::RequestExit => {
                     info!("Requesting exit");
@@ -118,80 +152,36 @@ impl Application {
                 UserInputEvent::Navigate(command) => {
                     info!("Received event: Navigate to {}", command.url);
 
-                    let placeholder_view =
-                        load_from_memory(include_bytes!("../assets/placeholder-loading-view.png"))
-                            .unwrap()
-                            .to_rgba8();
-
-                    self.render_screen(placeholder_view);
-
-                    self.browser_core.navigate_to(&command.url);
-
-                    match &self.browser_core.state {
-                        BrowserState::ViewingPage {
-                            url: _,
-                            page_canvases,
-                        } => {
-                            info!("Page loaded successfully");
-
-                            self.current_page_idx = 0;
-                            let page_canvas = page_canvases.get(0).unwrap().clone();
-                            self.render_screen(page_canvas);
-                        }
-                        BrowserState::PageError { url: _, error: _ } => {
-                            warn!("Failed to load the page, time to show the error view!");
-
-                            let placeholder_view = load_from_memory(include_bytes!(
-                                "../assets/placeholder-error-view.png"
-                            ))
-                            .unwrap()
-                            .to_rgba8();
-
-                            self.render_screen(placeholder_view);
-                        }
-                        _ => {
-                            unreachable!("Unexpected browser state after navigation");
-                        }
-                    }
+                    self.handle_navigate_command(command);
                 }
                 UserInputEvent::Render(command) => {
                     info!("Received event: Render HTML {}", command.html);
 
-                    let placeholder_view =
-                        load_from_memory(include_bytes!("../assets/placeholder-loading-view.png"))
-                            .unwrap()
-                            .to_rgba8();
-
-                    self.render_screen(placeholder_view);
+                    self.render_loading_view();
 
                     self.browser_core.render(&command.html, &command.page_url);
 
-                    match &self.browser_core.state {
-                        BrowserState::ViewingPage {
-                            url: _,
-                            page_canvases,
-                        } => {
-                            info!("Page loaded successfully");
+                    self.wait_for_page();
+                }
+                UserInputEvent::NavigateBack => {
+                    info!("Received event: Navigate back");
 
-                            self.current_page_idx = 0;
-                            let page_canvas = page_canvases.get(0).unwrap().clone();
-                            self.render_screen(page_canvas);
-                        }
-                        BrowserState::PageError { url: _, error: _ } => {
-                            warn!("Failed to load the page, time to show the error view!");
+                    self.go_back();
+                }
+                UserInputEvent::NavigateForward => {
+                    info!("Received event: Navigate forward");
 
-                            let placeholder_view = load_from_memory(include_bytes!(
-                                "../assets/placeholder-error-view.png"
-                            ))
-                            .unwrap()
-                            .to_rgba8();
+                    self.go_forward();
+                }
+                UserInputEvent::IncreaseFontSize => {
+                    info!("Received event: Increase font size");
 
-                            self.render_screen(placeholder_view);
-                        }
-                        _ => {
-                            unreachable!("Unexpected browser state after render");
-                        }
-                    }
+                    self.change_font_size(FONT_SIZE_STEP);
+                }
+                UserInputEvent::DecreaseFontSize => {
+                    info!("Received event: Decrease font size");
+
+                    self.change_font_size(-FONT_SIZE_STEP);
                 }
                 UserInputEvent::ViewPreviousPage => {
                     match self.browser_core.state {
@@ -219,16 +209,439 @@ impl Application {
         Ok(())
     }
 
-    fn view_next_page(&mut self) {
-        match self.browser_core.get_pages().get(self.current_page_idx + 1) {
-            Some(page_canvas) => {
-                self.current_page_idx += 1;
-                self.render_screen(page_canvas.clone());
+    /// Waits for the next input, loading the rest of the page in the meantime
+    fn next_input_event(&mut self) -> Option<UserInputEvent> {
+        while self.browser_core.is_loading() {
+            match self.user_input_rx.try_recv() {
+                Ok(input_event) => return Some(input_event),
+                Err(TryRecvError::Empty) => self.continue_loading(),
+                Err(TryRecvError::Disconnected) => return None,
+            }
+        }
+
+        self.user_input_rx.recv().ok()
+    }
+
+    fn handle_tap(&mut self, x: u32, y: u32) {
+        if is_within_keyboard(y, CANVAS_HEIGHT, &self.keyboard_state) {
+            match get_key_at(x, y, CANVAS_WIDTH, CANVAS_HEIGHT, &self.keyboard_state) {
+                Some(key) => self.handle_key_press(key),
+                None => info!("Ignoring tap between keys"),
+            }
+            return;
+        }
+
+        if self.page_number_input.is_some() {
+            info!("Tap: Close go-to-page dialog");
+            self.close_go_to_page();
+            return;
+        }
+
+        if let Some(target) = get_topbar_target(x, y, CANVAS_WIDTH, &self.topbar_state) {
+            match target {
+                TopbarTarget::Back => {
+                    info!("Tap: Back");
+                    self.go_back();
+                }
+                TopbarTarget::Forward => {
+                    info!("Tap: Forward");
+                    self.go_forward();
+                }
+                TopbarTarget::UrlBar => {
+                    info!("Tap: URL bar");
+                    self.start_editing_url();
+                }
+                TopbarTarget::Menu => {
+                    info!("Tap: Menu");
+                    self.toggle_table_of_contents();
+                }
+            }
+            return;
+        }
+
+        if self.table_of_contents_state != TableOfContentsState::Hidden {
+            self.handle_table_of_contents_tap(x, y);
+            return;
+        }
+
+        if self.url_input.is_some() {
+            info!("Tap: Stop editing URL");
+            self.stop_editing_url();
+            return;
+        }
+
+        match &self.browser_core.state {
+            BrowserState::ViewingPage { pages, .. } => {
+                // There's no bar to tap until it's known how many pages there are
+                let total_pages = if self.browser_core.is_laid_out() {
+                    pages.len()
+                } else {
+                    0
+                };
+                let progress_target =
+                    get_progress_target(x, y, CANVAS_WIDTH, CANVAS_HEIGHT, total_pages);
+
+                if let Some(ProgressTarget::Page(page_idx)) = progress_target {
+                    info!("Tap: Progress bar, page {}", page_idx + 1);
+                    self.view_page(page_idx);
+                } else if let Some(ProgressTarget::PageNumber) = progress_target {
+                    info!("Tap: Page number");
+                    self.open_go_to_page();
+                } else if let Some(destination) = self.find_link_at(x, y) {
+                    info!("Tap: Follow link to {}", destination);
+                    self.navigate_to(&destination);
+                } else if x < CANVAS_WIDTH / 3 {
+                    info!("Tap: Previous page");
+                    self.view_previous_page();
+                } else {
+                    info!("Tap: Next page");
+                    self.view_next_page();
+                }
+            }
+            _ => {
+                info!("Ignoring tap event, not in viewing state");
+            }
+        };
+    }
+
+    fn toggle_table_of_contents(&mut self) {
+        if self.table_of_contents_state != TableOfContentsState::Hidden {
+            self.close_table_of_contents();
+            return;
+        }
+
+        let BrowserState::ViewingPage { .. } = self.browser_core.state else {
+            info!("Ignoring menu tap, not in viewing state");
+            return;
+        };
+
+        self.table_of_contents_state = TableOfContentsState::open_at(
+            self.browser_core.get_table_of_contents(),
+            self.current_page_idx,
+            CANVAS_HEIGHT,
+        );
+        self.render_current_view();
+    }
+
+    fn close_table_of_contents(&mut self) {
+        self.table_of_contents_state = TableOfContentsState::Hidden;
+        self.render_current_view();
+    }
+
+    fn handle_table_of_contents_tap(&mut self, x: u32, y: u32) {
+        let entries = self.browser_core.get_table_of_contents();
+        let target = get_table_of_contents_target(
+            x,
+            y,
+            CANVAS_WIDTH,
+            CANVAS_HEIGHT,
+            &self.table_of_contents_state,
+            entries,
+        );
+
+        match target {
+            Some(TableOfContentsTarget::Entry(entry_idx)) => {
+                let entry = &entries[entry_idx];
+                info!("Tap: Table of contents entry {}", entry.title);
+
+                let page_idx = entry.page_idx;
+                self.table_of_contents_state = TableOfContentsState::Hidden;
+                self.render_loaded_page(page_idx);
+            }
+            Some(TableOfContentsTarget::PreviousPage) | Some(TableOfContentsTarget::NextPage) => {
+                let delta = if target == Some(TableOfContentsTarget::NextPage) {
+                    1
+                } else {
+                    -1
+                };
+                self.table_of_contents_state =
+                    turn_page(&self.table_of_contents_state, delta, entries, CANVAS_HEIGHT);
+                self.render_current_view();
+            }
+            Some(TableOfContentsTarget::Panel) => {
+                info!("Ignoring tap inside the table of contents");
             }
             None => {
-                warn!("No next page to display, ignoring tap");
+                info!("Tap: Close table of contents");
+                self.close_table_of_contents();
+            }
+        }
+    }
+
+    fn open_go_to_page(&mut self) {
+        self.page_number_input = Some(String::new());
+        self.keyboard_state = KeyboardState::Normal;
+        self.render_current_view();
+    }
+
+    fn close_go_to_page(&mut self) {
+        self.page_number_input = None;
+        self.keyboard_state = KeyboardState::Hidden;
+        self.render_current_view();
+    }
+
+    fn handle_page_number_key(&mut self, key: KeyCode) {
+        let total_pages = self.browser_core.get_pages().len();
+        let Some(page_number_input) = self.page_number_input.as_mut() else {
+            return;
+        };
+
+        match key {
+            KeyCode::Backspace => {
+                page_number_input.pop();
+            }
+            KeyCode::Return => {
+                let Some(page_idx) = parse_page_number(page_number_input, total_pages) else {
+                    info!("Ignoring return without a page number");
+                    return;
+                };
+
+                self.page_number_input = None;
+                self.keyboard_state = KeyboardState::Hidden;
+                self.view_page(page_idx);
+                return;
+            }
+            other => match get_key_char(&other) {
+                Some(c) if c.is_ascii_digit() && can_add_digit(page_number_input, total_pages) => {
+                    page_number_input.push(c);
+                }
+                _ => {
+                    info!("Ignoring key in the go-to-page dialog: {:?}", other);
+                    return;
+                }
+            },
+        }
+
+        self.render_current_view();
+    }
+
+    fn start_editing_url(&mut self) {
+        if self.url_input.is_some() {
+            return;
+        }
+
+        self.url_input = Some(String::new());
+        self.keyboard_state = KeyboardState::Normal;
+        self.render_current_view();
+    }
+
+    fn stop_editing_url(&mut self) {
+        self.url_input = None;
+        self.keyboard_state = KeyboardState::Hidden;
+        self.render_current_view();
+    }
+
+    fn handle_key_press(&mut self, key: KeyCode) {
+        info!("Key pressed: {:?}", key);
+
+        if self.page_number_input.is_some() {
+            self.handle_page_number_key(key);
+            return;
+        }
+
+        let Some(url_input) = self.url_input.as_mut() else {
+            warn!("Key pressed while not editing, ignoring");
+            return;
+        };
+
+        match key {
+            KeyCode::Shift => {
+                self.keyboard_state = match self.keyboard_state {
+                    KeyboardState::Shift => KeyboardState::Normal,
+                    _ => KeyboardState::Shift,
+                };
+            }
+            KeyCode::Backspace => {
+                url_input.pop();
+            }
+            KeyCode::Return => {
+                let url = url_input.trim().to_string();
+                if url.is_empty() {
+                    info!("Ignoring return with an empty URL");
+                    return;
+                }
+
+                self.url_input = None;
+                self.keyboard_state = KeyboardState::Hidden;
+                self.handle_navigate_command(NavigateCommand { url });
+                return;
+            }
+            other => {
+                if let Some(c) = get_key_char(&other) {
+                    url_input.push(c);
+                }
+
+                // Shift only applies to the next character, like on a phone
+                if self.keyboard_state == KeyboardState::Shift {
+                    self.keyboard_state = KeyboardState::Normal;
+                }
+            }
+        }
+
+        self.render_current_view();
+    }
+
+    fn handle_navigate_command(&mut self, command: NavigateCommand) {
+        self.navigate_to(&command.url);
+    }
+
+    fn navigate_to(&mut self, url: &str) {
+        if let Some(page_idx) = self.browser_core.jump_to_fragment(url) {
+            self.render_loaded_page(page_idx);
+            return;
+        }
+
+        self.render_loading_view();
+
+        self.browser_core.navigate_to(url);
+
+        self.wait_for_page();
+    }
+
+    fn go_back(&mut self) {
+        if !self.browser_core.can_go_back() {
+            warn!("No previous page in the history, ignoring");
+            return;
+        }
+
+        self.render_loading_view();
+
+        self.browser_core.go_back();
+
+        self.wait_for_page();
+    }
+
+    fn go_forward(&mut self) {
+        if !self.browser_core.can_go_forward() {
+            warn!("No next page in the history, ignoring");
+            return;
+        }
+
+        self.render_loading_view();
+
+        self.browser_core.go_forward();
+
+        self.wait_for_page();
+    }
+
+    /// Lays out the current page again in the new size, keeping our place in it
+    fn change_font_size(&mut self, delta: i32) {
+        if let Some(page_idx) = self.browser_core.change_font_size(delta) {
+            self.render_loaded_page(page_idx);
+        }
+    }
+
+    /// Shows the loading progress until the page is ready to open, then the page. The rest
+    /// of it loads in between handling input.
+    fn wait_for_page(&mut self) {
+        loop {
+            match self.browser_core.continue_loading() {
+                LoadingProgress::LoadingImages { loaded, total } => {
+                    self.render_loading_progress(loaded, total);
+                }
+                LoadingProgress::Ready { page_idx } => {
+                    self.render_loaded_page(page_idx);
+                    return;
+                }
+                LoadingProgress::PagesRendered { .. } => {}
+                LoadingProgress::Finished => return,
+            }
+        }
+    }
+
+    /// Takes a step of loading the rest of the page, and shows the page we're on again if
+    /// it was laid out again with its images
+    fn continue_loading(&mut self) {
+        match self.browser_core.continue_loading() {
+            LoadingProgress::Ready { page_idx } => self.render_loaded_page(page_idx),
+            LoadingProgress::PagesRendered { page_idxs }
+                if page_idxs.contains(&self.current_page_idx) =>
+            {
+                self.view_page(self.current_page_idx);
+            }
+            _ => {}
+        }
+    }
+
+    fn render_loading_view(&mut self) {
+        let placeholder_view = load_loading_placeholder();
+
+        self.render_screen(placeholder_view, RefreshMode::Full);
+    }
+
+    /// Redraws the loading view with the new count. Only the count changes on screen.
+    fn render_loading_progress(&mut self, loaded: usize, total: usize) {
+        let mut loading_view = load_loading_placeholder();
+        add_loading_progress_overlay(
+            &mut loading_view,
+            &mut self.browser_core.font_system,
+            &mut self.swash_cache,
+            loaded,
+            total,
+        );
+
+        self.render_screen(loading_view, RefreshMode::Fast);
+    }
+
+    fn render_loaded_page(&mut self, page_idx: usize) {
+        match &self.browser_core.state {
+            BrowserState::ViewingPage {
+                pages, metadata, ..
+            } => {
+                info!(
+                    "Page loaded successfully: {}",
+                    metadata.title.as_deref().unwrap_or("(untitled)")
+                );
+
+                // The page may have rendered differently since we were last here
+                let page_idx = page_idx.min(pages.len() - 1);
+                let page_canvas = pages[page_idx].canvas.clone();
+
+                self.current_page_idx = page_idx;
+                self.browser_core.set_current_page_idx(page_idx);
+                self.table_of_contents_state = TableOfContentsState::Hidden;
+                self.render_screen(page_canvas, RefreshMode::Full);
             }
+            BrowserState::PageError { url: _, error: _ } => {
+                warn!("Failed to load the page, time to show the error view!");
+                self.table_of_contents_state = TableOfContentsState::Hidden;
+
+                let placeholder_view =
+                    load_from_memory(include_bytes!("../assets/placeholder-error-view.png"))
+                        .unwrap()
+                        .to_rgba8();
+
+                self.render_screen(placeholder_view, RefreshMode::Full);
+            }
+            _ => {
+                unreachable!("Unexpected browser state after loading a page");
+            }
+        }
+    }
+
+    fn find_link_at(&self, x: u32, y: u32) -> Option<String> {
+        let page = self.browser_core.get_pages().get(self.current_page_idx)?;
+
+        page.links
+            .iter()
+            .find(|link| link.contains(x, y))
+            .map(|link| link.destination.clone())
+    }
+
+    fn view_next_page(&mut self) {
+        // The next page may not be laid out yet
+        while self.current_page_idx + 1 >= self.browser_core.get_pages().len()
+            && !self.browser_core.is_laid_out()
+        {
+            self.continue_loading();
         }
+
+        if self.current_page_idx + 1 >= self.browser_core.get_pages().len() {
+            warn!("No next page to display, ignoring tap");
+            return;
+        }
+
+        self.view_page(self.current_page_idx + 1);
     }
 
     fn view_previous_page(&mut self) {
@@ -237,36 +650,114 @@ impl Application {
             return;
         }
 
-        match self.browser_core.get_pages().get(self.current_page_idx - 1) {
-            Some(page_canvas) => {
-                self.current_page_idx -= 1;
-                self.render_screen(page_canvas.clone());
+        self.view_page(self.current_page_idx - 1);
+    }
+
+    fn view_page(&mut self, page_idx: usize) {
+        match self.browser_core.get_pages().get(page_idx) {
+            Some(page) => {
+                let page_canvas = page.canvas.clone();
+                self.current_page_idx = page_idx;
+                self.browser_core
+                    .set_current_page_idx(self.current_page_idx);
+                self.render_screen(page_canvas, RefreshMode::Full);
             }
             None => {
-                warn!("No previous page to display, ignoring tap");
+                warn!("No page {} to display, ignoring", page_idx + 1);
             }
         }
     }
 
-    fn render_screen(&mut self, page_canvas: RgbaImage) {
+    /// Redraws the last view with fresh UI overlays on top
+    fn render_current_view(&mut self) {
+        if let Some(view) = self.current_view.clone() {
+            self.render_screen(view, RefreshMode::Fast);
+        }
+    }
+
+    fn render_screen(&mut self, page_canvas: RgbaImage, refresh_mode: RefreshMode) {
         let mut canvas_with_ui = page_canvas.clone();
+        self.current_view = Some(page_canvas);
+
+        // The font system lives in the browser core, so read everything else from it first
+        let can_go_back = self.browser_core.can_go_back();
+        let can_go_forward = self.browser_core.can_go_forward();
+        let current_url = self.browser_core.get_current_url().map(str::to_string);
+        let table_of_contents = self.browser_core.get_table_of_contents().to_vec();
+        let is_laid_out = self.browser_core.is_laid_out();
+        let total_pages = match &self.browser_core.state {
+            BrowserState::ViewingPage { pages, .. } => pages.len(),
+            _ => 0,
+        };
+
+        let url_bar_content = match &self.url_input {
+            Some(text) => UrlBarContent::Editing(text),
+            None => UrlBarContent::Url(current_url.as_deref()),
+        };
 
         add_topbar_overlay(
             &mut canvas_with_ui,
-            &mut self.font_system,
+            &mut self.browser_core.font_system,
             &mut self.swash_cache,
             &self.topbar_state,
+            can_go_back,
+            can_go_forward,
+            &url_bar_content,
+        );
+
+        add_table_of_contents_overlay(
+            &mut canvas_with_ui,
+            &mut self.browser_core.font_system,
+            &mut self.swash_cache,
+            &self.table_of_contents_state,
+            &table_of_contents,
+            self.current_page_idx,
+            is_laid_out,
+        );
+
+        add_go_to_page_overlay(
+            &mut canvas_with_ui,
+            &mut self.browser_core.font_system,
+            &mut self.swash_cache,
+            self.page_number_input.as_deref(),
+            total_pages,
         );
 
         add_keyboard_overlay(
             &mut canvas_with_ui,
-            &mut self.font_system,
+            &mut self.browser_core.font_system,
             &mut self.swash_cache,
             &self.keyboard_state,
         );
 
-        self.output_tx
-            .send(OutputEvent::RenderFullScreen(canvas_with_ui))
-            .unwrap();
+        self.send_frame(canvas_with_ui, refresh_mode);
     }
+
+    fn send_frame(&mut self, frame: RgbaImage, refresh_mode: RefreshMode) {
+        let output_event = match &self.last_frame {
+            None => OutputEvent::RenderFullScreen(frame.clone()),
+            Some(last_frame) => {
+                let regions = find_dirty_rects(last_frame, &frame);
+                if regions.is_empty() {
+                    info!("Nothing changed on screen, skipping the refresh");
+                    return;
+                }
+
+                OutputEvent::RenderRegions {
+                    frame: frame.clone(),
+                    regions,
+                    refresh_mode,
+                }
+            }
+        };
+
+        self.last_frame = Some(frame);
+        self.output_tx.send(output_event).unwrap();
+    }
+}
+
+fn load_loading_placeholder() -> RgbaImage {
+    load_from_memory(include_bytes!("../assets/placeholder-loading-view.png"))
+        .unwrap()
+        .to_rgba8()
 }
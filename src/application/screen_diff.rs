@@ -0,0 +1,124 @@
+use cgmath::Point2;
+use image::RgbaImage;
+
+/// Changed rows this close together are refreshed as one region, since every refresh has
+/// its own overhead on the e-ink panel
+const MERGE_GAP_ROWS: u32 = 24;
+
+/// An area of the screen that changed, with inclusive corners
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirtyRect {
+    pub top_left: Point2<u32>,
+    pub bottom_right: Point2<u32>,
+}
+
+impl DirtyRect {
+    pub fn width(&self) -> u32 {
+        self.bottom_right.x - self.top_left.x + 1
+    }
+
+    pub fn height(&self) -> u32 {
+        self.bottom_right.y - self.top_left.y + 1
+    }
+}
+
+/// Finds the parts of the screen that differ between two frames, as horizontal bands
+/// trimmed to the changed columns. The keyboard and topbar end up as separate regions.
+pub fn find_dirty_rects(previous_frame: &RgbaImage, next_frame: &RgbaImage) -> Vec<DirtyRect> {
+    if previous_frame.dimensions() != next_frame.dimensions() {
+        return vec![DirtyRect {
+            top_left: Point2::new(0, 0),
+            bottom_right: Point2::new(next_frame.width() - 1, next_frame.height() - 1),
+        }];
+    }
+
+    let mut dirty_rects: Vec<DirtyRect> = vec![];
+
+    for y in 0..next_frame.height() {
+        let Some((min_x, max_x)) = find_changed_columns(previous_frame, next_frame, y) else {
+            continue;
+        };
+
+        match dirty_rects.last_mut() {
+            Some(last_rect) if y - last_rect.bottom_right.y <= MERGE_GAP_ROWS => {
+                last_rect.top_left.x = last_rect.top_left.x.min(min_x);
+                last_rect.bottom_right.x = last_rect.bottom_right.x.max(max_x);
+                last_rect.bottom_right.y = y;
+            }
+            _ => {
+                dirty_rects.push(DirtyRect {
+                    top_left: Point2::new(min_x, y),
+                    bottom_right: Point2::new(max_x, y),
+                });
+            }
+        }
+    }
+
+    dirty_rects
+}
+
+fn find_changed_columns(
+    previous_frame: &RgbaImage,
+    next_frame: &RgbaImage,
+    y: u32,
+) -> Option<(u32, u32)> {
+    let is_changed = |x: u32| previous_frame.get_pixel(x, y) != next_frame.get_pixel(x, y);
+
+    let min_x = (0..next_frame.width()).find(|x| is_changed(*x))?;
+    let max_x = (0..next_frame.width()).rev().find(|x| is_changed(*x))?;
+
+    Some((min_x, max_x))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::Rgba;
+
+    const WHITE: Rgba<u8> = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
+    const BLACK: Rgba<u8> = Rgba([0x00, 0x00, 0x00, 0xFF]);
+
+    #[test]
+    fn identical_frames() {
+        let frame = RgbaImage::from_pixel(100, 100, WHITE);
+
+        assert_eq!(find_dirty_rects(&frame, &frame.clone()), vec![]);
+    }
+
+    #[test]
+    fn separate_regions() {
+        let previous_frame = RgbaImage::from_pixel(200, 200, WHITE);
+        let mut next_frame = previous_frame.clone();
+
+        // Something like the URL bar near the top...
+        next_frame.put_pixel(20, 5, BLACK);
+        next_frame.put_pixel(60, 10, BLACK);
+        // ...and a key on the keyboard near the bottom
+        next_frame.put_pixel(150, 180, BLACK);
+
+        assert_eq!(
+            find_dirty_rects(&previous_frame, &next_frame),
+            vec![
+                DirtyRect {
+                    top_left: Point2::new(20, 5),
+                    bottom_right: Point2::new(60, 10),
+                },
+                DirtyRect {
+                    top_left: Point2::new(150, 180),
+                    bottom_right: Point2::new(150, 180),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn different_sizes() {
+        let previous_frame = RgbaImage::from_pixel(10, 10, WHITE);
+        let next_frame = RgbaImage::from_pixel(20, 30, WHITE);
+
+        let dirty_rects = find_dirty_rects(&previous_frame, &next_frame);
+        assert_eq!(dirty_rects.len(), 1);
+        assert_eq!(dirty_rects[0].width(), 20);
+        assert_eq!(dirty_rects[0].height(), 30);
+    }
+}
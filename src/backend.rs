@@ -4,6 +4,9 @@ pub mod desktop_backend;
 #[cfg(feature = "remarkable")]
 pub mod remarkable_backend;
 
+#[cfg(test)]
+pub mod headless_backend;
+
 pub trait Backend {
     fn run(&mut self) -> Result<(), Box<dyn std::error::Error>>;
 }
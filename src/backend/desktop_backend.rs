@@ -1,6 +1,6 @@
-use image::Rgb;
+use image::{Rgb, RgbaImage};
 use log::info;
-use softbuffer::Surface;
+use softbuffer::{Rect, Surface};
 use std::num::NonZeroU32;
 use std::rc::Rc;
 use std::sync::mpsc::{Receiver, Sender, TryRecvError};
@@ -11,7 +11,7 @@ use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
 use winit::keyboard::{Key, NamedKey};
 use winit::window::{Window, WindowId};
 
-use crate::application::{OutputEvent, UserInputEvent};
+use crate::application::{DirtyRect, OutputEvent, UserInputEvent};
 use crate::backend::Backend;
 use crate::{CANVAS_HEIGHT, CANVAS_WIDTH};
 
@@ -39,6 +39,49 @@ impl DesktopBackend {
     }
 }
 
+impl DesktopBackend {
+    /// Draws the whole frame, since softbuffer doesn't promise to keep the previous one,
+    /// but only tells the window about the damaged regions if there are any
+    fn present_frame(&mut self, image: &RgbaImage, regions: Option<&[DirtyRect]>) {
+        let mut buffer = self
+            .surface
+            .as_mut()
+            .expect("Surface not initialized")
+            .buffer_mut()
+            .unwrap();
+
+        let image_width = image.width() as usize;
+
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let red = pixel.0[0] as u32;
+            let green = pixel.0[1] as u32;
+            let blue = pixel.0[2] as u32;
+
+            let color = blue | (green << 8) | (red << 16);
+            buffer[y as usize * image_width + x as usize] = color;
+        }
+
+        match regions {
+            Some(regions) => {
+                let damage: Vec<Rect> = regions
+                    .iter()
+                    .map(|region| Rect {
+                        x: region.top_left.x,
+                        y: region.top_left.y,
+                        width: NonZeroU32::new(region.width()).unwrap(),
+                        height: NonZeroU32::new(region.height()).unwrap(),
+                    })
+                    .collect();
+
+                buffer.present_with_damage(&damage).unwrap();
+            }
+            None => {
+                buffer.present().unwrap();
+            }
+        }
+    }
+}
+
 impl Backend for DesktopBackend {
     fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         info!("Desktop backend running");
@@ -105,25 +148,20 @@ impl ApplicationHandler for DesktopBackend {
                         OutputEvent::RenderFullScreen(image) => {
                             info!("Received output event: RenderFullScreen");
 
-                            let mut buffer = self
-                                .surface
-                                .as_mut()
-                                .expect("Surface not initialized")
-                                .buffer_mut()
-                                .unwrap();
-
-                            let image_width = image.width() as usize;
-
-                            for (x, y, pixel) in image.enumerate_pixels() {
-                                let red = pixel.0[0] as u32;
-                                let green = pixel.0[1] as u32;
-                                let blue = pixel.0[2] as u32;
-
-                                let color = blue | (green << 8) | (red << 16);
-                                buffer[y as usize * image_width + x as usize] = color;
-                            }
+                            self.present_frame(&image, None);
+                        }
+                        OutputEvent::RenderRegions {
+                            frame,
+                            regions,
+                            refresh_mode,
+                        } => {
+                            info!(
+                                "Received output event: RenderRegions ({} regions, {:?} refresh)",
+                                regions.len(),
+                                refresh_mode
+                            );
 
-                            buffer.present().unwrap();
+                            self.present_frame(&frame, Some(&regions));
                         }
                     },
                     Err(TryRecvError::Empty) => {
@@ -175,6 +213,30 @@ impl ApplicationHandler for DesktopBackend {
                             .send(UserInputEvent::ViewNextPage)
                             .unwrap();
                     }
+                    Key::Named(NamedKey::BrowserBack) => {
+                        info!("Browser back key pressed");
+                        self.user_input_tx
+                            .send(UserInputEvent::NavigateBack)
+                            .unwrap();
+                    }
+                    Key::Named(NamedKey::BrowserForward) => {
+                        info!("Browser forward key pressed");
+                        self.user_input_tx
+                            .send(UserInputEvent::NavigateForward)
+                            .unwrap();
+                    }
+                    Key::Character(ref character) if character == "+" || character == "=" => {
+                        info!("Plus key pressed");
+                        self.user_input_tx
+                            .send(UserInputEvent::IncreaseFontSize)
+                            .unwrap();
+                    }
+                    Key::Character(ref character) if character == "-" => {
+                        info!("Minus key pressed");
+                        self.user_input_tx
+                            .send(UserInputEvent::DecreaseFontSize)
+                            .unwrap();
+                    }
                     Key::Named(NamedKey::Escape) => {
                         info!("Escape key pressed");
                         event_loop.exit();
@@ -194,10 +256,10 @@ impl ApplicationHandler for DesktopBackend {
             }
             WindowEvent::MouseInput {
                 device_id: _,
-                state,
+                state: ElementState::Pressed,
                 button,
-            } => {
-                if state == ElementState::Pressed && button == MouseButton::Left {
+            } => match button {
+                MouseButton::Left => {
                     let cursor_x: u32 = self.mouse_position.0 as u32;
                     let cursor_y: u32 = self.mouse_position.1 as u32;
                     info!("Left mouse button pressed at ({}, {})", cursor_x, cursor_y);
@@ -209,7 +271,20 @@ impl ApplicationHandler for DesktopBackend {
                         })
                         .unwrap();
                 }
-            }
+                MouseButton::Back => {
+                    info!("Back mouse button pressed");
+                    self.user_input_tx
+                        .send(UserInputEvent::NavigateBack)
+                        .unwrap();
+                }
+                MouseButton::Forward => {
+                    info!("Forward mouse button pressed");
+                    self.user_input_tx
+                        .send(UserInputEvent::NavigateForward)
+                        .unwrap();
+                }
+                _ => {}
+            },
             _ => (),
         }
     }
@@ -0,0 +1,358 @@
+use image::{Rgba, RgbaImage};
+use log::info;
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender};
+
+use crate::application::{DirtyRect, OutputEvent, RefreshMode, UserInputEvent};
+use crate::backend::Backend;
+
+const COLOR_DIFF_SAME: Rgba<u8> = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
+const COLOR_DIFF_CHANGED: Rgba<u8> = Rgba([0xFF, 0x00, 0x00, 0xFF]);
+
+/// A backend without a screen or input devices. It plays a script of input events to the
+/// application, then keeps every frame the application sends back.
+pub struct HeadlessBackend {
+    script: Vec<UserInputEvent>,
+    user_input_tx: Sender<UserInputEvent>,
+    output_rx: Receiver<OutputEvent>,
+    frames: Vec<CapturedFrame>,
+}
+
+/// A frame the application sent, and how it asked for the screen to be refreshed
+pub struct CapturedFrame {
+    pub image: RgbaImage,
+    // None for a full screen render
+    pub refresh_mode: Option<RefreshMode>,
+    pub regions: Vec<DirtyRect>,
+}
+
+/// How two images differ
+pub struct ImageComparison {
+    pub differing_pixels: u32,
+    // Changed pixels in red, everything else white
+    pub diff_image: RgbaImage,
+}
+
+impl HeadlessBackend {
+    pub fn new(
+        user_input_tx: Sender<UserInputEvent>,
+        output_rx: Receiver<OutputEvent>,
+        script: Vec<UserInputEvent>,
+    ) -> Self {
+        Self {
+            script,
+            user_input_tx,
+            output_rx,
+            frames: vec![],
+        }
+    }
+
+    pub fn frames(&self) -> &[CapturedFrame] {
+        &self.frames
+    }
+
+    pub fn last_frame(&self) -> Option<&CapturedFrame> {
+        self.frames.last()
+    }
+
+    /// Saves every frame as `frame-000.png`, `frame-001.png`, ... in the directory
+    pub fn write_frames(&self, directory: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(directory)?;
+
+        for (frame_idx, frame) in self.frames.iter().enumerate() {
+            let file_path = directory.join(format!("frame-{:03}.png", frame_idx));
+            frame.image.save(file_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Queues up the whole script, ending with a request to exit
+    pub fn send_script(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        info!(
+            "Headless backend running {} scripted events",
+            self.script.len()
+        );
+
+        self.user_input_tx
+            .send(UserInputEvent::RequestInitialPaint)?;
+        for input_event in self.script.drain(..) {
+            self.user_input_tx.send(input_event)?;
+        }
+        self.user_input_tx.send(UserInputEvent::RequestExit)?;
+
+        Ok(())
+    }
+
+    /// Keeps the frames the application sends, until it exits
+    pub fn capture_frames(&mut self) {
+        // The application handles events in order, and hangs up once it has exited
+        while let Ok(output_event) = self.output_rx.recv() {
+            self.capture(output_event);
+        }
+
+        info!("Headless backend captured {} frames", self.frames.len());
+    }
+
+    fn capture(&mut self, output_event: OutputEvent) {
+        let frame = match output_event {
+            OutputEvent::RenderFullScreen(image) => CapturedFrame {
+                image,
+                refresh_mode: None,
+                regions: vec![],
+            },
+            OutputEvent::RenderRegions {
+                frame,
+                regions,
+                refresh_mode,
+            } => CapturedFrame {
+                image: frame,
+                refresh_mode: Some(refresh_mode),
+                regions,
+            },
+        };
+
+        self.frames.push(frame);
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.send_script()?;
+        self.capture_frames();
+
+        Ok(())
+    }
+}
+
+/// Compares two images pixel by pixel. Channels within the tolerance count as the same,
+/// so tiny antialiasing differences don't count as changes.
+pub fn compare_images(expected: &RgbaImage, actual: &RgbaImage, tolerance: u8) -> ImageComparison {
+    let width = expected.width().max(actual.width());
+    let height = expected.height().max(actual.height());
+
+    let mut differing_pixels = 0;
+    let mut diff_image = RgbaImage::from_pixel(width, height, COLOR_DIFF_SAME);
+
+    for y in 0..height {
+        for x in 0..width {
+            let is_same = match (
+                expected.get_pixel_checked(x, y),
+                actual.get_pixel_checked(x, y),
+            ) {
+                (Some(expected_pixel), Some(actual_pixel)) => expected_pixel
+                    .0
+                    .iter()
+                    .zip(actual_pixel.0.iter())
+                    .all(|(a, b)| a.abs_diff(*b) <= tolerance),
+                _ => false,
+            };
+
+            if !is_same {
+                differing_pixels += 1;
+                diff_image.put_pixel(x, y, COLOR_DIFF_CHANGED);
+            }
+        }
+    }
+
+    ImageComparison {
+        differing_pixels,
+        diff_image,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::{Application, NavigateCommand, RenderCommand};
+    use crate::settings::Settings;
+    use crate::{CANVAS_HEIGHT, CANVAS_MARGIN_BOTTOM, CANVAS_WIDTH};
+    use std::sync::mpsc::channel;
+
+    fn run_script(script: Vec<UserInputEvent>) -> HeadlessBackend {
+        let (user_input_tx, user_input_rx) = channel::<UserInputEvent>();
+        let (output_tx, output_rx) = channel::<OutputEvent>();
+
+        // Every event is waiting before the application starts, so it never loads pages in
+        // the background between them, and the frames come out the same every time
+        let mut backend = HeadlessBackend::new(user_input_tx, output_rx, script);
+        backend.send_script().unwrap();
+
+        let mut app = Application::new(Settings::default(), user_input_rx, output_tx);
+        let app_handle = std::thread::spawn(move || {
+            app.run().unwrap();
+        });
+
+        backend.capture_frames();
+        app_handle.join().unwrap();
+
+        backend
+    }
+
+    fn render_command(html: &str) -> UserInputEvent {
+        UserInputEvent::Render(RenderCommand {
+            html: html.to_string(),
+            page_url: "https://example.com/".to_string(),
+        })
+    }
+
+    fn long_article() -> String {
+        // Numbered, so pages shown before the page numbers are drawn still differ
+        let paragraphs: String = (1..=40)
+            .map(|number| format!("<p>{}. Lorem ipsum dolor sit amet, consectetur adipiscing elit. Morbi posuere eros dui, tempus bibendum sem efficitur at. In vitae bibendum justo, quis eleifend purus.</p>", number))
+            .collect();
+
+        format!("<html><body>{}</body></html>", paragraphs)
+    }
+
+    #[test]
+    fn initial_paint() {
+        let backend = run_script(vec![]);
+
+        assert_eq!(backend.frames().len(), 1);
+
+        let frame = &backend.frames()[0];
+        assert_eq!(frame.refresh_mode, None);
+        assert_eq!(frame.image.dimensions(), (CANVAS_WIDTH, CANVAS_HEIGHT));
+    }
+
+    #[test]
+    fn page_through_an_article() {
+        let backend = run_script(vec![
+            render_command(&long_article()),
+            UserInputEvent::ViewNextPage,
+            UserInputEvent::ViewPreviousPage,
+        ]);
+
+        // Initial paint, loading view, first page, second page, first page again
+        let frames = backend.frames();
+        assert_eq!(frames.len(), 5);
+
+        let first_page = &frames[2];
+        let second_page = &frames[3];
+        assert_eq!(second_page.refresh_mode, Some(RefreshMode::Full));
+        assert!(compare_images(&first_page.image, &second_page.image, 0).differing_pixels > 0);
+        assert_eq!(
+            compare_images(&first_page.image, &frames[4].image, 0).differing_pixels,
+            0
+        );
+    }
+
+    #[test]
+    fn change_font_size() {
+        let backend = run_script(vec![
+            render_command(&long_article()),
+            UserInputEvent::ViewNextPage,
+            UserInputEvent::IncreaseFontSize,
+            UserInputEvent::DecreaseFontSize,
+        ]);
+
+        // Initial paint, loading view, first page, second page, then the same place in the
+        // article laid out bigger, then back to how it was
+        let frames = backend.frames();
+        assert_eq!(frames.len(), 6);
+
+        let second_page = &frames[3];
+        let bigger_text = &frames[4];
+        assert_eq!(bigger_text.refresh_mode, Some(RefreshMode::Full));
+        assert!(compare_images(&second_page.image, &bigger_text.image, 0).differing_pixels > 0);
+
+        // The second page was shown before the rest were laid out, so its page number
+        // doesn't say how many pages there are yet, but it's there
+        let crop = |frame: &CapturedFrame, top_y: u32, height: u32| {
+            image::imageops::crop_imm(&frame.image, 0, top_y, CANVAS_WIDTH, height).to_image()
+        };
+        let text_area = |frame| crop(frame, 0, CANVAS_HEIGHT - CANVAS_MARGIN_BOTTOM);
+        let overlay_area = |frame| {
+            crop(
+                frame,
+                CANVAS_HEIGHT - CANVAS_MARGIN_BOTTOM,
+                CANVAS_MARGIN_BOTTOM,
+            )
+        };
+        assert_eq!(
+            compare_images(&text_area(second_page), &text_area(&frames[5]), 0).differing_pixels,
+            0
+        );
+        let blank_overlay_area = RgbaImage::from_pixel(
+            CANVAS_WIDTH,
+            CANVAS_MARGIN_BOTTOM,
+            Rgba([0xFF, 0xFF, 0xFF, 0xFF]),
+        );
+        assert!(
+            compare_images(&blank_overlay_area, &overlay_area(second_page), 0).differing_pixels > 0
+        );
+    }
+
+    #[test]
+    fn type_into_the_url_bar() {
+        let backend = run_script(vec![
+            render_command("<html><body><p>Hello</p></body></html>"),
+            // Open the keyboard, then type a letter
+            UserInputEvent::Tap {
+                x: CANVAS_WIDTH / 2,
+                y: 36,
+            },
+            UserInputEvent::Tap {
+                x: CANVAS_WIDTH / 2 + 30,
+                y: CANVAS_HEIGHT - 250,
+            },
+        ]);
+
+        let frames = backend.frames();
+        assert_eq!(frames.len(), 5);
+        let last_frame = backend.last_frame().unwrap();
+        assert!(compare_images(&frames[2].image, &last_frame.image, 0).differing_pixels > 0);
+
+        for typing_frame in &frames[3..] {
+            assert_eq!(typing_frame.refresh_mode, Some(RefreshMode::Fast));
+            assert!(!typing_frame.regions.is_empty());
+        }
+    }
+
+    #[test]
+    fn error_view() {
+        let backend = run_script(vec![UserInputEvent::Navigate(NavigateCommand {
+            url: "http://127.0.0.1:1/".to_string(),
+        })]);
+
+        // Initial paint, loading view, error view
+        let frames = backend.frames();
+        assert_eq!(frames.len(), 3);
+
+        let error_view = &frames[2];
+        assert_eq!(error_view.refresh_mode, Some(RefreshMode::Full));
+        assert!(compare_images(&frames[1].image, &error_view.image, 0).differing_pixels > 0);
+    }
+
+    #[test]
+    fn write_frames_to_png() {
+        let backend = run_script(vec![]);
+        // Its own directory, so test runs side by side don't read each other's frames
+        let directory = std::env::temp_dir().join(format!(
+            "skelly-headless-frames-{}-write_frames_to_png",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+
+        backend.write_frames(&directory).unwrap();
+
+        let written = image::open(directory.join("frame-000.png")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(written.width(), CANVAS_WIDTH);
+    }
+
+    #[test]
+    fn compare_with_tolerance() {
+        let expected = RgbaImage::from_pixel(4, 4, Rgba([100, 100, 100, 255]));
+        let mut actual = RgbaImage::from_pixel(4, 4, Rgba([102, 100, 100, 255]));
+        actual.put_pixel(1, 2, Rgba([0, 0, 0, 255]));
+
+        let comparison = compare_images(&expected, &actual, 2);
+        assert_eq!(comparison.differing_pixels, 1);
+        assert_eq!(*comparison.diff_image.get_pixel(1, 2), COLOR_DIFF_CHANGED);
+        assert_eq!(*comparison.diff_image.get_pixel(0, 0), COLOR_DIFF_SAME);
+
+        assert_eq!(compare_images(&expected, &actual, 1).differing_pixels, 16);
+    }
+}
@@ -2,12 +2,12 @@ use cgmath::Point2;
 use image::{load_from_memory, RgbaImage};
 use libremarkable::framebuffer::common::*;
 use libremarkable::framebuffer::core::Framebuffer;
-use libremarkable::framebuffer::{FramebufferIO, FramebufferRefresh};
+use libremarkable::framebuffer::{FramebufferIO, FramebufferRefresh, PartialRefreshMode};
 use libremarkable::input::{ev::EvDevContext, InputDevice, InputEvent, MultitouchEvent};
 use log::info;
 use std::sync::mpsc::{channel, Receiver, Sender};
 
-use crate::application::{OutputEvent, UserInputEvent};
+use crate::application::{DirtyRect, OutputEvent, RefreshMode, UserInputEvent};
 use crate::backend::Backend;
 
 pub struct RemarkableBackend {
@@ -35,6 +35,53 @@ impl RemarkableBackend {
         }
     }
 
+    fn render_region(&mut self, frame: &RgbaImage, region: &DirtyRect) {
+        for y in region.top_left.y..=region.bottom_right.y {
+            for x in region.top_left.x..=region.bottom_right.x {
+                let pixel = frame.get_pixel(x, y);
+                let pixel_pos = Point2::<u32>::new(x, y);
+                self.framebuffer.write_pixel(
+                    pixel_pos.cast().unwrap(),
+                    color::RGB(pixel.0[0], pixel.0[1], pixel.0[2]),
+                );
+            }
+        }
+    }
+
+    fn refresh_region(&mut self, region: &DirtyRect, refresh_mode: RefreshMode) {
+        let rect = mxcfb_rect {
+            top: region.top_left.y,
+            left: region.top_left.x,
+            width: region.width(),
+            height: region.height(),
+        };
+
+        match refresh_mode {
+            RefreshMode::Fast => {
+                self.framebuffer.partial_refresh(
+                    &rect,
+                    PartialRefreshMode::Async,
+                    waveform_mode::WAVEFORM_MODE_GC16_FAST,
+                    display_temp::TEMP_USE_REMARKABLE_DRAW,
+                    dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+                    0,
+                    false,
+                );
+            }
+            RefreshMode::Full => {
+                self.framebuffer.partial_refresh(
+                    &rect,
+                    PartialRefreshMode::Async,
+                    waveform_mode::WAVEFORM_MODE_GC16,
+                    display_temp::TEMP_USE_AMBIENT,
+                    dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+                    0,
+                    true,
+                );
+            }
+        }
+    }
+
     fn refresh_screen(&mut self) {
         self.framebuffer.full_refresh(
             waveform_mode::WAVEFORM_MODE_INIT,
@@ -83,6 +130,16 @@ impl Backend for RemarkableBackend {
                     self.render_page(&image);
                     self.refresh_screen();
                 }
+                OutputEvent::RenderRegions {
+                    frame,
+                    regions,
+                    refresh_mode,
+                } => {
+                    for region in regions.iter() {
+                        self.render_region(&frame, region);
+                        self.refresh_region(region, refresh_mode);
+                    }
+                }
             }
         }
 
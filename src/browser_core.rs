@@ -1,20 +1,49 @@
 use std::collections::HashMap;
+use std::ops::Range;
+use std::time::Duration;
 
+use cosmic_text::{FontSystem, SwashCache};
 use image::RgbaImage;
 use log::{error, info, warn};
 
+use crate::browser_core::history::{History, HistoryEntry};
+use crate::browser_core::image_loader::{ImageLimits, ImageLoader, NextImage};
 use crate::browser_core::network::{
-    fetch_image, fetch_webpage, resolve_url, ContentType, ImageResponse,
+    build_http_client, fetch_webpage, get_base_url, keep_fragment, normalize_typed_url,
+    split_fragment, try_resolve_url, ContentType,
 };
-use crate::browser_core::parsing::{parse_webpage, Block, Document};
-use crate::browser_core::rendering::Renderer;
+use crate::browser_core::parsing::{parse_article, parse_webpage, Block, Document, Span};
+use crate::browser_core::rendering::{
+    find_page_for_position, DocumentLayout, Renderer, SourcePosition,
+};
+use crate::browser_core::table_of_contents::build_table_of_contents;
+use crate::fonts::build_font_system;
 use crate::settings::Settings;
 
 mod debugging;
+mod history;
+mod image_loader;
 mod network;
 mod parsing;
 mod rendering;
+mod svg;
+mod table_of_contents;
+
+#[cfg(test)]
+mod snapshot_tests;
+
+pub use parsing::DocumentMetadata;
+pub use rendering::RenderedPage;
+pub use table_of_contents::TableOfContentsEntry;
 
+pub const MIN_FONT_SIZE: u32 = 8;
+pub const MAX_FONT_SIZE: u32 = 32;
+
+// How long to wait for an image at a time once the pages are laid out, so taps still get
+// handled in between
+const IMAGE_WAIT: Duration = Duration::from_millis(100);
+
+#[allow(clippy::large_enum_variant)]
 pub enum BrowserState {
     Initial,
     LoadingPage {
@@ -22,7 +51,8 @@ pub enum BrowserState {
     },
     ViewingPage {
         url: String,
-        page_canvases: Vec<image::RgbaImage>,
+        pages: Vec<RenderedPage>,
+        metadata: DocumentMetadata,
     },
     PageError {
         url: String,
@@ -31,29 +61,303 @@ pub enum BrowserState {
 }
 
 pub type ImagesByUrl = HashMap<String, Option<RgbaImage>>;
+/// Images ready to go on a page, by their URL and the width they're shown at
+pub type PreparedImages = HashMap<(String, u32), RgbaImage>;
+
+/// What changed in a step of loading a page
+#[derive(Debug, PartialEq)]
+pub enum LoadingProgress {
+    /// Still fetching images, and this many of them are in
+    LoadingImages { loaded: usize, total: usize },
+    /// The page to open the document on is laid out, or loading it failed
+    Ready { page_idx: usize },
+    /// These pages were laid out, for the first time or again with more of their images
+    PagesRendered { page_idxs: Range<usize> },
+    /// Every page is laid out with all the images we could get
+    Finished,
+}
+
+/// Where to open a page once it's loaded
+enum PageToShow {
+    Index(usize),
+    // The page with the element the URL's fragment points at
+    Fragment(String),
+}
+
+/// Everything needed to lay out the current page again, without fetching it again
+struct LoadedDocument {
+    url: String,
+    // What its relative links and images point from
+    base_url: String,
+    document: Document,
+    images: ImagesByUrl,
+    prepared_images: PreparedImages,
+    // What was at the top of the page we're reading
+    reading_position: SourcePosition,
+    // The headings on the pages laid out so far, and the pages they're on
+    table_of_contents: Vec<TableOfContentsEntry>,
+    // How far laying it out into pages has got
+    layout: DocumentLayout,
+}
 
 pub struct BrowserCore {
     pub settings: Settings,
     pub state: BrowserState,
+    // Shared with the UI overlays, since building it means loading every font
+    pub font_system: FontSystem,
+    // Kept between layouts, so glyphs are only rasterized once
+    swash_cache: SwashCache,
+    // Shared by every request, so they reuse connections
+    http_client: reqwest::blocking::Client,
+    history: History,
+    loaded_document: Option<LoadedDocument>,
+    // Fetching the images of the page we're loading, until they're all in
+    image_loader: Option<ImageLoader>,
+    // Where to open the page we're loading, until it's ready there
+    page_to_show: Option<PageToShow>,
 }
 
 impl BrowserCore {
     pub fn new(settings: Settings) -> Self {
+        let font_system = build_font_system(&settings.rendering);
+        let http_client = build_http_client(&settings.network);
+
         Self {
             settings,
+            font_system,
+            swash_cache: SwashCache::new(),
+            http_client,
             state: BrowserState::Initial,
+            history: History::new(),
+            loaded_document: None,
+            image_loader: None,
+            page_to_show: None,
         }
     }
 
+    /// Starts loading a URL. Once it's loaded, the page to show is the one with the element
+    /// the URL's fragment points at, or the first.
     pub fn navigate_to(&mut self, url: &str) {
+        let url = normalize_typed_url(url);
         info!("Navigating to {}", url);
 
+        self.history.push(HistoryEntry::new(&url, None));
+        self.page_to_show = Some(match split_fragment(&url) {
+            (_, Some(fragment)) => PageToShow::Fragment(fragment),
+            (_, None) => PageToShow::Index(0),
+        });
+        self.load_url(&url);
+    }
+
+    /// Whether there's more to do for the page we're loading
+    pub fn is_loading(&self) -> bool {
+        self.page_to_show.is_some() || self.image_loader.is_some() || !self.is_laid_out()
+    }
+
+    /// Takes the next step of loading the page: laying out another page, or putting in an
+    /// image that came in. Pages come first, so the text can be read while images are on
+    /// their way, and the pages an image shows up on are laid out again once it's in.
+    pub fn continue_loading(&mut self) -> LoadingProgress {
+        let is_laid_out = self.is_laid_out();
+
+        if let Some(image_loader) = &mut self.image_loader {
+            // Images that are in already go in before laying out more pages without them
+            let wait = if is_laid_out {
+                IMAGE_WAIT
+            } else {
+                Duration::ZERO
+            };
+
+            match image_loader.next_image(wait) {
+                NextImage::Loaded(image_url, image) => {
+                    let (loaded, total) = image_loader.progress();
+                    self.add_image(image_url, image);
+
+                    return LoadingProgress::LoadingImages { loaded, total };
+                }
+                NextImage::Pending if is_laid_out => {
+                    let (loaded, total) = image_loader.progress();
+
+                    return LoadingProgress::LoadingImages { loaded, total };
+                }
+                NextImage::Pending => {}
+                NextImage::Done => self.image_loader = None,
+            }
+        }
+
+        if !self.is_laid_out() {
+            let page_idxs = self.render_next_pages();
+
+            return match self.take_page_to_show() {
+                Some(page_idx) => LoadingProgress::Ready { page_idx },
+                None => LoadingProgress::PagesRendered { page_idxs },
+            };
+        }
+
+        // Like when loading failed, or the fragment wasn't on any page
+        if let Some(page_idx) = self.take_page_to_show() {
+            return LoadingProgress::Ready { page_idx };
+        }
+
+        LoadingProgress::Finished
+    }
+
+    /// Waits until the page is loaded, returning the page of it to show
+    pub fn finish_loading(&mut self) -> usize {
+        let mut page_to_show = 0;
+
+        loop {
+            match self.continue_loading() {
+                LoadingProgress::Ready { page_idx } => page_to_show = page_idx,
+                LoadingProgress::Finished => return page_to_show,
+                _ => {}
+            }
+        }
+    }
+
+    /// Jumps to a fragment like `#history` of the page we're viewing, without loading it
+    /// again. Returns the page to show, or None if the URL is for some other page.
+    pub fn jump_to_fragment(&mut self, url: &str) -> Option<usize> {
+        let (_, Some(fragment)) = split_fragment(url) else {
+            return None;
+        };
+        if !self.is_viewing_document(url) {
+            return None;
+        }
+
+        info!("Jumping to #{} in the current page", fragment);
+
+        // Fragments of a page sent to us as HTML can be loaded again the same way
+        let current_entry = self.history.current();
+        let current_page_idx = current_entry.map_or(0, |entry| entry.page_idx);
+        let html = current_entry.and_then(|entry| entry.html.clone());
+        self.history.push(HistoryEntry::new(url, html.as_deref()));
+        self.set_document_url(url);
+
+        let page_idx = self.find_fragment_page(&fragment).unwrap_or_else(|| {
+            warn!("Nothing in the page has the id {}", fragment);
+            current_page_idx
+        });
+
+        Some(page_idx)
+    }
+
+    /// Starts loading a page sent to us as HTML, to be shown from the first page
+    pub fn render(&mut self, html: &str, page_url: &str) {
+        info!("Rendering direct HTML from page url: {}", page_url);
+
+        self.history.push(HistoryEntry::new(page_url, Some(html)));
+        self.page_to_show = Some(PageToShow::Index(0));
+        self.do_render(html, page_url)
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.history.can_go_back()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.history.can_go_forward()
+    }
+
+    /// Starts loading the previous page in the history, to be shown at the page we were on
+    /// there. Returns false if there's no previous page.
+    pub fn go_back(&mut self) -> bool {
+        let Some(entry) = self.history.go_back().cloned() else {
+            return false;
+        };
+        info!("Going back to {}", entry.url);
+
+        self.load_history_entry(&entry);
+
+        true
+    }
+
+    /// Starts loading the next page in the history, to be shown at the page we were on
+    /// there. Returns false if there's no next page.
+    pub fn go_forward(&mut self) -> bool {
+        let Some(entry) = self.history.go_forward().cloned() else {
+            return false;
+        };
+        info!("Going forward to {}", entry.url);
+
+        self.load_history_entry(&entry);
+
+        true
+    }
+
+    /// Remembers which page we're viewing, so we can come back to it later
+    pub fn set_current_page_idx(&mut self, page_idx: usize) {
+        self.history.set_current_page_idx(page_idx);
+
+        let (BrowserState::ViewingPage { pages, .. }, Some(loaded_document)) =
+            (&self.state, &mut self.loaded_document)
+        else {
+            return;
+        };
+
+        // Only move our place when the page changes, so laying the page out again a few
+        // times doesn't creep backwards
+        if find_page_for_position(pages, &loaded_document.reading_position) != page_idx {
+            if let Some(page) = pages.get(page_idx) {
+                loaded_document.reading_position = page.start_position;
+            }
+        }
+    }
+
+    fn load_history_entry(&mut self, entry: &HistoryEntry) {
+        self.page_to_show = Some(PageToShow::Index(entry.page_idx));
+
+        // Entries for fragments of the page we're viewing don't need loading again
+        if self.is_viewing_document(&entry.url) {
+            self.set_document_url(&entry.url);
+            return;
+        }
+
+        match &entry.html {
+            Some(html) => self.do_render(html, &entry.url),
+            None => self.load_url(&entry.url),
+        }
+    }
+
+    /// Changes the font size and lays out the current page again, if there is one. Returns
+    /// the page that now shows what was at the top of the page we were reading.
+    pub fn change_font_size(&mut self, delta: i32) -> Option<usize> {
+        let font_size = self.settings.rendering.font_size as i32;
+        let new_font_size =
+            (font_size + delta).clamp(MIN_FONT_SIZE as i32, MAX_FONT_SIZE as i32) as u32;
+        if new_font_size == self.settings.rendering.font_size {
+            info!("Font size is already {}", new_font_size);
+            return None;
+        }
+
+        info!("Changing font size to {}", new_font_size);
+        self.settings.rendering.font_size = new_font_size;
+
+        if !matches!(self.state, BrowserState::ViewingPage { .. }) {
+            return None;
+        }
+        let position = self.loaded_document.as_ref()?.reading_position;
+
+        self.render_pages();
+
+        let new_page_idx = find_page_for_position(self.get_pages(), &position);
+        info!(
+            "Reading position {:?} is now on page {}",
+            position, new_page_idx
+        );
+
+        Some(new_page_idx)
+    }
+
+    fn load_url(&mut self, url: &str) {
         self.state = BrowserState::LoadingPage {
             url: url.to_string(),
         };
+        self.loaded_document = None;
+        self.image_loader = None;
 
         info!("Fetching webpage...");
-        let fetch_result = fetch_webpage(url);
+        let fetch_result = fetch_webpage(&self.http_client, url);
         if let Err(err) = fetch_result {
             error!("Failed to fetch webpage: {}", err);
             self.state = BrowserState::PageError {
@@ -72,18 +376,31 @@ impl BrowserCore {
             return;
         }
 
-        self.do_render(&page.content, url);
-    }
-
-    pub fn render(&mut self, html: &str, page_url: &str) {
-        info!("Rendering direct HTML from page url: {}", page_url);
+        // Relative links and images are relative to where the page ended up, so that's the
+        // URL we keep for it
+        let page_url = keep_fragment(&page.url, url);
+        if page_url != url {
+            info!("Redirected to {}", page_url);
+            self.history.set_current_url(&page_url);
+        }
 
-        self.do_render(html, page_url)
+        self.do_render(&page.content, &page_url);
     }
 
+    /// Parses the page and starts fetching its images. It's laid out once they're in.
     fn do_render(&mut self, html: &str, page_url: &str) {
+        self.state = BrowserState::LoadingPage {
+            url: page_url.to_string(),
+        };
+        self.loaded_document = None;
+        self.image_loader = None;
+
         info!("Parsing...");
-        let parse_result = parse_webpage(html);
+        let parse_result = if self.settings.rendering.reading_mode {
+            parse_article(html)
+        } else {
+            parse_webpage(html)
+        };
         if let Err(err) = parse_result {
             error!("Failed to parse webpage: {}", err);
             self.state = BrowserState::PageError {
@@ -95,22 +412,199 @@ impl BrowserCore {
         let document = parse_result.unwrap();
         // info!("Parsed document: {:#?}", document);
 
-        info!("Fetching images...");
-        let images = fetch_images(page_url, &document);
+        let base_url = get_base_url(page_url, document.metadata.base_href.as_deref());
+        let image_urls = get_image_urls(&base_url, &document);
+        if !image_urls.is_empty() {
+            self.image_loader = Some(ImageLoader::start(
+                &self.http_client,
+                image_urls,
+                &ImageLimits::default(),
+            ));
+        }
+
+        self.loaded_document = Some(LoadedDocument {
+            url: page_url.to_string(),
+            base_url,
+            document,
+            images: ImagesByUrl::new(),
+            prepared_images: PreparedImages::new(),
+            reading_position: SourcePosition::default(),
+            table_of_contents: vec![],
+            layout: DocumentLayout::new(),
+        });
+    }
+
+    /// Lays out the whole document again from the start
+    fn render_pages(&mut self) {
+        let Some(loaded_document) = &mut self.loaded_document else {
+            warn!("No document to render");
+            return;
+        };
 
         info!("Rendering pages...");
-        let mut renderer = Renderer::new(&self.settings.rendering, page_url, images);
-        let page_canvases = renderer.render_document(&document);
+        loaded_document.layout = DocumentLayout::new();
+        while !self.is_laid_out() {
+            self.render_next_pages();
+        }
+    }
 
-        self.state = BrowserState::ViewingPage {
-            url: page_url.to_string(),
-            page_canvases,
+    /// Lays out the next page of the document, or more if a block spans them
+    fn render_next_pages(&mut self) -> Range<usize> {
+        let Some(loaded_document) = &mut self.loaded_document else {
+            warn!("No document to render");
+            return 0..0;
+        };
+
+        if !matches!(self.state, BrowserState::ViewingPage { .. }) {
+            self.state = BrowserState::ViewingPage {
+                url: loaded_document.url.clone(),
+                pages: vec![],
+                metadata: loaded_document.document.metadata.clone(),
+            };
+        }
+        let BrowserState::ViewingPage { pages, .. } = &mut self.state else {
+            unreachable!();
+        };
+
+        let mut renderer = Renderer::new(
+            &self.settings.rendering,
+            &loaded_document.base_url,
+            &loaded_document.images,
+            &mut loaded_document.prepared_images,
+            &mut self.font_system,
+            &mut self.swash_cache,
+        );
+        let page_idxs = renderer.render_next_pages(
+            &loaded_document.document,
+            &mut loaded_document.layout,
+            pages,
+        );
+
+        // Built from the pages so far, so it's there to use while the rest are laid out
+        loaded_document.table_of_contents = build_table_of_contents(
+            &loaded_document.document,
+            pages,
+            loaded_document.layout.laid_out_until(),
+        );
+
+        page_idxs
+    }
+
+    /// Whether every page of the document is laid out, or there's no document to lay out
+    pub fn is_laid_out(&self) -> bool {
+        self.loaded_document
+            .as_ref()
+            .is_none_or(|loaded_document| loaded_document.layout.is_complete())
+    }
+
+    /// The page to open the document on, once it's laid out that far
+    fn take_page_to_show(&mut self) -> Option<usize> {
+        let page_idx = match (self.page_to_show.as_ref()?, &self.state) {
+            (PageToShow::Index(page_idx), BrowserState::ViewingPage { pages, .. })
+                if *page_idx < pages.len() =>
+            {
+                *page_idx
+            }
+            (PageToShow::Fragment(fragment), BrowserState::ViewingPage { .. }) => {
+                match self.find_fragment_page(fragment) {
+                    Some(page_idx) => page_idx,
+                    None if self.is_laid_out() => 0,
+                    None => return None,
+                }
+            }
+            _ if self.is_laid_out() => 0,
+            _ => return None,
         };
+
+        self.page_to_show = None;
+        Some(page_idx)
+    }
+
+    /// Keeps an image that came in, and lays out the pages it shows up on again
+    fn add_image(&mut self, image_url: String, image: Option<RgbaImage>) {
+        let Some(loaded_document) = &mut self.loaded_document else {
+            return;
+        };
+
+        // Images that failed to load take up the same space as ones that aren't in yet
+        if image.is_some() {
+            let block_idx = loaded_document.document.blocks.iter().position(|block| {
+                let mut image_urls = vec![];
+                collect_block_image_urls(
+                    &loaded_document.base_url,
+                    std::slice::from_ref(block),
+                    &mut image_urls,
+                );
+                image_urls.contains(&image_url)
+            });
+
+            if let Some(block_idx) = block_idx {
+                loaded_document.layout.rewind_to_block(block_idx);
+            }
+        }
+
+        loaded_document.images.insert(image_url, image);
+    }
+
+    /// Whether the URL points at the page we're viewing, perhaps at a different fragment
+    fn is_viewing_document(&self, url: &str) -> bool {
+        let (BrowserState::ViewingPage { .. }, Some(loaded_document)) =
+            (&self.state, &self.loaded_document)
+        else {
+            return false;
+        };
+
+        split_fragment(&loaded_document.url).0 == split_fragment(url).0
+    }
+
+    /// Changes which fragment of the current page we're at, which only changes the URL
+    fn set_document_url(&mut self, url: &str) {
+        if let Some(loaded_document) = &mut self.loaded_document {
+            loaded_document.url = url.to_string();
+        }
+        if let BrowserState::ViewingPage {
+            url: viewing_url, ..
+        } = &mut self.state
+        {
+            *viewing_url = url.to_string();
+        }
+    }
+
+    /// Finds the page with the element the fragment points at. An empty fragment and `#top`
+    /// mean the top of the page, unless something has that id.
+    fn find_fragment_page(&self, fragment: &str) -> Option<usize> {
+        let BrowserState::ViewingPage { pages, .. } = &self.state else {
+            return None;
+        };
+
+        pages
+            .iter()
+            .position(|page| page.anchors.iter().any(|id| id == fragment))
+            .or_else(|| (fragment.is_empty() || fragment.eq_ignore_ascii_case("top")).then_some(0))
+    }
+
+    pub fn get_current_url(&self) -> Option<&str> {
+        match &self.state {
+            BrowserState::Initial => None,
+            BrowserState::LoadingPage { url }
+            | BrowserState::ViewingPage { url, .. }
+            | BrowserState::PageError { url, .. } => Some(url),
+        }
     }
 
-    pub fn get_pages(&self) -> &Vec<image::RgbaImage> {
-        if let BrowserState::ViewingPage { page_canvases, .. } = &self.state {
-            return page_canvases;
+    /// The headings of the page we're viewing, if any
+    pub fn get_table_of_contents(&self) -> &[TableOfContentsEntry] {
+        match (&self.state, &self.loaded_document) {
+            (BrowserState::ViewingPage { .. }, Some(loaded_document)) => {
+                &loaded_document.table_of_contents
+            }
+            _ => &[],
+        }
+    }
+
+    pub fn get_pages(&self) -> &Vec<RenderedPage> {
+        if let BrowserState::ViewingPage { pages, .. } = &self.state {
+            pages
         } else {
             // TODO: gracefully handle this
             panic!("Browser is not in viewing state");
@@ -118,46 +612,264 @@ impl BrowserCore {
     }
 }
 
-fn fetch_images(webpage_url: &str, document: &Document) -> ImagesByUrl {
-    let mut images = HashMap::new();
+/// Every image in the document, including ones inside lists, quotes, tables and text, each
+/// listed once
+fn get_image_urls(webpage_url: &str, document: &Document) -> Vec<String> {
+    let mut image_urls = vec![];
+
+    collect_block_image_urls(webpage_url, &document.blocks, &mut image_urls);
 
-    let image_urls = get_image_urls(webpage_url, document);
-    for image_url in image_urls {
-        let image_response = fetch_image(&image_url);
-        if let Err(err) = image_response {
-            warn!("Failed to fetch image: {}", err);
-            images.insert(image_url, None);
-            continue;
+    image_urls
+}
+
+fn collect_block_image_urls(webpage_url: &str, blocks: &[Block], image_urls: &mut Vec<String>) {
+    for block in blocks.iter() {
+        match block {
+            Block::Image { url, .. } => push_image_url(webpage_url, url, image_urls),
+            Block::Heading { content, .. } | Block::Paragraph { content } => {
+                collect_span_image_urls(webpage_url, content, image_urls);
+            }
+            Block::List { items } => {
+                for item in items.iter() {
+                    collect_block_image_urls(webpage_url, &item.content, image_urls);
+                }
+            }
+            Block::BlockQuote { content } => {
+                collect_block_image_urls(webpage_url, content, image_urls);
+            }
+            Block::Table { rows } => {
+                for cell in rows.iter().flat_map(|row| row.cells.iter()) {
+                    collect_span_image_urls(webpage_url, &cell.content, image_urls);
+                }
+            }
+            Block::ThematicBreak | Block::CodeBlock { .. } | Block::Anchor { .. } => {}
         }
-        let image_response = image_response.unwrap();
+    }
+}
 
-        let image = load_image(image_response);
-        images.insert(image_url, image);
+fn collect_span_image_urls(webpage_url: &str, content: &[Span], image_urls: &mut Vec<String>) {
+    for span in content.iter() {
+        if let Span::Image { url, .. } = span {
+            push_image_url(webpage_url, url, image_urls);
+        }
     }
+}
 
-    images
+/// Images whose URL doesn't make sense, like the `//:0` some lazy-loading scripts leave in
+/// `src`, are left out
+fn push_image_url(webpage_url: &str, url: &str, image_urls: &mut Vec<String>) {
+    let Some(resolved_url) = try_resolve_url(webpage_url, url) else {
+        warn!("Skipping image with invalid URL: {}", url);
+        return;
+    };
+    if !image_urls.contains(&resolved_url) {
+        image_urls.push(resolved_url);
+    }
 }
 
-fn load_image(image_response: ImageResponse) -> Option<RgbaImage> {
-    let image = image::load_from_memory(&image_response.data);
-    if let Err(err) = image {
-        warn!("Failed to load image: {}", err);
-        return None;
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    const PAGE_URL: &str = "https://example.com/guide.html";
+
+    #[test]
+    fn test_get_image_urls() {
+        let document = parse_webpage(
+            r#"<html><body>
+            <p>A <img src="icon.png"> icon</p>
+            <img src="/photo.jpg">
+            <ul><li><img src="icon.png"></li></ul>
+            <blockquote><p><img src="https://cdn.example.com/quote.png"></p></blockquote>
+            <table><tr><td>Cell <img src="cell.gif"></td></tr></table>
+            </body></html>"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_image_urls(PAGE_URL, &document),
+            vec![
+                "https://example.com/icon.png",
+                "https://example.com/photo.jpg",
+                "https://cdn.example.com/quote.png",
+                "https://example.com/cell.gif",
+            ]
+        );
     }
-    let image = image.unwrap().to_rgba8();
 
-    Some(image)
-}
+    #[test]
+    fn test_invalid_image_urls() {
+        let html = r#"<html><body>
+            <p>A <img src="http://"> icon</p>
+            <img src="//:0">
+            <img src="https://exa mple.com/photo.jpg">
+            </body></html>"#;
+        assert!(get_image_urls(PAGE_URL, &parse_webpage(html).unwrap()).is_empty());
 
-fn get_image_urls(webpage_url: &str, document: &Document) -> Vec<String> {
-    let mut image_urls = vec![];
+        let mut browser = BrowserCore::new(Settings::default());
+        browser.render(html, PAGE_URL);
+        assert_eq!(browser.finish_loading(), 0);
+        assert_eq!(browser.get_pages().len(), 1);
+    }
+
+    #[test]
+    fn test_load_page_with_images() {
+        let mut browser = BrowserCore::new(Settings::default());
+        browser.render(
+            r#"<html><body>
+            <p>Text first</p>
+            <img src="data:image/svg+xml;base64,PHN2ZyB4bWxucz0iaHR0cDovL3d3dy53My5vcmcvMjAwMC9zdmciIHdpZHRoPSIxMCIgaGVpZ2h0PSIxMCIvPg==">
+            <svg width="30" height="20"><circle cx="10" cy="10" r="10"/></svg>
+            </body></html>"#,
+            PAGE_URL,
+        );
+
+        assert!(matches!(browser.state, BrowserState::LoadingPage { .. }));
+        assert_eq!(browser.finish_loading(), 0);
+        assert!(matches!(browser.state, BrowserState::ViewingPage { .. }));
+        assert!(!browser.is_loading());
 
-    for block in document.blocks.iter() {
-        if let Block::Image { url, .. } = block {
-            let resolved_url = resolve_url(webpage_url, url);
-            image_urls.push(resolved_url.to_string());
+        let images = &browser.loaded_document.as_ref().unwrap().images;
+        let mut sizes: Vec<_> = images
+            .values()
+            .map(|image| image.as_ref().map(|image| image.dimensions()))
+            .collect();
+        sizes.sort();
+        assert_eq!(sizes, vec![Some((20, 20)), Some((60, 40))]);
+    }
+
+    #[test]
+    fn test_show_text_before_images() {
+        // Accepts connections, but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let html = format!(
+            r#"<html><body><p>Text first</p><img src="http://{}/slow.png"></body></html>"#,
+            listener.local_addr().unwrap()
+        );
+
+        let mut browser = BrowserCore::new(Settings::default());
+        browser.render(&html, PAGE_URL);
+
+        assert_eq!(
+            browser.continue_loading(),
+            LoadingProgress::Ready { page_idx: 0 }
+        );
+        assert_eq!(browser.get_pages().len(), 1);
+        assert!(browser.is_loading());
+    }
+
+    #[test]
+    fn test_follow_redirect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for response in [
+                "HTTP/1.1 301 Moved Permanently\r\nLocation: /guide/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 53\r\nConnection: close\r\n\r\n<p><a href=\"setup.html\">Setup</a> <img src=x.png></p>",
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let bytes_read = stream.read(&mut buffer).unwrap();
+                    request.extend_from_slice(&buffer[..bytes_read]);
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let mut browser = BrowserCore::new(Settings::default());
+        browser.navigate_to(&format!("{}/guide#setup", server_url));
+        server.join().unwrap();
+
+        let final_url = format!("{}/guide/#setup", server_url);
+        assert_eq!(browser.get_current_url(), Some(final_url.as_str()));
+        assert_eq!(browser.history.current().unwrap().url, final_url);
+
+        browser.finish_loading();
+        let loaded_document = browser.loaded_document.as_ref().unwrap();
+        assert!(loaded_document
+            .images
+            .contains_key(&format!("{}/guide/x.png", server_url)));
+        assert_eq!(
+            browser.get_pages()[0].links[0].destination,
+            format!("{}/guide/setup.html", server_url)
+        );
+    }
+
+    #[test]
+    fn test_base_href() {
+        let mut browser = BrowserCore::new(Settings::default());
+        browser.render(
+            r#"<html><head><base href="/docs/v2/"></head><body>
+            <p><a href="install.html">Install</a></p>
+            </body></html>"#,
+            PAGE_URL,
+        );
+        browser.finish_loading();
+
+        assert_eq!(browser.get_current_url(), Some(PAGE_URL));
+        assert_eq!(
+            browser.get_pages()[0].links[0].destination,
+            "https://example.com/docs/v2/install.html"
+        );
+    }
+
+    fn render_guide() -> BrowserCore {
+        let mut html = String::from("<html><body><h1>Guide</h1>");
+        for _ in 0..30 {
+            html.push_str("<p>Some filler text, long enough to fill a few pages on its own.</p>");
         }
+        html.push_str(r#"<h2 id="café">Café</h2><p>Coffee.</p></body></html>"#);
+
+        let mut browser = BrowserCore::new(Settings::default());
+        browser.render(&html, PAGE_URL);
+        browser.finish_loading();
+        browser
     }
 
-    image_urls
+    #[test]
+    fn test_jump_to_fragment() {
+        let mut browser = render_guide();
+        let last_page_idx = browser.get_pages().len() - 1;
+        assert!(last_page_idx > 0);
+
+        let url = format!("{}#caf%C3%A9", PAGE_URL);
+        assert_eq!(browser.jump_to_fragment(&url), Some(last_page_idx));
+        assert_eq!(browser.get_current_url(), Some(url.as_str()));
+        assert!(browser.can_go_back());
+
+        assert_eq!(
+            browser.jump_to_fragment(&format!("{}#top", PAGE_URL)),
+            Some(0)
+        );
+        assert_eq!(
+            browser.jump_to_fragment(&format!("{}#missing", PAGE_URL)),
+            Some(0)
+        );
+
+        // Going back to a fragment of the same page doesn't load it again
+        assert!(browser.go_back());
+        assert_eq!(browser.finish_loading(), 0);
+        assert_eq!(
+            browser.get_current_url(),
+            Some(format!("{}#top", PAGE_URL).as_str())
+        );
+    }
+
+    #[test]
+    fn test_jump_to_fragment_of_another_page() {
+        let mut browser = render_guide();
+
+        assert_eq!(browser.jump_to_fragment(PAGE_URL), None);
+        assert_eq!(
+            browser.jump_to_fragment("https://example.com/other.html#intro"),
+            None
+        );
+        assert_eq!(browser.get_current_url(), Some(PAGE_URL));
+    }
 }
@@ -0,0 +1,143 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub url: String,
+    // Pages rendered from HTML sent to us (instead of fetched) can't be fetched again
+    pub html: Option<String>,
+    pub page_idx: usize,
+}
+
+impl HistoryEntry {
+    pub fn new(url: &str, html: Option<&str>) -> Self {
+        Self {
+            url: url.to_string(),
+            html: html.map(|html| html.to_string()),
+            page_idx: 0,
+        }
+    }
+}
+
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    current_idx: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![],
+            current_idx: None,
+        }
+    }
+
+    /// Adds a new entry after the current one, discarding any entries we could go forward to
+    pub fn push(&mut self, entry: HistoryEntry) {
+        let next_idx = self.current_idx.map_or(0, |idx| idx + 1);
+
+        self.entries.truncate(next_idx);
+        self.entries.push(entry);
+        self.current_idx = Some(next_idx);
+    }
+
+    pub fn current(&self) -> Option<&HistoryEntry> {
+        self.current_idx.and_then(|idx| self.entries.get(idx))
+    }
+
+    /// Changes the current entry's URL, like when the page it points at redirected elsewhere
+    pub fn set_current_url(&mut self, url: &str) {
+        if let Some(idx) = self.current_idx {
+            self.entries[idx].url = url.to_string();
+        }
+    }
+
+    pub fn set_current_page_idx(&mut self, page_idx: usize) {
+        if let Some(idx) = self.current_idx {
+            self.entries[idx].page_idx = page_idx;
+        }
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        matches!(self.current_idx, Some(idx) if idx > 0)
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        matches!(self.current_idx, Some(idx) if idx + 1 < self.entries.len())
+    }
+
+    pub fn go_back(&mut self) -> Option<&HistoryEntry> {
+        if !self.can_go_back() {
+            return None;
+        }
+
+        self.current_idx = self.current_idx.map(|idx| idx - 1);
+        self.current()
+    }
+
+    pub fn go_forward(&mut self) -> Option<&HistoryEntry> {
+        if !self.can_go_forward() {
+            return None;
+        }
+
+        self.current_idx = self.current_idx.map(|idx| idx + 1);
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn empty_history() {
+        let mut history = History::new();
+
+        assert!(!history.can_go_back());
+        assert!(!history.can_go_forward());
+        assert_eq!(history.go_back(), None);
+        assert_eq!(history.go_forward(), None);
+        assert_eq!(history.current(), None);
+    }
+
+    #[test]
+    fn back_and_forward() {
+        let mut history = History::new();
+        history.push(HistoryEntry::new("https://example.com/1", None));
+        history.set_current_page_idx(3);
+        history.push(HistoryEntry::new("https://example.com/2", None));
+
+        assert!(history.can_go_back());
+        assert!(!history.can_go_forward());
+
+        let entry = history.go_back().unwrap();
+        assert_eq!(entry.url, "https://example.com/1");
+        assert_eq!(entry.page_idx, 3);
+        assert!(!history.can_go_back());
+        assert!(history.can_go_forward());
+
+        let entry = history.go_forward().unwrap();
+        assert_eq!(entry.url, "https://example.com/2");
+        assert_eq!(entry.page_idx, 0);
+    }
+
+    #[test]
+    fn push_discards_forward_entries() {
+        let mut history = History::new();
+        history.push(HistoryEntry::new("https://example.com/1", None));
+        history.push(HistoryEntry::new("https://example.com/2", None));
+        history.go_back();
+        history.push(HistoryEntry::new(
+            "https://example.com/3",
+            Some("<p>Hi</p>"),
+        ));
+
+        assert!(!history.can_go_forward());
+        assert_eq!(
+            history.current(),
+            Some(&HistoryEntry {
+                url: "https://example.com/3".to_string(),
+                html: Some("<p>Hi</p>".to_string()),
+                page_idx: 0,
+            })
+        );
+        assert_eq!(history.go_back().unwrap().url, "https://example.com/1");
+    }
+}
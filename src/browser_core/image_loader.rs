@@ -0,0 +1,257 @@
+use image::io::Reader as ImageReader;
+use image::RgbaImage;
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::browser_core::network::{fetch_image, FetchError, ImageResponse};
+use crate::browser_core::svg::{is_svg_content_type, rasterize_svg, SvgError};
+
+/// How much we'll do for a page's images, so a slow or huge one can't hold up its text
+#[derive(Debug, Clone)]
+pub struct ImageLimits {
+    pub max_concurrent_fetches: usize,
+    // For each image, from connecting until the last byte is in
+    pub image_timeout: Duration,
+    // For all of the page's images together. Any still on their way are left out.
+    pub total_timeout: Duration,
+    pub max_bytes: u64,
+    // Width times height, once decoded
+    pub max_pixels: u64,
+}
+
+impl Default for ImageLimits {
+    fn default() -> Self {
+        ImageLimits {
+            max_concurrent_fetches: 4,
+            image_timeout: Duration::from_secs(15),
+            total_timeout: Duration::from_secs(30),
+            max_bytes: 10 * 1024 * 1024,
+            max_pixels: 24_000_000,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+enum ImageLoadError {
+    #[error("Failed to fetch image: {0}")]
+    Fetch(#[from] FetchError),
+    #[error("Failed to decode image: {0}")]
+    Decode(#[from] image::ImageError),
+    #[error("Failed to read image: {0}")]
+    Read(#[from] std::io::Error),
+    #[error(transparent)]
+    Svg(#[from] SvgError),
+    #[error("Image is {0}x{1}, which is too many pixels")]
+    TooManyPixels(u32, u32),
+}
+
+/// What came of waiting for the next image
+pub enum NextImage {
+    /// An image came in, whether it loaded or not
+    Loaded(String, Option<RgbaImage>),
+    /// None came in while we waited
+    Pending,
+    /// They're all in, or we've waited for them as long as we will
+    Done,
+}
+
+/// Fetches a page's images in the background, a few at a time, and hands them over as they
+/// come in
+pub struct ImageLoader {
+    // The URLs no worker has picked up yet
+    queue: Arc<Mutex<VecDeque<String>>>,
+    results_rx: Receiver<(String, Option<RgbaImage>)>,
+    total: usize,
+    received: usize,
+    deadline: Instant,
+}
+
+impl ImageLoader {
+    pub fn start(
+        client: &reqwest::blocking::Client,
+        image_urls: Vec<String>,
+        limits: &ImageLimits,
+    ) -> Self {
+        info!("Fetching {} images...", image_urls.len());
+
+        let total = image_urls.len();
+        let queue = Arc::new(Mutex::new(VecDeque::from(image_urls)));
+        let (results_tx, results_rx) = mpsc::channel();
+
+        for _ in 0..limits.max_concurrent_fetches.min(total) {
+            let queue = queue.clone();
+            let results_tx = results_tx.clone();
+            let client = client.clone();
+            let limits = limits.clone();
+
+            thread::spawn(move || loop {
+                let Some(image_url) = queue.lock().unwrap().pop_front() else {
+                    return;
+                };
+
+                let image = load_image(&client, &image_url, &limits)
+                    .inspect_err(|err| warn!("Failed to load image {}: {}", image_url, err))
+                    .ok();
+
+                // Nobody is waiting for it anymore, like after going to another page
+                if results_tx.send((image_url, image)).is_err() {
+                    return;
+                }
+            });
+        }
+
+        ImageLoader {
+            queue,
+            results_rx,
+            total,
+            received: 0,
+            deadline: Instant::now() + limits.total_timeout,
+        }
+    }
+
+    /// Waits up to the given time for the next image to come in
+    pub fn next_image(&mut self, wait: Duration) -> NextImage {
+        if self.received == self.total {
+            return NextImage::Done;
+        }
+
+        let timeout = wait.min(self.deadline.saturating_duration_since(Instant::now()));
+        match self.results_rx.recv_timeout(timeout) {
+            Ok((image_url, image)) => {
+                self.received += 1;
+                NextImage::Loaded(image_url, image)
+            }
+            Err(RecvTimeoutError::Timeout) if Instant::now() < self.deadline => NextImage::Pending,
+            Err(_) => {
+                warn!(
+                    "Gave up waiting for {} of {} images",
+                    self.total - self.received,
+                    self.total
+                );
+                NextImage::Done
+            }
+        }
+    }
+
+    /// How many images have come in so far, and how many there are
+    pub fn progress(&self) -> (usize, usize) {
+        (self.received, self.total)
+    }
+}
+
+impl Drop for ImageLoader {
+    fn drop(&mut self) {
+        // Images still waiting for a worker aren't needed anymore
+        self.queue.lock().unwrap().clear();
+    }
+}
+
+fn load_image(
+    client: &reqwest::blocking::Client,
+    image_url: &str,
+    limits: &ImageLimits,
+) -> Result<RgbaImage, ImageLoadError> {
+    let image_response = fetch_image(client, image_url, limits.max_bytes, limits.image_timeout)?;
+
+    decode_image(image_response, limits.max_pixels)
+}
+
+fn decode_image(
+    image_response: ImageResponse,
+    max_pixels: u64,
+) -> Result<RgbaImage, ImageLoadError> {
+    if is_svg_content_type(&image_response.content_type) {
+        return Ok(rasterize_svg(&image_response.data, max_pixels)?);
+    }
+
+    // Checked before decoding, so a huge image never takes up the memory
+    let reader = ImageReader::new(Cursor::new(&image_response.data)).with_guessed_format()?;
+    let (width, height) = reader.into_dimensions()?;
+    if width as u64 * height as u64 > max_pixels {
+        return Err(ImageLoadError::TooManyPixels(width, height));
+    }
+
+    let reader = ImageReader::new(Cursor::new(&image_response.data)).with_guessed_format()?;
+
+    Ok(reader.decode()?.to_rgba8())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    const SQUARE_SVG_URI: &str = "data:image/svg+xml,%3Csvg%20xmlns%3D%22http%3A%2F%2Fwww.w3.org%2F2000%2Fsvg%22%20width%3D%2210%22%20height%3D%2210%22%2F%3E";
+    const HUGE_SVG_URI: &str = "data:image/svg+xml,%3Csvg%20xmlns%3D%22http%3A%2F%2Fwww.w3.org%2F2000%2Fsvg%22%20width%3D%22100%22%20height%3D%22100000%22%2F%3E";
+
+    fn load_all(image_loader: &mut ImageLoader) -> Vec<(String, Option<(u32, u32)>)> {
+        let mut results = vec![];
+        loop {
+            match image_loader.next_image(Duration::from_millis(50)) {
+                NextImage::Loaded(image_url, image) => {
+                    results.push((image_url, image.map(|image| image.dimensions())));
+                }
+                NextImage::Pending => {}
+                NextImage::Done => break,
+            }
+        }
+        results.sort();
+        results
+    }
+
+    #[test]
+    fn test_load_images() {
+        let image_urls = vec![
+            SQUARE_SVG_URI.to_string(),
+            HUGE_SVG_URI.to_string(),
+            "data:image/png;base64,bm90IGEgcG5n".to_string(),
+        ];
+
+        let mut image_loader = ImageLoader::start(
+            &reqwest::blocking::Client::new(),
+            image_urls,
+            &ImageLimits::default(),
+        );
+
+        assert_eq!(image_loader.progress(), (0, 3));
+        assert_eq!(
+            load_all(&mut image_loader),
+            vec![
+                ("data:image/png;base64,bm90IGEgcG5n".to_string(), None),
+                (SQUARE_SVG_URI.to_string(), Some((20, 20))),
+                (HUGE_SVG_URI.to_string(), None),
+            ]
+        );
+        assert_eq!(image_loader.progress(), (3, 3));
+    }
+
+    #[test]
+    fn test_total_timeout() {
+        // Accepts connections, but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let slow_url = format!("http://{}/slow.png", listener.local_addr().unwrap());
+
+        let limits = ImageLimits {
+            max_concurrent_fetches: 1,
+            total_timeout: Duration::from_millis(300),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let mut image_loader = ImageLoader::start(
+            &reqwest::blocking::Client::new(),
+            vec![slow_url, SQUARE_SVG_URI.to_string()],
+            &limits,
+        );
+
+        assert_eq!(load_all(&mut image_loader), vec![]);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(image_loader.progress(), (0, 2));
+    }
+}
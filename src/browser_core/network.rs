@@ -1,10 +1,20 @@
-use log::info;
+use data_url::DataUrl;
+use log::{info, warn};
+use percent_encoding::percent_decode_str;
+use reqwest::blocking::Client;
+use reqwest::redirect;
+use std::io::Read;
 use std::result::Result;
+use std::time::Duration;
 use thiserror::Error;
 use url::Url;
 
+use crate::settings::{ContentEncoding, NetworkSettings};
+
 #[derive(Debug)]
 pub struct Webpage {
+    // Where the page ended up, after following any redirects
+    pub url: String,
     pub content: String,
     pub content_type: ContentType,
 }
@@ -17,7 +27,7 @@ pub struct ImageResponse {
 
 #[derive(Debug)]
 pub enum ContentType {
-    HTML,
+    Html,
     Other(String),
 }
 
@@ -31,12 +41,42 @@ pub enum FetchError {
     MissingContentType,
     #[error("Incorrect or unsupported image Content-Type: {0}")]
     IncorrectContentType(String),
+    #[error("Invalid data: URI: {0}")]
+    InvalidDataUri(String),
+    #[error("Response is bigger than {0} bytes")]
+    TooLarge(u64),
     #[error("Unknown error: {0}")]
     UnknownError(String),
 }
 
-pub fn fetch_webpage(url: &str) -> Result<Webpage, FetchError> {
-    let client = reqwest::blocking::Client::new();
+/// Builds the client for every request the browser makes, so a page and its images can
+/// share connections to the same host
+pub fn build_http_client(settings: &NetworkSettings) -> Client {
+    let mut builder = Client::builder()
+        .user_agent(&settings.user_agent)
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+        .timeout(Duration::from_secs(settings.request_timeout_secs))
+        .redirect(redirect::Policy::limited(settings.max_redirects))
+        .gzip(settings.accept_encodings.contains(&ContentEncoding::Gzip))
+        .brotli(settings.accept_encodings.contains(&ContentEncoding::Brotli));
+
+    if let Some(proxy_url) = &settings.proxy {
+        match reqwest::Proxy::all(proxy_url) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(err) => warn!("Ignoring invalid proxy {}: {}", proxy_url, err),
+        }
+    }
+
+    builder.build().unwrap_or_else(|err| {
+        warn!(
+            "Failed to build the HTTP client, using the defaults: {}",
+            err
+        );
+        Client::new()
+    })
+}
+
+pub fn fetch_webpage(client: &Client, url: &str) -> Result<Webpage, FetchError> {
     let request = client.get(url).header("Accept", "text/html");
     let send_result = request.send();
 
@@ -56,28 +96,47 @@ pub fn fetch_webpage(url: &str) -> Result<Webpage, FetchError> {
         Some(header_value) => {
             let header_value_str = header_value.to_str().unwrap();
             if header_value_str.starts_with("text/html") {
-                ContentType::HTML
+                ContentType::Html
             } else {
                 ContentType::Other(header_value_str.to_string())
             }
         }
     };
 
-    let body = response.text().unwrap();
+    let final_url = response.url().to_string();
+    // The body can still fail to arrive, like when the connection drops or it's corrupt
+    let body = response
+        .text()
+        .map_err(|err| FetchError::UnknownError(err.to_string()))?;
 
     let webpage = Webpage {
-        content: body.to_string(),
+        url: final_url,
+        content: body,
         content_type,
     };
 
-    return Ok(webpage);
+    Ok(webpage)
 }
 
-pub fn fetch_image(image_url: &str) -> Result<ImageResponse, FetchError> {
+/// Fetches an image, or decodes it if it's written into the page as a `data:` URI. Images
+/// bigger than `max_bytes`, or slower than the timeout, are turned away.
+pub fn fetch_image(
+    client: &Client,
+    image_url: &str,
+    max_bytes: u64,
+    timeout: Duration,
+) -> Result<ImageResponse, FetchError> {
+    if image_url.starts_with("data:") {
+        let image_response = decode_data_uri(image_url)?;
+        if image_response.data.len() as u64 > max_bytes {
+            return Err(FetchError::TooLarge(max_bytes));
+        }
+        return Ok(image_response);
+    }
+
     info!("Fetching image: {}", image_url);
 
-    let client = reqwest::blocking::Client::new();
-    let request = client.get(image_url);
+    let request = client.get(image_url).timeout(timeout);
     let send_result = request.send();
 
     if let Err(err) = send_result {
@@ -91,24 +150,62 @@ pub fn fetch_image(image_url: &str) -> Result<ImageResponse, FetchError> {
         return Err(FetchError::NonSuccessStatusCode(status_code.as_u16()));
     }
 
+    if response
+        .content_length()
+        .is_some_and(|content_length| content_length > max_bytes)
+    {
+        return Err(FetchError::TooLarge(max_bytes));
+    }
+
     let content_type = response.headers().get("Content-Type");
     if content_type.is_none() {
         return Err(FetchError::MissingContentType);
     }
-    let content_type = content_type.unwrap().to_str().unwrap().to_string();
+    let content_type = content_type.unwrap().to_str().unwrap();
+    let content_type = media_type(content_type);
 
     if !is_supported_image_content_type(&content_type) {
         return Err(FetchError::IncorrectContentType(content_type));
     }
 
-    let body = response.bytes();
-    if let Err(err) = body {
+    // Servers don't always say how big a response is, so stop reading past the limit
+    let mut data = vec![];
+    if let Err(err) = response.take(max_bytes + 1).read_to_end(&mut data) {
         return Err(FetchError::UnknownError(err.to_string()));
     }
-    let body = body.unwrap();
-    let data = body.to_vec();
+    if data.len() as u64 > max_bytes {
+        return Err(FetchError::TooLarge(max_bytes));
+    }
+
+    Ok(ImageResponse { data, content_type })
+}
+
+/// Decodes the image in a `data:` URI, whether it's base64 or percent-encoded
+fn decode_data_uri(data_uri: &str) -> Result<ImageResponse, FetchError> {
+    let data_url = DataUrl::process(data_uri)
+        .map_err(|err| FetchError::InvalidDataUri(format!("{:?}", err)))?;
+
+    let mime_type = data_url.mime_type();
+    let content_type = format!("{}/{}", mime_type.type_, mime_type.subtype);
+    if !is_supported_image_content_type(&content_type) {
+        return Err(FetchError::IncorrectContentType(content_type));
+    }
+
+    let (data, _) = data_url
+        .decode_to_vec()
+        .map_err(|err| FetchError::InvalidDataUri(format!("{:?}", err)))?;
 
-    return Ok(ImageResponse { data, content_type });
+    Ok(ImageResponse { data, content_type })
+}
+
+/// The media type of a Content-Type header, without parameters like the charset
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
 }
 
 pub fn is_supported_image_content_type(content_type: &str) -> bool {
@@ -116,30 +213,73 @@ pub fn is_supported_image_content_type(content_type: &str) -> bool {
         return false;
     }
 
-    match content_type {
-        "image/jpeg" => true,
-        "image/png" => true,
-        "image/gif" => true,
-        "image/webp" => true,
-        _ => false,
+    matches!(
+        content_type,
+        "image/jpeg" | "image/png" | "image/gif" | "image/webp" | "image/svg+xml"
+    )
+}
+
+pub fn try_resolve_url(webpage_url: &str, href: &str) -> Option<String> {
+    let url = Url::parse(webpage_url).ok()?;
+    let resolved_url = url.join(href).ok()?;
+
+    Some(resolved_url.to_string())
+}
+
+/// The URL that a page's relative links and images are resolved against: its `<base href>`
+/// if it has a usable one, or else the page's own URL
+pub fn get_base_url(webpage_url: &str, base_href: Option<&str>) -> String {
+    base_href
+        .and_then(|base_href| try_resolve_url(webpage_url, base_href))
+        .filter(|base_url| base_url.starts_with("http://") || base_url.starts_with("https://"))
+        .unwrap_or_else(|| webpage_url.to_string())
+}
+
+/// Keeps the fragment of the URL we asked for when a redirect didn't give one, the way
+/// browsers do, so `#section` still points somewhere after the page moved
+pub fn keep_fragment(final_url: &str, requested_url: &str) -> String {
+    match (split_fragment(final_url).1, requested_url.split_once('#')) {
+        (None, Some((_, fragment))) => format!("{}#{}", final_url, fragment),
+        _ => final_url.to_string(),
     }
 }
 
-pub fn resolve_url(webpage_url: &str, href: &str) -> String {
-    let url = Url::parse(webpage_url).unwrap();
-    let base_url = url.join(href).unwrap();
-    return base_url.to_string();
+/// Splits a URL into the document it points at and the fragment after `#`, decoded so it
+/// can be compared with element ids
+pub fn split_fragment(url: &str) -> (&str, Option<String>) {
+    match url.split_once('#') {
+        Some((document_url, fragment)) => {
+            let fragment = percent_decode_str(fragment).decode_utf8_lossy();
+            (document_url, Some(fragment.to_string()))
+        }
+        None => (url, None),
+    }
+}
+
+/// Turns what someone typed into the URL bar into a URL we can fetch
+pub fn normalize_typed_url(input: &str) -> String {
+    let input = input.trim();
+
+    if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("https://{}", input)
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
     use super::*;
 
     #[test]
     fn resolve_absolute_path() {
         let webpage_url = "https://example.com";
         let href = "/path/to/image.jpg";
-        let resolved = resolve_url(webpage_url, href);
+        let resolved = try_resolve_url(webpage_url, href).unwrap();
         assert_eq!(resolved, "https://example.com/path/to/image.jpg");
     }
 
@@ -147,7 +287,7 @@ mod test {
     fn resolve_relative_path() {
         let webpage_url = "https://example.com";
         let href = "image.jpg";
-        let resolved = resolve_url(webpage_url, href);
+        let resolved = try_resolve_url(webpage_url, href).unwrap();
         assert_eq!(resolved, "https://example.com/image.jpg");
     }
 
@@ -155,7 +295,150 @@ mod test {
     fn resolve_full_url() {
         let webpage_url = "https://example.com";
         let href = "https://http.cat/images/200.jpg";
-        let resolved = resolve_url(webpage_url, href);
+        let resolved = try_resolve_url(webpage_url, href).unwrap();
         assert_eq!(resolved, "https://http.cat/images/200.jpg");
     }
+
+    #[test]
+    fn resolve_invalid_url() {
+        let webpage_url = "https://example.com";
+        assert_eq!(try_resolve_url(webpage_url, "//:0"), None);
+        assert_eq!(try_resolve_url(webpage_url, "http://"), None);
+        assert_eq!(
+            try_resolve_url(webpage_url, "https://exa mple.com/a.png"),
+            None
+        );
+    }
+
+    #[test]
+    fn decode_data_uris() {
+        let client = Client::new();
+        let fetch_image = |image_url| fetch_image(&client, image_url, 1024, Duration::from_secs(1));
+
+        let image = fetch_image("data:image/png;base64,iVBORw0KGgo=").unwrap();
+        assert_eq!(image.content_type, "image/png");
+        assert_eq!(image.data, b"\x89PNG\r\n\x1a\n");
+
+        let image = fetch_image("data:image/svg+xml;charset=utf-8,%3Csvg%2F%3E").unwrap();
+        assert_eq!(image.content_type, "image/svg+xml");
+        assert_eq!(image.data, b"<svg/>");
+
+        assert!(matches!(
+            fetch_image("data:text/plain,hello"),
+            Err(FetchError::IncorrectContentType(_))
+        ));
+    }
+
+    #[test]
+    fn split_fragments() {
+        assert_eq!(
+            split_fragment("https://example.com/page"),
+            ("https://example.com/page", None)
+        );
+        assert_eq!(
+            split_fragment("https://example.com/page#Caf%C3%A9_history"),
+            ("https://example.com/page", Some("Café_history".to_string()))
+        );
+        assert_eq!(
+            split_fragment("https://example.com/page#"),
+            ("https://example.com/page", Some("".to_string()))
+        );
+    }
+
+    #[test]
+    fn resolve_base_urls() {
+        let webpage_url = "https://example.com/blog/post";
+        assert_eq!(get_base_url(webpage_url, None), webpage_url);
+        assert_eq!(
+            get_base_url(webpage_url, Some("/docs/")),
+            "https://example.com/docs/"
+        );
+        assert_eq!(
+            get_base_url(webpage_url, Some("https://cdn.example.com/")),
+            "https://cdn.example.com/"
+        );
+        assert_eq!(
+            get_base_url(webpage_url, Some("javascript:void(0)")),
+            webpage_url
+        );
+    }
+
+    #[test]
+    fn keep_fragments_across_redirects() {
+        assert_eq!(
+            keep_fragment("https://example.com/new/", "http://example.com/old#intro"),
+            "https://example.com/new/#intro"
+        );
+        assert_eq!(
+            keep_fragment(
+                "https://example.com/new/#top",
+                "http://example.com/old#intro"
+            ),
+            "https://example.com/new/#top"
+        );
+        assert_eq!(
+            keep_fragment("https://example.com/new/", "http://example.com/old"),
+            "https://example.com/new/"
+        );
+    }
+
+    #[test]
+    fn normalize_typed_urls() {
+        assert_eq!(normalize_typed_url("example.com"), "https://example.com");
+        assert_eq!(
+            normalize_typed_url(" http://example.com/page "),
+            "http://example.com/page"
+        );
+        assert_eq!(
+            normalize_typed_url("https://example.com"),
+            "https://example.com"
+        );
+    }
+
+    /// Answers one request with the response, and hands back the request it got
+    fn serve_once(response: &'static [u8]) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/page.html", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let bytes_read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..bytes_read]);
+            }
+            stream.write_all(response).unwrap();
+
+            String::from_utf8(request).unwrap().to_ascii_lowercase()
+        });
+
+        (url, server)
+    }
+
+    #[test]
+    fn send_configured_headers() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 9\r\nConnection: close\r\n\r\n<p>Hi</p>");
+
+        let client = build_http_client(&NetworkSettings {
+            user_agent: "TestAgent/1.0".to_string(),
+            accept_encodings: vec![ContentEncoding::Gzip],
+            ..Default::default()
+        });
+        let webpage = fetch_webpage(&client, &url).unwrap();
+        let request = server.join().unwrap();
+
+        assert_eq!(webpage.content, "<p>Hi</p>");
+        assert!(request.contains("user-agent: testagent/1.0\r\n"));
+        assert!(request.contains("accept-encoding: gzip\r\n"));
+    }
+
+    #[test]
+    fn connection_closed_mid_body() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 100\r\nConnection: close\r\n\r\n<p>Hi");
+
+        let result = fetch_webpage(&Client::new(), &url);
+        server.join().unwrap();
+
+        assert!(matches!(result, Err(FetchError::UnknownError(_))));
+    }
 }
@@ -1,15 +1,43 @@
-use htmd::{Element, HtmlToMarkdown};
-use markup5ever_rcdom::NodeData;
-use log::info;
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+use log::{debug, info};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
 use thiserror::Error;
-use tree_sitter::{Node, Parser};
 
-#[derive(Debug, PartialEq)]
+mod image_sources;
+mod inline_svg;
+mod metadata;
+mod readability;
+
+use image_sources::choose_image_url;
+use inline_svg::parse_inline_svg;
+use metadata::parse_metadata;
+
+#[derive(Debug, Default, PartialEq)]
 pub struct Document {
     pub blocks: Vec<Block>,
+    pub metadata: DocumentMetadata,
+}
+
+/// What the page says about itself, as opposed to its content. Every field is optional,
+/// since plenty of pages don't say much.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DocumentMetadata {
+    pub title: Option<String>,
+    pub byline: Option<String>,
+    // As the page wrote it, which is usually (but not always) an ISO 8601 date
+    pub published: Option<String>,
+    pub site_name: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub canonical_url: Option<String>,
+    pub lead_image_url: Option<String>,
+    // The `<base href>` as the page wrote it, which may itself be relative
+    pub base_href: Option<String>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Block {
     Heading {
         level: u8,
@@ -36,34 +64,46 @@ pub enum Block {
     Table {
         rows: Vec<TableRow>,
     },
+    /// Marks where an element with an `id` was, so links to it can find it. Takes no space.
+    Anchor {
+        id: String,
+    },
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ListItem {
     pub marker: ListMarker,
     pub content: Vec<Block>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ListMarker {
     Bullet,
     Ordered { content: String },
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct TableRow {
     pub cells: Vec<TableCell>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct TableCell {
     pub content: Vec<Span>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Span {
-    Text { content: String, style: SpanStyle },
+    Text {
+        content: String,
+        style: SpanStyle,
+    },
     Link(Link),
+    /// An image in the middle of some text, like an icon or an emoji
+    Image {
+        url: String,
+        alt_text: Option<String>,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -83,529 +123,758 @@ pub struct Link {
 
 #[derive(Debug, Error)]
 pub enum ParseError {
-    #[error("HTMD error")]
-    HtmdError,
-
-    #[error("TreeSitter error")]
-    TreeSitterError,
-
-    #[error("Encountered unexpected node kind: {0}")]
-    UnexpectedNodeKind(String),
-
-    #[error("Wrong node kind: Expected {0} but received {1}")]
-    WrongNodeKind(String, String),
-
-    #[error("Missing expected node kind: {0}")]
-    MissingExpectedNodeKind(String),
+    #[error("Missing expected element: {0}")]
+    MissingExpectedElement(String),
+}
 
-    #[error("Failed to get node text: {0}")]
-    FailedToGetNodeText(#[from] std::str::Utf8Error),
+/// Elements whose contents never show up in the document
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "head", "title", "script", "style", "noscript", "template", "iframe", "object", "embed",
+    "canvas", "svg", "math", "input", "select", "textarea", "button",
+];
+
+/// Elements that start a new block, rather than flowing inline with their siblings
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "center",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+/// Elements whose content is flattened into text, losing the elements inside. Their ids
+/// are moved in front of them instead.
+const FLATTENED_ELEMENTS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6", "pre", "table"];
+
+/// Fallback text for links that contain no text at all, like a linked image
+const COMPLEX_LINK_TEXT: &str = "(complex link contents)";
+
+/// Intermediate inline content, before it is sorted into paragraphs and image blocks
+enum Inline {
+    Span(Span),
+    LineBreak(SpanStyle),
+    Anchor(String),
 }
 
 pub fn parse_webpage(page_html: &str) -> Result<Document, ParseError> {
-    // HACK: we're parsing from HTML to markdown, then parsing that markdown
-    // We should eventually consolidate and just work with a single intermediate representation
+    let dom = parse_document(RcDom::default(), Default::default()).one(page_html);
+    let node_body = find_body(&dom)?;
 
-    let converter = HtmlToMarkdown::builder()
-        .add_handler(vec!["script", "style", "title"], |_: Element| None)
-        .add_handler(vec!["figcaption"], figcaption_handler)
-        .add_handler(vec!["dt", "dd"], definition_list_handler)
-        .add_handler(vec!["tbody"], table_body_handler)
-        .add_handler(vec!["tr"], table_row_handler)
-        .add_handler(vec!["td", "th"], table_cell_handler)
-        .build();
-    let page_markdown = converter.convert(page_html);
-    if page_markdown.is_err() {
-        return Err(ParseError::HtmdError);
-    }
-    let page_markdown = page_markdown.unwrap();
-    let source = page_markdown.as_bytes();
-
-    // info!("Page content as markdown: {}", page_markdown);
-
-    let markdown_language = tree_sitter_markdown::language();
+    let metadata = parse_metadata(&dom);
+    let blocks = parse_child_blocks(&node_body);
 
-    let mut parser = Parser::new();
-    parser
-        .set_language(markdown_language)
-        .expect("Error loading Markdown grammar");
+    let document = Document { blocks, metadata };
 
-    let tree_sitter_parse_result = parser.parse(source, None);
-    if tree_sitter_parse_result.is_none() {
-        return Err(ParseError::TreeSitterError);
-    }
-    let tree = tree_sitter_parse_result.unwrap();
-
-    // info!("Tree: {}", tree.root_node().to_sexp());
+    // info!("Parsed document: {:#?}", document);
 
-    let node_doc = tree.root_node();
+    Ok(document)
+}
 
-    if node_doc.kind() != "document" {
-        panic!("Expected root node to be a document");
-    }
+/// Parses only the main article of a page, for reading mode. Pages where we can't tell
+/// which part is the article are parsed whole instead.
+pub fn parse_article(page_html: &str) -> Result<Document, ParseError> {
+    let dom = parse_document(RcDom::default(), Default::default()).one(page_html);
+    let node_body = find_body(&dom)?;
 
-    let child_blocks = parse_child_blocks(&node_doc, source)?;
+    // Before looking for the article, since that throws away parts of the page
+    let metadata = parse_metadata(&dom);
 
-    let document = Document {
-        blocks: child_blocks,
+    let Some(node_article_parts) = readability::extract_article(&node_body) else {
+        info!("Could not find the main article, so parsing the whole page");
+        return parse_webpage(page_html);
     };
 
-    // info!("Parsed document: {:#?}", document);
-
-    Ok(document)
-}
+    let blocks = node_article_parts.iter().flat_map(parse_block).collect();
 
-fn figcaption_handler(element: Element) -> Option<String> {
-    Some(format!("\n\n{}\n\n", element.content))
+    Ok(Document { blocks, metadata })
 }
 
-fn definition_list_handler(element: Element) -> Option<String> {
-    Some(format!("{}\n\n", element.content))
+fn find_body(dom: &RcDom) -> Result<Handle, ParseError> {
+    find_element(&dom.document, "body")
+        .ok_or_else(|| ParseError::MissingExpectedElement("body".to_string()))
 }
 
-fn table_body_handler(element: Element) -> Option<String> {
-    let num_columns = count_table_columns(&element);
+fn parse_child_blocks(node_parent: &Handle) -> Vec<Block> {
+    let mut blocks = vec![];
+    let mut pending_inlines: Vec<Inline> = vec![];
 
-    if num_columns == 0 {
-        Some(element.content.to_string())
-    } else {
-        let divider = "| - ".repeat(num_columns) + "|";
+    for node_child in node_parent.children.borrow().iter() {
+        if is_skipped(node_child) && !is_svg(node_child) {
+            continue;
+        }
 
-        Some(format!("{}\n{}", divider, element.content))
+        if is_block(node_child) {
+            flush_inlines(&mut pending_inlines, &mut blocks);
+            blocks.extend(parse_block(node_child));
+        } else {
+            parse_inline(node_child, &SpanStyle::Normal, &mut pending_inlines);
+        }
     }
-}
 
-fn count_table_columns(tbody_element: &Element) -> usize {
-    let tbody_children = tbody_element.node.children.borrow();
-
-    for child in tbody_children.iter() {
-        if let NodeData::Element{ name: node_name, .. } = &child.data {
-            if node_name.local.to_string() == "tr" {
-                let first_row = child;
-                let tr_children = first_row.children.borrow();
-
-                let mut num_columns = 0;
-                for tr_child in tr_children.iter() {
-                    if let NodeData::Element{ name: node_name, .. } = &tr_child.data {
-                        if node_name.local.to_string() == "td" {
-                            num_columns += 1;
-                        }
-                    }
-                }
+    flush_inlines(&mut pending_inlines, &mut blocks);
 
-                return num_columns;
-            }
+    blocks
+}
+
+/// Turns a run of inline content into a paragraph, or into image blocks if it has no text.
+/// Images in a paragraph with text stay in it, for the renderer to place.
+fn flush_inlines(inlines: &mut Vec<Inline>, blocks: &mut Vec<Block>) {
+    let has_text = inlines.iter().any(|inline| match inline {
+        Inline::Span(Span::Text { content, .. }) => !is_blank(content),
+        Inline::Span(Span::Link(_)) => true,
+        Inline::Span(Span::Image { .. }) | Inline::LineBreak(_) | Inline::Anchor(_) => false,
+    });
+
+    // Anchors go in front of the paragraph they were in
+    for inline in inlines.iter() {
+        if let Inline::Anchor(id) = inline {
+            blocks.push(Block::Anchor { id: id.clone() });
         }
     }
 
-    0
-}
+    if has_text {
+        let spans = normalize_spans(inlines.drain(..));
 
-fn table_row_handler(element: Element) -> Option<String> {
-    Some(format!("{}|\n", element.content))
+        if !spans.is_empty() {
+            blocks.push(Block::Paragraph { content: spans });
+        }
+    } else {
+        for inline in inlines.drain(..) {
+            if let Inline::Span(Span::Image { url, alt_text }) = inline {
+                blocks.push(Block::Image { url, alt_text });
+            }
+        }
+    }
 }
 
-fn table_cell_handler(element: Element) -> Option<String> {
-    Some(format!("| {} ", element.content))
-}
+fn parse_block(node_block: &Handle) -> Vec<Block> {
+    let tag_name = element_name(node_block).unwrap_or_default();
 
-fn parse_child_blocks(parent_block: &Node, source: &[u8]) -> Result<Vec<Block>, ParseError> {
-    let mut cursor = parent_block.walk();
-    let mut blocks = vec![];
+    let mut blocks: Vec<Block> = get_anchor_id(node_block)
+        .map(|id| Block::Anchor { id })
+        .into_iter()
+        .collect();
+    if FLATTENED_ELEMENTS.contains(&tag_name.as_str()) {
+        collect_descendant_anchors(node_block, &mut blocks);
+    }
 
-    for node_block in parent_block.named_children(&mut cursor) {
-        let block_result = parse_block(&node_block, source);
-        if let Err(block_error) = block_result {
-            return Err(block_error);
+    let block = match tag_name.as_str() {
+        "h1" => parse_heading(node_block, 1),
+        "h2" => parse_heading(node_block, 2),
+        "h3" => parse_heading(node_block, 3),
+        "h4" => parse_heading(node_block, 4),
+        "h5" => parse_heading(node_block, 5),
+        "h6" => parse_heading(node_block, 6),
+        "ul" => parse_list(node_block, false),
+        "ol" => parse_list(node_block, true),
+        "blockquote" => parse_block_quote(node_block),
+        "hr" => Some(Block::ThematicBreak),
+        "pre" => parse_code_block(node_block),
+        "table" => {
+            blocks.extend(parse_table(node_block));
+            return blocks;
         }
-        let block = block_result.unwrap();
-        if let Some(block) = block {
-            blocks.push(block);
+        // A link around whole blocks, like a card with a heading and a summary
+        "a" if get_attribute(node_block, "href").is_some() => {
+            let destination = get_attribute(node_block, "href").unwrap_or_default();
+            blocks.extend(
+                parse_child_blocks(node_block)
+                    .into_iter()
+                    .map(|block| link_block(block, &destination)),
+            );
+            return blocks;
         }
-    }
+        // Everything else (div, p, section, figure, ...) is a transparent container
+        _ => {
+            blocks.extend(parse_child_blocks(node_block));
+            return blocks;
+        }
+    };
 
-    Ok(blocks)
+    blocks.extend(block);
+    blocks
 }
 
-fn parse_block(node_block: &Node, source: &[u8]) -> Result<Option<Block>, ParseError> {
-    match node_block.kind() {
-        "atx_heading" => parse_heading(node_block, source),
-        "paragraph" => parse_paragraph(node_block, source),
-        "tight_list" => parse_list(node_block, source),
-        "loose_list" => parse_list(node_block, source),
-        "block_quote" => parse_block_quote(node_block, source),
-        "thematic_break" => Ok(Some(Block::ThematicBreak)),
-        "fenced_code_block" => parse_code_block(node_block, source),
-        "html_block" => Ok(Some(Block::Paragraph {
-            content: vec![Span::Text {
-                content: "(HTML block)".to_string(),
-                style: SpanStyle::Normal,
-            }],
-        })),
-        "list_marker" => Ok(None),
-        "table" => parse_table(node_block, source),
-        _ => Err(ParseError::UnexpectedNodeKind(
-            node_block.kind().to_string(),
-        )),
+/// Makes the text of a block inside a link part of the link, so tapping it follows the link
+fn link_block(block: Block, destination: &str) -> Block {
+    match block {
+        Block::Heading { level, content } => Block::Heading {
+            level,
+            content: link_spans(content, destination),
+        },
+        Block::Paragraph { content } => Block::Paragraph {
+            content: link_spans(content, destination),
+        },
+        Block::BlockQuote { content } => Block::BlockQuote {
+            content: content
+                .into_iter()
+                .map(|block| link_block(block, destination))
+                .collect(),
+        },
+        Block::List { items } => Block::List {
+            items: items
+                .into_iter()
+                .map(|item| ListItem {
+                    marker: item.marker,
+                    content: item
+                        .content
+                        .into_iter()
+                        .map(|block| link_block(block, destination))
+                        .collect(),
+                })
+                .collect(),
+        },
+        block => block,
     }
 }
 
-fn parse_heading(node_heading: &Node, source: &[u8]) -> Result<Option<Block>, ParseError> {
-    let mut cursor = node_heading.walk();
+/// Turns each run of text into a link. Links and images already in it are left alone.
+fn link_spans(spans: Vec<Span>, destination: &str) -> Vec<Span> {
+    let mut result = vec![];
+    let mut raw_text = String::new();
 
-    cursor.goto_first_child();
+    let flush_text = |raw_text: &mut String, result: &mut Vec<Span>| {
+        let text = raw_text.trim();
+        if text.is_empty() {
+            if !raw_text.is_empty() {
+                push_text_span(result, std::mem::take(raw_text), SpanStyle::Normal);
+            }
+            return;
+        }
 
-    let heading_level_marker = cursor.node().kind();
-    let level = match heading_level_marker {
-        "atx_h1_marker" => Some(1),
-        "atx_h2_marker" => Some(2),
-        "atx_h3_marker" => Some(3),
-        "atx_h4_marker" => Some(4),
-        "atx_h5_marker" => Some(5),
-        "atx_h6_marker" => Some(6),
-        _ => None,
+        if raw_text.starts_with(is_html_whitespace) {
+            push_text_span(result, " ".to_string(), SpanStyle::Normal);
+        }
+        result.push(Span::Link(Link {
+            destination: destination.to_string(),
+            text: text.to_string(),
+        }));
+        if raw_text.ends_with(is_html_whitespace) {
+            push_text_span(result, " ".to_string(), SpanStyle::Normal);
+        }
+        raw_text.clear();
     };
-    if level.is_none() {
-        return Err(ParseError::UnexpectedNodeKind(
-            heading_level_marker.to_string(),
-        ));
-    }
-    let level = level.unwrap();
 
-    let moved = cursor.goto_next_sibling();
-    if !moved {
-        // No actual content in this heading, so skip it
-        return Ok(None);
+    for span in spans {
+        match span {
+            Span::Text { content, .. } => raw_text.push_str(&content),
+            span => {
+                flush_text(&mut raw_text, &mut result);
+                result.push(span);
+            }
+        }
     }
+    flush_text(&mut raw_text, &mut result);
 
-    if cursor.node().kind() != "heading_content" {
-        return Err(ParseError::UnexpectedNodeKind(
-            cursor.node().kind().to_string(),
-        ));
-    }
-    let node_heading_content = cursor.node();
-    let mut spans = flatten_child_spans(&node_heading_content, &SpanStyle::Normal, source)?;
+    result
+}
 
-    // HACK: tree-sitter adds a leading space to the first span, so we trim it
-    if let Some(first_span) = spans.first_mut() {
-        if let Span::Text { content, .. } = first_span {
-            *content = content.trim_start().to_string();
+fn collect_descendant_anchors(node: &Handle, blocks: &mut Vec<Block>) {
+    for node_child in node.children.borrow().iter() {
+        if is_skipped(node_child) {
+            continue;
         }
+
+        if let Some(id) = get_anchor_id(node_child) {
+            blocks.push(Block::Anchor { id });
+        }
+        collect_descendant_anchors(node_child, blocks);
     }
+}
 
-    Ok(Some(Block::Heading {
+fn parse_heading(node_heading: &Handle, level: u8) -> Option<Block> {
+    let spans = flatten_child_spans(node_heading, &SpanStyle::Normal);
+    if spans.is_empty() {
+        // No actual content in this heading, so skip it
+        return None;
+    }
+
+    Some(Block::Heading {
         level,
         content: spans,
-    }))
+    })
 }
 
-fn parse_paragraph(node_paragraph: &Node, source: &[u8]) -> Result<Option<Block>, ParseError> {
-    // If the paragraph contains an image and nothing else, create a Block::Image
-    let mut cursor = node_paragraph.walk();
-    let mut has_image = false;
-    let mut has_text = false;
-    for child in node_paragraph.named_children(&mut cursor) {
-        match child.kind() {
-            "image" => has_image = true,
-            "text" => has_text = true,
-            _ => {}
-        }
+fn parse_block_quote(node_block_quote: &Handle) -> Option<Block> {
+    let child_blocks = parse_child_blocks(node_block_quote);
+    if child_blocks.is_empty() {
+        return None;
     }
 
-    if has_image && !has_text {
-        return parse_image(node_paragraph, source);
+    Some(Block::BlockQuote {
+        content: child_blocks,
+    })
+}
+
+fn parse_code_block(node_pre: &Handle) -> Option<Block> {
+    let mut content = String::new();
+    collect_text(node_pre, &mut content);
+
+    let content = content.trim_end_matches('\n').to_string();
+    if content.is_empty() {
+        return None;
     }
 
-    let spans = flatten_child_spans(node_paragraph, &SpanStyle::Normal, source)?;
+    let node_code = node_pre
+        .children
+        .borrow()
+        .iter()
+        .find(|child| element_name(child).as_deref() == Some("code"))
+        .cloned();
+
+    let language = node_code
+        .and_then(|node_code| get_code_language(&node_code))
+        .or_else(|| get_code_language(node_pre));
 
-    Ok(Some(Block::Paragraph { content: spans }))
+    Some(Block::CodeBlock { language, content })
 }
 
-fn parse_image(node_paragraph: &Node, source: &[u8]) -> Result<Option<Block>, ParseError> {
-    let mut cursor = node_paragraph.walk();
-    let node_image = expect_node_kind(node_paragraph.named_child(0), "image")?;
-
-    let node_link_destination = expect_node_kind(
-        node_image
-            .named_children(&mut cursor)
-            .find(|child| child.kind() == "link_destination"),
-        "link_destination",
-    )?;
-    let node_link_destination_inner_text =
-        expect_node_kind(node_link_destination.named_child(0), "text")?;
-    let url = node_link_destination_inner_text
-        .utf8_text(source)?
-        .to_string();
-
-    let node_image_description = node_image
-        .named_children(&mut cursor)
-        .find(|child| child.kind() == "image_description");
-
-    let alt_text: Option<String> = match node_image_description {
-        Some(node_image_description) => {
-            let node_image_description_inner_text =
-                expect_node_kind(node_image_description.named_child(0), "text")?;
-
-            Some(
-                node_image_description_inner_text
-                    .utf8_text(source)?
-                    .to_string(),
-            )
-        }
-        None => None,
-    };
+fn get_code_language(node: &Handle) -> Option<String> {
+    let class = get_attribute(node, "class")?;
 
-    Ok(Some(Block::Image { url, alt_text }))
+    class.split_whitespace().find_map(|class_name| {
+        class_name
+            .strip_prefix("language-")
+            .or_else(|| class_name.strip_prefix("lang-"))
+            .map(|language| language.to_string())
+    })
 }
 
-fn parse_block_quote(node_block_quote: &Node, source: &[u8]) -> Result<Option<Block>, ParseError> {
-    let child_blocks = parse_child_blocks(node_block_quote, source)?;
+fn parse_list(node_list: &Handle, is_ordered: bool) -> Option<Block> {
+    let node_list_items: Vec<Handle> = node_list
+        .children
+        .borrow()
+        .iter()
+        .filter(|node_child| element_name(node_child).as_deref() == Some("li"))
+        .cloned()
+        .collect();
+
+    if node_list_items.is_empty() {
+        return None;
+    }
 
-    Ok(Some(Block::BlockQuote {
-        content: child_blocks,
-    }))
-}
+    let is_reversed = get_attribute(node_list, "reversed").is_some();
+    let step: i64 = if is_reversed { -1 } else { 1 };
+    let numbering_type = get_attribute(node_list, "type").unwrap_or_default();
+    let mut number: i64 = get_attribute(node_list, "start")
+        .and_then(|start| start.trim().parse().ok())
+        .unwrap_or(if is_reversed {
+            node_list_items.len() as i64
+        } else {
+            1
+        });
 
-fn parse_code_block(
-    node_fenced_code_block: &Node,
-    source: &[u8],
-) -> Result<Option<Block>, ParseError> {
-    let mut cursor = node_fenced_code_block.walk();
-
-    let node_info_string = node_fenced_code_block
-        .named_children(&mut cursor)
-        .find(|child| child.kind() == "info_string");
-    let language = match node_info_string {
-        Some(node_info_string) => {
-            let node_text = expect_node_kind(node_info_string.named_child(0), "text")?;
-            let language = node_text.utf8_text(source)?.to_string();
-
-            Some(language)
-        }
-        None => None,
-    };
+    let mut items: Vec<ListItem> = vec![];
 
-    let node_code_fence_content = expect_node_kind(
-        node_fenced_code_block
-            .named_children(&mut cursor)
-            .find(|child| child.kind() == "code_fence_content"),
-        "code_fence_content",
-    )?;
-
-    let mut content: String = String::new();
-    for child in node_code_fence_content.named_children(&mut cursor) {
-        match child.kind() {
-            "text" => {
-                let node_text = expect_node_kind(Some(child), "text")?;
-                content.push_str(node_text.utf8_text(source)?);
-            }
-            "line_break" => {
-                content.push('\n');
-            }
-            _ => {
-                return Err(ParseError::UnexpectedNodeKind(child.kind().to_string()));
+    for node_list_item in node_list_items.iter() {
+        let marker = if is_ordered {
+            // An item can set its own number, and the ones after it continue from there
+            if let Some(value) =
+                get_attribute(node_list_item, "value").and_then(|value| value.trim().parse().ok())
+            {
+                number = value;
             }
-        }
+
+            let content = format_list_number(number, &numbering_type);
+            number += step;
+
+            ListMarker::Ordered { content }
+        } else {
+            ListMarker::Bullet
+        };
+
+        items.push(parse_list_item(node_list_item, marker));
     }
 
-    Ok(Some(Block::CodeBlock { language, content }))
+    Some(Block::List { items })
 }
 
-fn parse_list(node_list: &Node, source: &[u8]) -> Result<Option<Block>, ParseError> {
-    let mut items: Vec<ListItem> = vec![];
+fn parse_list_item(node_list_item: &Handle, marker: ListMarker) -> ListItem {
+    let mut content: Vec<Block> = get_anchor_id(node_list_item)
+        .map(|id| Block::Anchor { id })
+        .into_iter()
+        .collect();
+    content.extend(parse_child_blocks(node_list_item));
 
-    let mut cursor = node_list.walk();
-    for node_list_item in node_list.named_children(&mut cursor) {
-        let node_list_item = expect_node_kind(Some(node_list_item), "list_item")?;
-        let item = parse_list_item(&node_list_item, source)?;
-        items.push(item);
-    }
+    ListItem { marker, content }
+}
+
+/// Formats the marker of an ordered list item, following the `type` attribute of the list
+fn format_list_number(number: i64, numbering_type: &str) -> String {
+    let formatted = match numbering_type {
+        "a" => format_alphabetic(number),
+        "A" => format_alphabetic(number).map(|letters| letters.to_uppercase()),
+        "i" => format_roman(number),
+        "I" => format_roman(number).map(|numeral| numeral.to_uppercase()),
+        _ => None,
+    };
 
-    Ok(Some(Block::List { items }))
+    // Letters and numerals can't count zero or below, so those fall back to plain numbers
+    format!("{}.", formatted.unwrap_or_else(|| number.to_string()))
 }
 
-fn parse_list_item(node_list_item: &Node, source: &[u8]) -> Result<ListItem, ParseError> {
-    let temp_marker = node_list_item.named_child(0).unwrap();
-    let temp_marker_text = temp_marker.utf8_text(source)?.to_string();
-    info!("List item marker: {}", temp_marker_text);
+fn format_alphabetic(number: i64) -> Option<String> {
+    if number < 1 {
+        return None;
+    }
+
+    let mut letters = vec![];
+    let mut remaining = number;
+    while remaining > 0 {
+        remaining -= 1;
+        letters.push((b'a' + (remaining % 26) as u8) as char);
+        remaining /= 26;
+    }
+
+    Some(letters.iter().rev().collect())
+}
 
-    let _node_list_marker = expect_node_kind(node_list_item.named_child(0), "list_marker")?;
-    // TODO: pass the type of the list marker
+fn format_roman(number: i64) -> Option<String> {
+    if !(1..4000).contains(&number) {
+        return None;
+    }
 
-    let child_blocks = parse_child_blocks(node_list_item, source)?;
+    const NUMERALS: [(i64, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+
+    let mut numeral = String::new();
+    let mut remaining = number;
+    for (value, symbol) in NUMERALS {
+        while remaining >= value {
+            numeral.push_str(symbol);
+            remaining -= value;
+        }
+    }
 
-    Ok(ListItem {
-        marker: ListMarker::Bullet,
-        content: child_blocks,
-    })
+    Some(numeral)
 }
 
-fn parse_table(node_table: &Node, source: &[u8]) -> Result<Option<Block>, ParseError> {
+fn parse_table(node_table: &Handle) -> Vec<Block> {
+    let mut blocks = vec![];
     let mut rows: Vec<TableRow> = vec![];
 
-    let mut cursor = node_table.walk();
-    for node_row in node_table.named_children(&mut cursor) {
-        let row = parse_table_row(&node_row, source)?;
-        if let Some(row) = row {
-            rows.push(row);
+    for node_child in node_table.children.borrow().iter() {
+        match element_name(node_child).as_deref() {
+            Some("caption") => {
+                let spans = flatten_child_spans(node_child, &SpanStyle::Normal);
+                if !spans.is_empty() {
+                    blocks.push(Block::Paragraph { content: spans });
+                }
+            }
+            Some("thead") | Some("tbody") | Some("tfoot") => {
+                for node_row in node_child.children.borrow().iter() {
+                    if let Some(row) = parse_table_row(node_row) {
+                        rows.push(row);
+                    }
+                }
+            }
+            Some("tr") => {
+                if let Some(row) = parse_table_row(node_child) {
+                    rows.push(row);
+                }
+            }
+            _ => {}
         }
     }
 
-    Ok(Some(Block::Table { rows }))
+    if !rows.is_empty() {
+        blocks.push(Block::Table { rows });
+    }
+
+    blocks
 }
 
-fn parse_table_row(node_row: &Node, source: &[u8]) -> Result<Option<TableRow>, ParseError> {
-    match node_row.kind() {
-        "table_header_row" | "table_data_row" => (),
-        _ => return Ok(None),
+fn parse_table_row(node_row: &Handle) -> Option<TableRow> {
+    if element_name(node_row).as_deref() != Some("tr") {
+        return None;
     }
 
-    let mut cursor = node_row.walk();
     let mut cells: Vec<TableCell> = vec![];
 
-    for node_cell in node_row.named_children(&mut cursor) {
-        let parent_style = match node_row.kind() {
-            "table_header_row" => &SpanStyle::Bold,
-            "table_data_row" => &SpanStyle::Normal,
-            _ => unreachable!(),
+    for node_cell in node_row.children.borrow().iter() {
+        let parent_style = match element_name(node_cell).as_deref() {
+            Some("th") => &SpanStyle::Bold,
+            Some("td") => &SpanStyle::Normal,
+            _ => continue,
         };
-        let cell = parse_table_cell(&node_cell, source, parent_style)?;
+        let cell = parse_table_cell(node_cell, parent_style);
         cells.push(cell);
     }
 
-    Ok(Some(TableRow { cells }))
-}
-
-fn parse_table_cell(node_cell: &Node, source: &[u8], parent_style: &SpanStyle) -> Result<TableCell, ParseError> {
-    if node_cell.kind() != "table_cell" {
-        return Err(ParseError::WrongNodeKind(
-            "table_cell".to_string(),
-            node_cell.kind().to_string(),
-        ));
+    if cells.is_empty() {
+        return None;
     }
 
-    // TODO: trim whitespace at beginning and end of cell content
+    Some(TableRow { cells })
+}
+
+fn parse_table_cell(node_cell: &Handle, parent_style: &SpanStyle) -> TableCell {
+    let content = flatten_child_spans(node_cell, parent_style);
 
-    let content = flatten_child_spans(node_cell, parent_style, source)?;
-    Ok(TableCell { content })
+    TableCell { content }
 }
 
-fn flatten_child_spans(
-    node_parent: &Node,
-    parent_style: &SpanStyle,
-    source: &[u8],
-) -> Result<Vec<Span>, ParseError> {
-    let mut overall_spans = vec![];
-
-    let mut cursor = node_parent.walk();
-    for node_child in node_parent.named_children(&mut cursor) {
-        let spans = parse_span(&node_child, parent_style, source)?;
-        for span in spans {
-            overall_spans.push(span);
-        }
+/// Collects all of the text inside a node as spans, flattening any block structure
+fn flatten_child_spans(node_parent: &Handle, parent_style: &SpanStyle) -> Vec<Span> {
+    let mut inlines = vec![];
+
+    for node_child in node_parent.children.borrow().iter() {
+        parse_inline(node_child, parent_style, &mut inlines);
     }
 
-    Ok(overall_spans)
+    normalize_spans(inlines.into_iter())
 }
 
-fn parse_span(
-    node_span: &Node,
-    parent_style: &SpanStyle,
-    source: &[u8],
-) -> Result<Vec<Span>, ParseError> {
-    match node_span.kind() {
-        "text" => {
-            let text = node_span.utf8_text(source)?.to_string();
-            Ok(vec![Span::Text {
-                content: text,
+fn parse_inline(node: &Handle, parent_style: &SpanStyle, inlines: &mut Vec<Inline>) {
+    let tag_name = match &node.data {
+        NodeData::Text { contents } => {
+            inlines.push(Inline::Span(Span::Text {
+                content: contents.borrow().to_string(),
                 style: parent_style.clone(),
-            }])
+            }));
+            return;
+        }
+        NodeData::Element { name, .. } => name.local.to_string(),
+        _ => return,
+    };
+
+    // Drawn as an image, though its contents are skipped everywhere else
+    if tag_name == "svg" {
+        inlines.extend(parse_inline_svg(node).map(Inline::Span));
+        return;
+    }
+
+    if SKIPPED_ELEMENTS.contains(&tag_name.as_str()) {
+        return;
+    }
+
+    if let Some(id) = get_anchor_id(node) {
+        inlines.push(Inline::Anchor(id));
+    }
+
+    match tag_name.as_str() {
+        "br" => inlines.push(Inline::LineBreak(parent_style.clone())),
+        "img" => {
+            if let Some(image) = parse_image(node, None) {
+                inlines.push(image);
+            }
         }
-        "link" => {
-            let link = parse_link(node_span, source)?;
-            Ok(vec![Span::Link(link)])
+        "picture" => {
+            let node_image = node
+                .children
+                .borrow()
+                .iter()
+                .find(|node_child| element_name(node_child).as_deref() == Some("img"))
+                .cloned();
+            if let Some(image) =
+                node_image.and_then(|node_image| parse_image(&node_image, Some(node)))
+            {
+                inlines.push(image);
+            }
         }
-        "strong_emphasis" => flatten_child_spans(
-            node_span,
-            &merge_styles(parent_style, &SpanStyle::Bold),
-            source,
-        ),
-        "emphasis" => flatten_child_spans(
-            node_span,
+        "a" if get_attribute(node, "href").is_some() => parse_link(node, parent_style, inlines),
+        "strong" | "b" => {
+            parse_child_inlines(node, &merge_styles(parent_style, &SpanStyle::Bold), inlines)
+        }
+        "em" | "i" | "cite" | "dfn" | "var" => parse_child_inlines(
+            node,
             &merge_styles(parent_style, &SpanStyle::Italic),
-            source,
+            inlines,
         ),
-        "code_span" => {
-            let node_code_span_content = expect_node_kind(node_span.named_child(0), "text")?;
-            let text = node_code_span_content.utf8_text(source)?.to_string();
-            Ok(vec![Span::Text {
-                content: text,
-                style: SpanStyle::Code,
-            }])
-        }
-        "backslash_escape" => {
-            let text = node_span.utf8_text(source)?.to_string();
-            let text = match text.as_str() {
-                "\\[" => "[".to_string(),
-                "\\]" => "]".to_string(),
-                "\\_" => "_".to_string(),
-                _ => format!("[TODO: handle backslash_escape content `{}`]", text),
-            };
-            info!("backslash escape {}", text);
-            Ok(vec![Span::Text {
-                content: text,
-                style: parent_style.clone(),
-            }])
-        }
-        "hard_line_break" => Ok(vec![Span::Text {
-            content: "\n".to_string(),
-            style: parent_style.clone(),
-        }]),
-        other_kind => {
-            let text = format!("[TODO: parse node `{}`]", other_kind);
-            Ok(vec![Span::Text {
-                content: text,
-                style: parent_style.clone(),
-            }])
+        "code" | "kbd" | "samp" | "tt" => parse_child_inlines(node, &SpanStyle::Code, inlines),
+        _ if BLOCK_ELEMENTS.contains(&tag_name.as_str()) => {
+            // Flattening a block into inline content, so keep it apart from its neighbors
+            inlines.push(whitespace_inline(parent_style));
+            parse_child_inlines(node, parent_style, inlines);
+            inlines.push(whitespace_inline(parent_style));
         }
+        _ => parse_child_inlines(node, parent_style, inlines),
     }
 }
 
-fn parse_link(node_link: &Node, source: &[u8]) -> Result<Link, ParseError> {
-    let mut cursor = node_link.walk();
+fn parse_child_inlines(node_parent: &Handle, parent_style: &SpanStyle, inlines: &mut Vec<Inline>) {
+    for node_child in node_parent.children.borrow().iter() {
+        parse_inline(node_child, parent_style, inlines);
+    }
+}
 
-    let node_link_text = node_link
-        .named_children(&mut cursor)
-        .find(|child| child.kind() == "link_text");
+/// Parses an `<img>`, on its own or as the fallback inside a `<picture>`
+fn parse_image(node_image: &Handle, node_picture: Option<&Handle>) -> Option<Inline> {
+    let url = choose_image_url(node_image, node_picture)?;
 
-    let text = match node_link_text {
-        None => "(no link text)".to_string(),
-        Some(node_link_text) => {
-            if node_link_text.named_child_count() == 1
-                && node_link_text.named_child(0).unwrap().kind() == "text"
-            {
-                let node_link_text_inner = expect_node_kind(node_link_text.named_child(0), "text")?;
-                let text = node_link_text_inner.utf8_text(source)?.to_string();
+    let alt_text = get_attribute(node_image, "alt").filter(|alt_text| !alt_text.is_empty());
 
-                text
-            } else {
-                "(complex link contents)".to_string()
-            }
+    Some(Inline::Span(Span::Image { url, alt_text }))
+}
+
+fn parse_link(node_link: &Handle, parent_style: &SpanStyle, inlines: &mut Vec<Inline>) {
+    let destination = get_attribute(node_link, "href").unwrap_or_default();
+
+    let mut child_inlines = vec![];
+    parse_child_inlines(node_link, parent_style, &mut child_inlines);
+
+    let mut raw_text = String::new();
+    let mut has_image = false;
+    for child_inline in child_inlines.iter() {
+        match child_inline {
+            Inline::Span(Span::Text { content, .. }) => raw_text.push_str(content),
+            Inline::Span(Span::Link(link)) => raw_text.push_str(&link.text),
+            Inline::LineBreak(_) => raw_text.push(' '),
+            Inline::Span(Span::Image { .. }) => has_image = true,
+            // Kept, but in front of the link, since links can't hold them
+            Inline::Anchor(id) => inlines.push(Inline::Anchor(id.clone())),
         }
+    }
+
+    let text = collapse_whitespace(&raw_text).trim().to_string();
+    let text = if !text.is_empty() {
+        text
+    } else if has_image {
+        COMPLEX_LINK_TEXT.to_string()
+    } else {
+        debug!("Skipping link to {} without any contents", destination);
+        return;
     };
 
-    let node_link_destination = expect_node_kind(
-        node_link
-            .named_children(&mut cursor)
-            .find(|child| child.kind() == "link_destination"),
-        "link_destination",
-    )?;
+    // Whitespace at the edges of the link still separates it from the surrounding text
+    if raw_text.starts_with(is_html_whitespace) {
+        inlines.push(whitespace_inline(parent_style));
+    }
+    inlines.push(Inline::Span(Span::Link(Link { destination, text })));
+    if raw_text.ends_with(is_html_whitespace) {
+        inlines.push(whitespace_inline(parent_style));
+    }
+}
+
+/// Applies HTML whitespace rules to a run of inline content: collapses whitespace (also
+/// across span boundaries), trims the edges, drops empty spans and merges neighbors with
+/// the same style. Anchors are dropped.
+fn normalize_spans(inlines: impl Iterator<Item = Inline>) -> Vec<Span> {
+    let mut result: Vec<Span> = vec![];
+    let mut ends_with_whitespace = true;
+
+    for inline in inlines {
+        match inline {
+            Inline::Span(Span::Text { content, style }) => {
+                let mut content = collapse_whitespace(&content);
+                if ends_with_whitespace {
+                    content = content.trim_start_matches(' ').to_string();
+                }
+                if content.is_empty() {
+                    continue;
+                }
+                ends_with_whitespace = content.ends_with(' ');
+
+                push_text_span(&mut result, content, style);
+            }
+            Inline::LineBreak(style) => {
+                if let Some(Span::Text { content, .. }) = result.last_mut() {
+                    let trimmed_len = content.trim_end_matches(' ').len();
+                    content.truncate(trimmed_len);
+                }
+                ends_with_whitespace = true;
+
+                push_text_span(&mut result, "\n".to_string(), style);
+            }
+            Inline::Span(span @ (Span::Link(_) | Span::Image { .. })) => {
+                ends_with_whitespace = false;
+                result.push(span);
+            }
+            Inline::Anchor(_) => {}
+        }
+    }
+
+    // Trim trailing whitespace, which may be spread across several spans
+    while let Some(Span::Text { content, .. }) = result.last_mut() {
+        let trimmed_len = content.trim_end().len();
+        content.truncate(trimmed_len);
+
+        if content.is_empty() {
+            result.pop();
+        } else {
+            break;
+        }
+    }
+
+    result
+}
 
-    let node_link_destination_inner =
-        expect_node_kind(node_link_destination.named_child(0), "text")?;
-    let destination = node_link_destination_inner.utf8_text(source)?.to_string();
+fn push_text_span(spans: &mut Vec<Span>, content: String, style: SpanStyle) {
+    if let Some(Span::Text {
+        content: previous_content,
+        style: previous_style,
+    }) = spans.last_mut()
+    {
+        if *previous_style == style {
+            previous_content.push_str(&content);
+            return;
+        }
+    }
 
-    Ok(Link { destination, text })
+    spans.push(Span::Text { content, style });
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut previous_was_whitespace = false;
+
+    for character in text.chars() {
+        if is_html_whitespace(character) {
+            if !previous_was_whitespace {
+                result.push(' ');
+            }
+            previous_was_whitespace = true;
+        } else {
+            result.push(character);
+            previous_was_whitespace = false;
+        }
+    }
+
+    result
 }
 
 pub fn merge_styles(parent_style: &SpanStyle, new_style: &SpanStyle) -> SpanStyle {
@@ -616,29 +885,115 @@ pub fn merge_styles(parent_style: &SpanStyle, new_style: &SpanStyle) -> SpanStyl
     }
 }
 
-fn expect_node_kind<'s, 'n>(
-    node: Option<Node<'n>>,
-    expected_kind: &str,
-) -> Result<Node<'n>, ParseError> {
-    match node {
-        None => Err(ParseError::MissingExpectedNodeKind(
-            expected_kind.to_string(),
-        )),
-        Some(node) => {
-            if node.kind() != expected_kind {
-                Err(ParseError::WrongNodeKind(
-                    expected_kind.to_string(),
-                    node.kind().to_string(),
-                ))
-            } else {
-                Ok(node)
+fn whitespace_inline(style: &SpanStyle) -> Inline {
+    Inline::Span(Span::Text {
+        content: " ".to_string(),
+        style: style.clone(),
+    })
+}
+
+fn collect_text(node: &Handle, text: &mut String) {
+    match &node.data {
+        NodeData::Text { contents } => text.push_str(&contents.borrow()),
+        NodeData::Element { name, .. } if &*name.local == "br" => text.push('\n'),
+        _ => {
+            for node_child in node.children.borrow().iter() {
+                collect_text(node_child, text);
             }
         }
     }
 }
 
+fn find_element(node: &Handle, tag_name: &str) -> Option<Handle> {
+    if element_name(node).as_deref() == Some(tag_name) {
+        return Some(node.clone());
+    }
+
+    node.children
+        .borrow()
+        .iter()
+        .find_map(|node_child| find_element(node_child, tag_name))
+}
+
+fn element_name(node: &Handle) -> Option<String> {
+    match &node.data {
+        NodeData::Element { name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+/// The name links can use to point at this element, like `#history`
+fn get_anchor_id(node: &Handle) -> Option<String> {
+    let id = get_attribute(node, "id").or_else(|| match element_name(node).as_deref() {
+        Some("a") => get_attribute(node, "name"),
+        _ => None,
+    })?;
+
+    if id.is_empty() {
+        None
+    } else {
+        Some(id)
+    }
+}
+
+fn get_attribute(node: &Handle, attribute_name: &str) -> Option<String> {
+    match &node.data {
+        NodeData::Element { attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|attr| &*attr.name.local == attribute_name)
+            .map(|attr| attr.value.to_string()),
+        _ => None,
+    }
+}
+
+fn is_svg(node: &Handle) -> bool {
+    element_name(node).as_deref() == Some("svg")
+}
+
+fn is_skipped(node: &Handle) -> bool {
+    match &node.data {
+        NodeData::Element { name, .. } => SKIPPED_ELEMENTS.contains(&&*name.local),
+        NodeData::Text { .. } => false,
+        _ => true,
+    }
+}
+
+/// Whether a node should be laid out as a block. Inline elements that wrap blocks
+/// (like a link around a whole card) are treated as blocks so the structure survives.
+fn is_block(node: &Handle) -> bool {
+    match &node.data {
+        NodeData::Element { name, .. } => {
+            BLOCK_ELEMENTS.contains(&&*name.local) || contains_block(node)
+        }
+        _ => false,
+    }
+}
+
+fn contains_block(node: &Handle) -> bool {
+    node.children
+        .borrow()
+        .iter()
+        .any(|node_child| match &node_child.data {
+            NodeData::Element { name, .. } => {
+                !SKIPPED_ELEMENTS.contains(&&*name.local)
+                    && (BLOCK_ELEMENTS.contains(&&*name.local) || contains_block(node_child))
+            }
+            _ => false,
+        })
+}
+
+fn is_html_whitespace(character: char) -> bool {
+    matches!(character, ' ' | '\t' | '\n' | '\r' | '\x0C')
+}
+
+fn is_blank(text: &str) -> bool {
+    text.chars().all(is_html_whitespace)
+}
+
 #[cfg(test)]
 mod test {
+    use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
     use pretty_assertions::assert_eq;
 
     use super::*;
@@ -647,6 +1002,14 @@ mod test {
         format!("<!doctype html><html><head><title>Document</title></head><body><article>{}</article></body></html>", inner_content)
     }
 
+    /// What `create_html_document` says about itself
+    fn test_metadata() -> DocumentMetadata {
+        DocumentMetadata {
+            title: Some("Document".to_string()),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_parse_simple() {
         let content = r#"
@@ -680,7 +1043,8 @@ mod test {
                             style: SpanStyle::Normal,
                         }],
                     }
-                ]
+                ],
+                metadata: test_metadata(),
             }
         );
     }
@@ -729,7 +1093,8 @@ mod test {
                             },
                         ],
                     }
-                ]
+                ],
+                metadata: test_metadata(),
             }
         );
     }
@@ -769,7 +1134,8 @@ mod test {
                             style: SpanStyle::Normal,
                         },
                     ],
-                }]
+                }],
+                metadata: test_metadata(),
             }
         );
     }
@@ -800,7 +1166,8 @@ mod test {
                             style: SpanStyle::Normal,
                         },
                     ],
-                }]
+                }],
+                metadata: test_metadata(),
             }
         );
     }
@@ -841,7 +1208,8 @@ mod test {
                             style: SpanStyle::Normal,
                         },
                     ]
-                }]
+                }],
+                metadata: test_metadata(),
             }
         );
     }
@@ -884,7 +1252,8 @@ mod test {
                             style: SpanStyle::Normal,
                         },
                     ]
-                }]
+                }],
+                metadata: test_metadata(),
             }
         )
     }
@@ -914,7 +1283,8 @@ mod test {
                         language: None,
                         content: "fn main() {\n    println!(\"Hello, world!\");\n}".to_string(),
                     }
-                ]
+                ],
+                metadata: test_metadata(),
             }
         );
     }
@@ -1018,7 +1388,8 @@ mod test {
                             },
                         ],
                     }
-                ]
+                ],
+                metadata: test_metadata(),
             }
         );
     }
@@ -1058,7 +1429,8 @@ mod test {
                         url: "https://www.example.com/cat.jpg".to_string(),
                         alt_text: None,
                     }
-                ]
+                ],
+                metadata: test_metadata(),
             }
         );
     }
@@ -1089,11 +1461,122 @@ mod test {
                             destination: "https://example.com".to_string(),
                         })]
                     }
-                ]
+                ],
+                metadata: test_metadata(),
             }
         );
     }
 
+    #[test]
+    fn test_link_around_blocks() {
+        let content = r#"
+        <a href="/x"><h2>T</h2><p>d <em>more</em></p></a>
+        "#;
+        let input = create_html_document(content);
+        let document = parse_webpage(&input).unwrap();
+
+        let link = |text: &str| {
+            vec![Span::Link(Link {
+                destination: "/x".to_string(),
+                text: text.to_string(),
+            })]
+        };
+        assert_eq!(
+            document.blocks,
+            vec![
+                Block::Heading {
+                    level: 2,
+                    content: link("T"),
+                },
+                Block::Paragraph {
+                    content: link("d more"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inline_image() {
+        let content = r#"
+        <p>Press <img src="/icons/star.png" alt="the star"> to save.</p>
+        <p><img src="/photos/cat.jpg"></p>
+        "#;
+        let input = create_html_document(content);
+        let document = parse_webpage(&input).unwrap();
+
+        assert_eq!(
+            document,
+            Document {
+                blocks: vec![
+                    Block::Paragraph {
+                        content: vec![
+                            Span::Text {
+                                content: "Press ".to_string(),
+                                style: SpanStyle::Normal,
+                            },
+                            Span::Image {
+                                url: "/icons/star.png".to_string(),
+                                alt_text: Some("the star".to_string()),
+                            },
+                            Span::Text {
+                                content: " to save.".to_string(),
+                                style: SpanStyle::Normal,
+                            },
+                        ]
+                    },
+                    Block::Image {
+                        url: "/photos/cat.jpg".to_string(),
+                        alt_text: None,
+                    },
+                ],
+                metadata: test_metadata(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_inline_svg() {
+        let content = r##"
+        <p>Saved <svg viewBox="0 0 10 10" aria-label="Check mark"><path d="M0 5L4 9"/></svg></p>
+        <svg width="20" height="10"><use xlink:href="#icon-star"/></svg>
+        <div><svg width="20" height="10"><rect width="20" height="10"/></svg></div>
+        "##;
+        let input = create_html_document(content);
+        let document = parse_webpage(&input).unwrap();
+
+        let svg_uri = |svg: &str| {
+            format!(
+                "data:image/svg+xml,{}",
+                utf8_percent_encode(svg, NON_ALPHANUMERIC)
+            )
+        };
+        assert_eq!(
+            document.blocks,
+            vec![
+                Block::Paragraph {
+                    content: vec![
+                        Span::Text {
+                            content: "Saved ".to_string(),
+                            style: SpanStyle::Normal,
+                        },
+                        Span::Image {
+                            url: svg_uri(
+                                r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 10 10" aria-label="Check mark"><path d="M0 5L4 9"></path></svg>"#
+                            ),
+                            alt_text: Some("Check mark".to_string()),
+                        },
+                    ]
+                },
+                Block::Image {
+                    url: svg_uri(
+                        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="20" height="10"><rect width="20" height="10"></rect></svg>"#
+                    ),
+                    alt_text: None,
+                },
+            ]
+        );
+    }
+
     #[test]
     fn test_definition_list() {
         let content = r#"
@@ -1155,7 +1638,8 @@ mod test {
                             },
                         ]
                     },
-                ]
+                ],
+                metadata: test_metadata(),
             }
         );
     }
@@ -1199,8 +1683,354 @@ mod test {
                             style: SpanStyle::Normal
                         }]
                     },
-                ]
+                ],
+                metadata: test_metadata(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let content = r#"
+        <table>
+            <thead>
+                <tr><th>Name</th><th>Legs</th></tr>
+            </thead>
+            <tbody>
+                <tr><td>Cat</td><td><em>Four</em></td></tr>
+                <tr><td><p>Bird</p></td><td>Two</td></tr>
+            </tbody>
+        </table>
+        "#;
+        let input = create_html_document(content);
+        let document = parse_webpage(&input).unwrap();
+
+        let text_cell = |content: &str, style: SpanStyle| TableCell {
+            content: vec![Span::Text {
+                content: content.to_string(),
+                style,
+            }],
+        };
+
+        assert_eq!(
+            document,
+            Document {
+                blocks: vec![Block::Table {
+                    rows: vec![
+                        TableRow {
+                            cells: vec![
+                                text_cell("Name", SpanStyle::Bold),
+                                text_cell("Legs", SpanStyle::Bold),
+                            ]
+                        },
+                        TableRow {
+                            cells: vec![
+                                text_cell("Cat", SpanStyle::Normal),
+                                text_cell("Four", SpanStyle::Italic),
+                            ]
+                        },
+                        TableRow {
+                            cells: vec![
+                                text_cell("Bird", SpanStyle::Normal),
+                                text_cell("Two", SpanStyle::Normal),
+                            ]
+                        },
+                    ]
+                }],
+                metadata: test_metadata(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_line_breaks_and_styled_links() {
+        let content = r#"
+        <p>First line <br>
+        second line with <a href="/about">a <strong>styled</strong> link</a></p>
+        <script>console.log("not content");</script>
+        "#;
+        let input = create_html_document(content);
+        let document = parse_webpage(&input).unwrap();
+
+        assert_eq!(
+            document,
+            Document {
+                blocks: vec![Block::Paragraph {
+                    content: vec![
+                        Span::Text {
+                            content: "First line\nsecond line with ".to_string(),
+                            style: SpanStyle::Normal,
+                        },
+                        Span::Link(Link {
+                            text: "a styled link".to_string(),
+                            destination: "/about".to_string(),
+                        }),
+                    ]
+                }],
+                metadata: test_metadata(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_mixed_inline_and_block_content() {
+        let content = r#"
+        <div>
+            Loose text
+            <blockquote><p>Quoted</p></blockquote>
+            <pre><code class="language-rust">let x = 1;
+</code></pre>
+            <hr>
+        </div>
+        "#;
+        let input = create_html_document(content);
+        let document = parse_webpage(&input).unwrap();
+
+        assert_eq!(
+            document,
+            Document {
+                blocks: vec![
+                    Block::Paragraph {
+                        content: vec![Span::Text {
+                            content: "Loose text".to_string(),
+                            style: SpanStyle::Normal,
+                        }]
+                    },
+                    Block::BlockQuote {
+                        content: vec![Block::Paragraph {
+                            content: vec![Span::Text {
+                                content: "Quoted".to_string(),
+                                style: SpanStyle::Normal,
+                            }]
+                        }]
+                    },
+                    Block::CodeBlock {
+                        language: Some("rust".to_string()),
+                        content: "let x = 1;".to_string(),
+                    },
+                    Block::ThematicBreak,
+                ],
+                metadata: test_metadata(),
             }
         );
     }
+
+    #[test]
+    fn test_ordered_list() {
+        let content = r#"
+        <ol start="3">
+            <li>Preheat the oven
+                <ol type="a">
+                    <li>Check the temperature</li>
+                    <li>Wait</li>
+                </ol>
+            </li>
+            <li>Bake</li>
+            <li value="10">Eat</li>
+            <li>Clean up</li>
+        </ol>
+        "#;
+        let input = create_html_document(content);
+        let document = parse_webpage(&input).unwrap();
+
+        fn paragraph(text: &str) -> Block {
+            Block::Paragraph {
+                content: vec![Span::Text {
+                    content: text.to_string(),
+                    style: SpanStyle::Normal,
+                }],
+            }
+        }
+
+        fn ordered(content: &str) -> ListMarker {
+            ListMarker::Ordered {
+                content: content.to_string(),
+            }
+        }
+
+        assert_eq!(
+            document,
+            Document {
+                blocks: vec![Block::List {
+                    items: vec![
+                        ListItem {
+                            marker: ordered("3."),
+                            content: vec![
+                                paragraph("Preheat the oven"),
+                                Block::List {
+                                    items: vec![
+                                        ListItem {
+                                            marker: ordered("a."),
+                                            content: vec![paragraph("Check the temperature")],
+                                        },
+                                        ListItem {
+                                            marker: ordered("b."),
+                                            content: vec![paragraph("Wait")],
+                                        },
+                                    ]
+                                },
+                            ]
+                        },
+                        ListItem {
+                            marker: ordered("4."),
+                            content: vec![paragraph("Bake")],
+                        },
+                        ListItem {
+                            marker: ordered("10."),
+                            content: vec![paragraph("Eat")],
+                        },
+                        ListItem {
+                            marker: ordered("11."),
+                            content: vec![paragraph("Clean up")],
+                        },
+                    ]
+                }],
+                metadata: test_metadata(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_list_numbering_types() {
+        assert_eq!(format_list_number(1, ""), "1.");
+        assert_eq!(format_list_number(28, "a"), "ab.");
+        assert_eq!(format_list_number(3, "A"), "C.");
+        assert_eq!(format_list_number(14, "i"), "xiv.");
+        assert_eq!(format_list_number(1994, "I"), "MCMXCIV.");
+        assert_eq!(format_list_number(0, "a"), "0.");
+    }
+
+    #[test]
+    fn test_reversed_list() {
+        let input =
+            create_html_document("<ol reversed><li>Three</li><li>Two</li><li>One</li></ol>");
+        let document = parse_webpage(&input).unwrap();
+
+        let Block::List { items } = &document.blocks[0] else {
+            panic!("Expected a list");
+        };
+        let markers: Vec<&ListMarker> = items.iter().map(|item| &item.marker).collect();
+
+        assert_eq!(
+            markers,
+            vec![
+                &ListMarker::Ordered {
+                    content: "3.".to_string()
+                },
+                &ListMarker::Ordered {
+                    content: "2.".to_string()
+                },
+                &ListMarker::Ordered {
+                    content: "1.".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_anchors() {
+        let html = create_html_document(
+            r#"
+            <h2 id="intro">Intro <a name="old-intro"></a></h2>
+            <p>See <span id="note">this note</span>.</p>
+            <ul><li id="first">First</li></ul>
+            "#,
+        );
+
+        let document = parse_webpage(&html).unwrap();
+
+        assert_eq!(
+            document.blocks,
+            vec![
+                Block::Anchor {
+                    id: "intro".to_string()
+                },
+                Block::Anchor {
+                    id: "old-intro".to_string()
+                },
+                Block::Heading {
+                    level: 2,
+                    content: vec![Span::Text {
+                        content: "Intro".to_string(),
+                        style: SpanStyle::Normal,
+                    }],
+                },
+                Block::Anchor {
+                    id: "note".to_string()
+                },
+                Block::Paragraph {
+                    content: vec![Span::Text {
+                        content: "See this note.".to_string(),
+                        style: SpanStyle::Normal,
+                    }],
+                },
+                Block::List {
+                    items: vec![ListItem {
+                        marker: ListMarker::Bullet,
+                        content: vec![
+                            Block::Anchor {
+                                id: "first".to_string()
+                            },
+                            Block::Paragraph {
+                                content: vec![Span::Text {
+                                    content: "First".to_string(),
+                                    style: SpanStyle::Normal,
+                                }],
+                            },
+                        ],
+                    }],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reading_mode() {
+        let article = r##"
+        <h1>Why e-ink?</h1>
+        <p>Paper-like screens are easy on the eyes, even after hours of reading, and they
+        can be read in bright sunlight without any glare.</p>
+        <ul><li><a href="#power">Power</a></li><li><a href="#glare">Glare</a></li></ul>
+        <p>They only use power when the picture changes, so a tablet can last for weeks
+        on a single charge.</p>
+        "##;
+        let page = format!(
+            r#"<!doctype html><html><body>
+            <div class="cookie-banner"><p>We use cookies. By continuing to use this site,
+            you agree to our use of cookies.</p><button>OK</button></div>
+            <header><nav><a href="/">Home</a> <a href="/blog">Blog</a></nav></header>
+            <main>
+                <article>{}
+                    <ul class="share-links"><li><a href="/share">Share</a></li></ul>
+                    <div class="tag-list"><a href="/tags/e-ink">e-ink</a>, <a href="/tags/hardware">hardware</a></div>
+                </article>
+                <div id="comments"><p>Nice article, but have you tried reading on a phone?
+                It works fine for me, honestly.</p></div>
+            </main>
+            <footer><p>Copyright 2024, all rights reserved, no exceptions whatsoever.</p></footer>
+            </body></html>"#,
+            article
+        );
+
+        let document = parse_article(&page).unwrap();
+        let expected = parse_webpage(&create_html_document(article)).unwrap();
+
+        assert_eq!(document.blocks, expected.blocks);
+    }
+
+    #[test]
+    fn test_reading_mode_fallback() {
+        // Nothing here reads like an article, so the whole page is kept
+        let page = create_html_document(
+            r#"
+            <nav><a href="/">Home</a></nav>
+            <ul><li>Milk</li><li>Eggs</li><li>Flour</li></ul>
+            "#,
+        );
+
+        let document = parse_article(&page).unwrap();
+
+        assert_eq!(document, parse_webpage(&page).unwrap());
+        assert_eq!(document.blocks.len(), 2);
+    }
 }
@@ -0,0 +1,390 @@
+//! Choosing which file to load for an image. Pages often offer several sizes of the same
+//! image through `srcset` and `<picture>`, or hide the real one in a `data-` attribute
+//! until a lazy loading script swaps it in, leaving `src` as a tiny placeholder.
+
+use markup5ever_rcdom::Handle;
+
+use super::{element_name, get_attribute};
+use crate::CANVAS_WIDTH;
+
+/// CSS pixels are drawn this many pixels wide on the canvas, like the text is
+const DISPLAY_SCALE: f32 = 2.0;
+/// How wide the canvas is in CSS pixels, for media queries and images without a width
+const VIEWPORT_WIDTH: f32 = CANVAS_WIDTH as f32 / DISPLAY_SCALE;
+/// CSS pixels in an `em` in a media query
+const MEDIA_QUERY_EM: f32 = 16.0;
+
+/// Where lazy loading scripts keep the real `srcset` and `src`, checked before the
+/// standard attributes
+const SRCSET_ATTRIBUTES: &[&str] = &["data-srcset", "data-lazy-srcset", "srcset"];
+const SRC_ATTRIBUTES: &[&str] = &["data-src", "data-lazy-src", "data-original", "src"];
+
+/// The formats we can decode, for skipping `<source>`s in others (like AVIF)
+const SUPPORTED_TYPES: &[&str] = &[
+    "image/bmp",
+    "image/gif",
+    "image/jpeg",
+    "image/jpg",
+    "image/png",
+    "image/svg+xml",
+    "image/tiff",
+    "image/vnd.microsoft.icon",
+    "image/webp",
+    "image/x-icon",
+];
+
+#[derive(Debug, PartialEq)]
+struct ImageCandidate {
+    url: String,
+    size: CandidateSize,
+}
+
+#[derive(Debug, PartialEq)]
+enum CandidateSize {
+    /// The image file's width in pixels, like `800w`
+    Width(u32),
+    /// How many image pixels there are per CSS pixel, like `2x`
+    Density(f32),
+}
+
+/// Picks the URL to load for an `<img>`, out of all the ones it offers. Inside a `<picture>`,
+/// the first `<source>` we can use wins over the image's own.
+pub fn choose_image_url(node_image: &Handle, node_picture: Option<&Handle>) -> Option<String> {
+    // Laid out this many CSS pixels wide, if the page says
+    let declared_width = get_attribute(node_image, "width")
+        .and_then(|width| width.trim().trim_end_matches("px").parse::<f32>().ok())
+        .filter(|width| *width > 0.0);
+
+    let candidates = node_picture
+        .and_then(find_picture_source_candidates)
+        .unwrap_or_else(|| find_image_candidates(node_image));
+
+    choose_candidate(candidates, declared_width)
+}
+
+fn find_picture_source_candidates(node_picture: &Handle) -> Option<Vec<ImageCandidate>> {
+    node_picture
+        .children
+        .borrow()
+        .iter()
+        .filter(|node_child| element_name(node_child).as_deref() == Some("source"))
+        .filter(|node_source| {
+            get_attribute(node_source, "type").is_none_or(|mime_type| {
+                SUPPORTED_TYPES.contains(&mime_type.trim().to_ascii_lowercase().as_str())
+            })
+        })
+        .filter(|node_source| {
+            get_attribute(node_source, "media").is_none_or(|media| matches_media_query(&media))
+        })
+        .map(|node_source| {
+            first_attribute(node_source, SRCSET_ATTRIBUTES)
+                .map(|srcset| parse_srcset(&srcset))
+                .unwrap_or_default()
+        })
+        .find(|candidates| !candidates.is_empty())
+}
+
+fn find_image_candidates(node_image: &Handle) -> Vec<ImageCandidate> {
+    let mut candidates = first_attribute(node_image, SRCSET_ATTRIBUTES)
+        .map(|srcset| parse_srcset(&srcset))
+        .unwrap_or_default();
+
+    // The plain source counts as the 1x version, unless the set already has one or is
+    // described by widths
+    let has_default = candidates.iter().any(|candidate| match candidate.size {
+        CandidateSize::Width(_) => true,
+        CandidateSize::Density(density) => density == 1.0,
+    });
+    if !has_default {
+        if let Some(src) = first_attribute(node_image, SRC_ATTRIBUTES) {
+            candidates.push(ImageCandidate {
+                url: src,
+                size: CandidateSize::Density(1.0),
+            });
+        }
+    }
+
+    candidates
+}
+
+/// The value of the first of the attributes the node has, skipping empty ones
+fn first_attribute(node: &Handle, attribute_names: &[&str]) -> Option<String> {
+    attribute_names.iter().find_map(|attribute_name| {
+        get_attribute(node, attribute_name)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    })
+}
+
+/// Picks the smallest candidate that fills the width it will be shown at, or the biggest
+/// one if none do
+fn choose_candidate(
+    candidates: Vec<ImageCandidate>,
+    declared_width: Option<f32>,
+) -> Option<String> {
+    let layout_width = declared_width.unwrap_or(VIEWPORT_WIDTH).min(VIEWPORT_WIDTH);
+    let target_width = layout_width * DISPLAY_SCALE;
+
+    let pixel_width = |candidate: &ImageCandidate| match candidate.size {
+        CandidateSize::Width(width) => width as f32,
+        CandidateSize::Density(density) => layout_width * density,
+    };
+
+    let big_enough = candidates
+        .iter()
+        .filter(|candidate| pixel_width(candidate) >= target_width)
+        .min_by(|a, b| pixel_width(a).total_cmp(&pixel_width(b)));
+    let biggest = || {
+        candidates
+            .iter()
+            .rev()
+            .max_by(|a, b| pixel_width(a).total_cmp(&pixel_width(b)))
+    };
+
+    big_enough
+        .or_else(biggest)
+        .map(|candidate| candidate.url.clone())
+}
+
+/// Reads the candidates in a `srcset`, like "small.jpg 480w, large.jpg 1080w". Candidates
+/// with descriptors we don't understand are skipped.
+fn parse_srcset(srcset: &str) -> Vec<ImageCandidate> {
+    let mut candidates = vec![];
+    let mut rest = srcset;
+
+    loop {
+        rest = rest.trim_start_matches(|character: char| {
+            character.is_ascii_whitespace() || character == ','
+        });
+        if rest.is_empty() {
+            break;
+        }
+
+        // URLs can hold commas themselves, so only whitespace ends one
+        let url_end = rest
+            .find(|character: char| character.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        let url = &rest[..url_end];
+        rest = &rest[url_end..];
+
+        let (url, descriptor) = if url.ends_with(',') {
+            (url.trim_end_matches(','), "")
+        } else {
+            let descriptor_end = rest.find(',').unwrap_or(rest.len());
+            let descriptor = &rest[..descriptor_end];
+            rest = &rest[descriptor_end..];
+            (url, descriptor)
+        };
+
+        if let Some(size) = parse_descriptor(descriptor) {
+            candidates.push(ImageCandidate {
+                url: url.to_string(),
+                size,
+            });
+        }
+    }
+
+    candidates
+}
+
+fn parse_descriptor(descriptor: &str) -> Option<CandidateSize> {
+    // A height descriptor can come along with a width, but doesn't help pick one
+    let size_descriptor = descriptor
+        .split_ascii_whitespace()
+        .find(|part| !part.ends_with('h'));
+
+    match size_descriptor {
+        None => Some(CandidateSize::Density(1.0)),
+        Some(part) => {
+            if let Some(width) = part.strip_suffix('w') {
+                width
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|width| *width > 0)
+                    .map(CandidateSize::Width)
+            } else if let Some(density) = part.strip_suffix('x') {
+                density
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|density| *density > 0.0)
+                    .map(CandidateSize::Density)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Checks a `<source>`'s media query against the canvas. Only the usual conditions about the
+/// screen's size, orientation and color scheme are understood; sources with any others are
+/// skipped.
+fn matches_media_query(media: &str) -> bool {
+    let media = media.trim().to_ascii_lowercase();
+
+    // Any of a comma separated list can match
+    media.split(',').any(|query| {
+        query
+            .split(" and ")
+            .map(str::trim)
+            .all(|condition| match condition {
+                "" | "all" | "screen" | "only screen" => true,
+                _ => matches_media_condition(condition),
+            })
+    })
+}
+
+fn matches_media_condition(condition: &str) -> bool {
+    let Some((feature, value)) = condition
+        .strip_prefix('(')
+        .and_then(|condition| condition.strip_suffix(')'))
+        .and_then(|condition| condition.split_once(':'))
+    else {
+        return false;
+    };
+
+    match (feature.trim(), value.trim()) {
+        ("min-width", length) => {
+            parse_media_length(length).is_some_and(|min| VIEWPORT_WIDTH >= min)
+        }
+        ("max-width", length) => {
+            parse_media_length(length).is_some_and(|max| VIEWPORT_WIDTH <= max)
+        }
+        ("orientation", orientation) => orientation == "portrait",
+        ("prefers-color-scheme", scheme) => scheme == "light",
+        _ => false,
+    }
+}
+
+fn parse_media_length(length: &str) -> Option<f32> {
+    if let Some(pixels) = length.strip_suffix("px") {
+        pixels.trim().parse().ok()
+    } else if let Some(ems) = length
+        .strip_suffix("rem")
+        .or_else(|| length.strip_suffix("em"))
+    {
+        ems.trim()
+            .parse::<f32>()
+            .ok()
+            .map(|ems| ems * MEDIA_QUERY_EM)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use html5ever::parse_document;
+    use html5ever::tendril::TendrilSink;
+    use markup5ever_rcdom::RcDom;
+
+    use super::*;
+    use crate::browser_core::parsing::find_element;
+
+    fn choose_in_html(html: &str) -> Option<String> {
+        let dom = parse_document(RcDom::default(), Default::default()).one(html);
+        let node_image = find_element(&dom.document, "img").unwrap();
+        let node_picture = find_element(&dom.document, "picture");
+
+        choose_image_url(&node_image, node_picture.as_ref())
+    }
+
+    #[test]
+    fn test_parse_srcset() {
+        let candidate = |url: &str, size| ImageCandidate {
+            url: url.to_string(),
+            size,
+        };
+
+        assert_eq!(
+            parse_srcset("small.jpg 480w, large.jpg 1080w 720h,huge.jpg"),
+            vec![
+                candidate("small.jpg", CandidateSize::Width(480)),
+                candidate("large.jpg", CandidateSize::Width(1080)),
+                candidate("huge.jpg", CandidateSize::Density(1.0)),
+            ]
+        );
+        assert_eq!(
+            parse_srcset("/c_fill,w_400/cat.jpg 1x, /c_fill,w_800/cat.jpg 2x, bad.jpg 3q"),
+            vec![
+                candidate("/c_fill,w_400/cat.jpg", CandidateSize::Density(1.0)),
+                candidate("/c_fill,w_800/cat.jpg", CandidateSize::Density(2.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_choose_from_srcset() {
+        assert_eq!(
+            choose_in_html(
+                r#"<img src="fallback.jpg"
+                    srcset="cat-480.jpg 480w, cat-1600.jpg 1600w, cat-3000.jpg 3000w">"#
+            ),
+            Some("cat-1600.jpg".to_string())
+        );
+        assert_eq!(
+            choose_in_html(r#"<img src="cat.jpg" srcset="cat-2x.jpg 2x, cat-3x.jpg 3x">"#),
+            Some("cat-2x.jpg".to_string())
+        );
+        assert_eq!(
+            choose_in_html(r#"<img src="icon.png" width="32" srcset="icon-4x.png 4x">"#),
+            Some("icon-4x.png".to_string())
+        );
+        assert_eq!(
+            choose_in_html(r#"<img srcset="tiny.jpg 200w, small.jpg 400w">"#),
+            Some("small.jpg".to_string())
+        );
+    }
+
+    #[test]
+    fn test_choose_lazy_loaded() {
+        assert_eq!(
+            choose_in_html(
+                r#"<img src="data:image/gif;base64,R0lGODlhAQABAAAAACw=" data-src="cat.jpg">"#
+            ),
+            Some("cat.jpg".to_string())
+        );
+        assert_eq!(
+            choose_in_html(
+                r#"<img src="placeholder.gif" data-srcset="cat-800.jpg 800w, cat-1600.jpg 1600w">"#
+            ),
+            Some("cat-1600.jpg".to_string())
+        );
+    }
+
+    #[test]
+    fn test_choose_from_picture() {
+        assert_eq!(
+            choose_in_html(
+                r#"<picture>
+                    <source type="image/avif" srcset="cat.avif">
+                    <source media="(min-width: 1200px)" srcset="cat-wide.webp">
+                    <source media="(prefers-color-scheme: dark)" srcset="cat-dark.webp">
+                    <source type="image/webp" srcset="cat-800.webp 800w, cat-1400.webp 1400w">
+                    <img src="cat.jpg">
+                </picture>"#
+            ),
+            Some("cat-1400.webp".to_string())
+        );
+        assert_eq!(
+            choose_in_html(
+                r#"<picture>
+                    <source type="image/avif" srcset="cat.avif">
+                    <img src="cat.jpg">
+                </picture>"#
+            ),
+            Some("cat.jpg".to_string())
+        );
+    }
+
+    #[test]
+    fn test_matches_media_query() {
+        assert!(matches_media_query("(max-width: 800px)"));
+        assert!(matches_media_query("screen and (min-width: 40em)"));
+        assert!(matches_media_query(
+            "(min-width: 1200px), (orientation: portrait)"
+        ));
+        assert!(!matches_media_query("(min-width: 1024px)"));
+        assert!(!matches_media_query("print"));
+        assert!(!matches_media_query("(hover: hover)"));
+    }
+}
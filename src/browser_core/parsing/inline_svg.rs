@@ -0,0 +1,117 @@
+//! Inline `<svg>` elements, like diagrams and icons drawn right in the page. They're written
+//! back out as SVG files in `data:` URIs, so they load and render like any other image.
+
+use markup5ever_rcdom::{Handle, NodeData};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use super::{collapse_whitespace, collect_text, element_name, get_attribute, Span};
+
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+/// Elements that draw something. Icons that only `<use>` a symbol from elsewhere in the page
+/// have none of their own, and would come out blank.
+const DRAWING_ELEMENTS: &[&str] = &[
+    "circle", "ellipse", "image", "line", "path", "polygon", "polyline", "rect", "text",
+];
+
+pub fn parse_inline_svg(node_svg: &Handle) -> Option<Span> {
+    if !has_drawing(node_svg) {
+        return None;
+    }
+
+    let mut svg = String::new();
+    write_svg_node(node_svg, true, &mut svg);
+
+    let alt_text = get_attribute(node_svg, "aria-label")
+        .or_else(|| find_title(node_svg))
+        .map(|alt_text| collapse_whitespace(&alt_text).trim().to_string())
+        .filter(|alt_text| !alt_text.is_empty());
+
+    Some(Span::Image {
+        url: format!(
+            "data:image/svg+xml,{}",
+            utf8_percent_encode(&svg, NON_ALPHANUMERIC)
+        ),
+        alt_text,
+    })
+}
+
+fn has_drawing(node: &Handle) -> bool {
+    node.children.borrow().iter().any(|node_child| {
+        element_name(node_child)
+            .is_some_and(|tag_name| DRAWING_ELEMENTS.contains(&tag_name.as_str()))
+            || has_drawing(node_child)
+    })
+}
+
+fn find_title(node_svg: &Handle) -> Option<String> {
+    let node_title = node_svg
+        .children
+        .borrow()
+        .iter()
+        .find(|node_child| element_name(node_child).as_deref() == Some("title"))
+        .cloned()?;
+
+    let mut title = String::new();
+    collect_text(&node_title, &mut title);
+
+    Some(title)
+}
+
+/// Writes the element out as XML. The HTML parser already dropped the namespace declarations
+/// an SVG file needs, so the root gets them back.
+fn write_svg_node(node: &Handle, is_root: bool, svg: &mut String) {
+    match &node.data {
+        NodeData::Element { name, attrs, .. } => {
+            svg.push('<');
+            svg.push_str(&name.local);
+            if is_root {
+                svg.push_str(&format!(
+                    r#" xmlns="{}" xmlns:xlink="{}""#,
+                    SVG_NAMESPACE, XLINK_NAMESPACE
+                ));
+            }
+
+            for attr in attrs.borrow().iter() {
+                let prefix = attr.name.prefix.as_deref();
+                if prefix == Some("xmlns") || &*attr.name.local == "xmlns" {
+                    continue;
+                }
+
+                svg.push(' ');
+                if let Some(prefix) = prefix {
+                    svg.push_str(prefix);
+                    svg.push(':');
+                }
+                svg.push_str(&attr.name.local);
+                svg.push_str("=\"");
+                push_escaped(&attr.value, svg);
+                svg.push('"');
+            }
+            svg.push('>');
+
+            for node_child in node.children.borrow().iter() {
+                write_svg_node(node_child, false, svg);
+            }
+
+            svg.push_str("</");
+            svg.push_str(&name.local);
+            svg.push('>');
+        }
+        NodeData::Text { contents } => push_escaped(&contents.borrow(), svg),
+        _ => {}
+    }
+}
+
+fn push_escaped(text: &str, svg: &mut String) {
+    for character in text.chars() {
+        match character {
+            '&' => svg.push_str("&amp;"),
+            '<' => svg.push_str("&lt;"),
+            '>' => svg.push_str("&gt;"),
+            '"' => svg.push_str("&quot;"),
+            _ => svg.push(character),
+        }
+    }
+}
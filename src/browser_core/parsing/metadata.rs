@@ -0,0 +1,356 @@
+//! Information about a page as a whole, rather than its content: the title, who wrote it
+//! and when. Sites describe this in several overlapping ways, so each field is taken from
+//! the most specific source that has it: JSON-LD first, then `<meta>` tags (including
+//! OpenGraph), then plain HTML like `<title>`.
+
+use log::warn;
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{
+    collapse_whitespace, collect_text, element_name, find_element, get_attribute, DocumentMetadata,
+};
+
+/// JSON-LD types that describe the page's main content
+const ARTICLE_TYPES: &[&str] = &[
+    "Article",
+    "BlogPosting",
+    "NewsArticle",
+    "Report",
+    "ScholarlyArticle",
+    "TechArticle",
+];
+
+pub fn parse_metadata(dom: &RcDom) -> DocumentMetadata {
+    let meta_tags = collect_meta_tags(&dom.document);
+    let meta = |names: &[&str]| names.iter().find_map(|name| meta_tags.get(*name).cloned());
+
+    let json_ld = find_json_ld_article(&dom.document);
+    let json_ld_field = |field: &str| json_ld.as_ref().and_then(|article| article.get(field));
+
+    let title = json_ld_field("headline")
+        .and_then(value_text)
+        .or_else(|| meta(&["og:title", "twitter:title"]))
+        .or_else(|| find_title(&dom.document));
+
+    let byline = json_ld_field("author")
+        .and_then(value_names)
+        .or_else(|| meta(&["author", "dc.creator"]))
+        .or_else(|| meta(&["article:author"]).filter(|author| !author.starts_with("http")));
+
+    let published = json_ld_field("datePublished")
+        .and_then(value_text)
+        .or_else(|| meta(&["article:published_time", "date", "dc.date"]));
+
+    let site_name = meta(&["og:site_name"])
+        .or_else(|| json_ld_field("publisher").and_then(value_names))
+        .or_else(|| meta(&["application-name"]));
+
+    let description = meta(&["og:description", "description", "twitter:description"])
+        .or_else(|| json_ld_field("description").and_then(value_text));
+
+    let language = find_element(&dom.document, "html")
+        .and_then(|node_html| get_attribute(&node_html, "lang"))
+        .and_then(non_empty)
+        .or_else(|| meta(&["og:locale"]).map(|locale| locale.replace('_', "-")))
+        .or_else(|| json_ld_field("inLanguage").and_then(value_text));
+
+    let canonical_url = find_canonical_link(&dom.document).or_else(|| meta(&["og:url"]));
+
+    let lead_image_url = meta(&["og:image", "og:image:url", "twitter:image"])
+        .or_else(|| json_ld_field("image").and_then(value_url));
+
+    let base_href = find_base_href(&dom.document);
+
+    DocumentMetadata {
+        title,
+        byline,
+        published,
+        site_name,
+        description,
+        language,
+        canonical_url,
+        lead_image_url,
+        base_href,
+    }
+}
+
+/// Collects the `content` of every `<meta>` tag, keyed by its `property` or `name`. When
+/// a tag appears more than once, the first one wins.
+fn collect_meta_tags(node_document: &Handle) -> HashMap<String, String> {
+    let mut meta_tags = HashMap::new();
+
+    let mut nodes_meta = vec![];
+    find_elements(node_document, "meta", &mut nodes_meta);
+
+    for node_meta in nodes_meta {
+        let Some(key) = get_attribute(&node_meta, "property")
+            .or_else(|| get_attribute(&node_meta, "name"))
+            .or_else(|| get_attribute(&node_meta, "itemprop"))
+        else {
+            continue;
+        };
+        let Some(content) = get_attribute(&node_meta, "content").and_then(non_empty) else {
+            continue;
+        };
+
+        meta_tags
+            .entry(key.trim().to_lowercase())
+            .or_insert(content);
+    }
+
+    meta_tags
+}
+
+fn find_title(node_document: &Handle) -> Option<String> {
+    let node_title = find_element(node_document, "title")?;
+
+    let mut text = String::new();
+    collect_text(&node_title, &mut text);
+
+    non_empty(text)
+}
+
+fn find_canonical_link(node_document: &Handle) -> Option<String> {
+    let mut nodes_link = vec![];
+    find_elements(node_document, "link", &mut nodes_link);
+
+    nodes_link
+        .iter()
+        .find(|node_link| {
+            get_attribute(node_link, "rel").is_some_and(|rel| {
+                rel.split_whitespace()
+                    .any(|rel| rel.eq_ignore_ascii_case("canonical"))
+            })
+        })
+        .and_then(|node_link| get_attribute(node_link, "href"))
+        .and_then(non_empty)
+}
+
+/// Only the first `<base>` with an `href` counts, like in browsers
+fn find_base_href(node_document: &Handle) -> Option<String> {
+    let mut nodes_base = vec![];
+    find_elements(node_document, "base", &mut nodes_base);
+
+    nodes_base
+        .iter()
+        .find_map(|node_base| get_attribute(node_base, "href"))
+        .and_then(non_empty)
+}
+
+/// Finds the JSON-LD object describing an article, looking inside arrays and `@graph`
+/// lists, since sites often describe the page, the site and the article side by side
+fn find_json_ld_article(node_document: &Handle) -> Option<Value> {
+    let mut nodes_script = vec![];
+    find_elements(node_document, "script", &mut nodes_script);
+
+    let mut objects = vec![];
+    for node_script in nodes_script {
+        if get_attribute(&node_script, "type").as_deref() != Some("application/ld+json") {
+            continue;
+        }
+
+        let mut json = String::new();
+        collect_text(&node_script, &mut json);
+
+        match serde_json::from_str::<Value>(&json) {
+            Ok(value) => flatten_json_ld(value, &mut objects),
+            Err(err) => warn!("Failed to parse JSON-LD: {}", err),
+        }
+    }
+
+    let is_article = |object: &&Value| {
+        object
+            .get("@type")
+            .is_some_and(|object_type| match object_type {
+                Value::String(object_type) => ARTICLE_TYPES.contains(&object_type.as_str()),
+                Value::Array(object_types) => object_types.iter().any(|object_type| {
+                    object_type
+                        .as_str()
+                        .is_some_and(|object_type| ARTICLE_TYPES.contains(&object_type))
+                }),
+                _ => false,
+            })
+    };
+
+    objects
+        .iter()
+        .find(is_article)
+        .or_else(|| {
+            objects
+                .iter()
+                .find(|object| object.get("headline").is_some())
+        })
+        .cloned()
+}
+
+fn flatten_json_ld(value: Value, objects: &mut Vec<Value>) {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                flatten_json_ld(value, objects);
+            }
+        }
+        Value::Object(mut object) => {
+            if let Some(graph) = object.remove("@graph") {
+                flatten_json_ld(graph, objects);
+            }
+            objects.push(Value::Object(object));
+        }
+        _ => {}
+    }
+}
+
+fn value_text(value: &Value) -> Option<String> {
+    value
+        .as_str()
+        .map(|text| text.to_string())
+        .and_then(non_empty)
+}
+
+/// The names of people or organizations, which can be plain strings, objects with a
+/// `name`, or a list of either
+fn value_names(value: &Value) -> Option<String> {
+    match value {
+        Value::String(name) => non_empty(name.clone()),
+        Value::Object(object) => object.get("name").and_then(value_text),
+        Value::Array(values) => {
+            let names: Vec<String> = values.iter().filter_map(value_names).collect();
+            non_empty(names.join(", "))
+        }
+        _ => None,
+    }
+}
+
+/// Image URLs, which can be plain strings, `ImageObject`s or a list of either
+fn value_url(value: &Value) -> Option<String> {
+    match value {
+        Value::String(url) => non_empty(url.clone()),
+        Value::Object(object) => object.get("url").and_then(value_text),
+        Value::Array(values) => values.iter().find_map(value_url),
+        _ => None,
+    }
+}
+
+fn find_elements(node: &Handle, tag_name: &str, nodes: &mut Vec<Handle>) {
+    if element_name(node).as_deref() == Some(tag_name) {
+        nodes.push(node.clone());
+    }
+
+    for node_child in node.children.borrow().iter() {
+        if let NodeData::Element { .. } | NodeData::Document = node_child.data {
+            find_elements(node_child, tag_name, nodes);
+        }
+    }
+}
+
+fn non_empty(text: String) -> Option<String> {
+    let text = collapse_whitespace(&text).trim().to_string();
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use html5ever::parse_document;
+    use html5ever::tendril::TendrilSink;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn parse_html_metadata(html: &str) -> DocumentMetadata {
+        let dom = parse_document(RcDom::default(), Default::default()).one(html);
+
+        parse_metadata(&dom)
+    }
+
+    #[test]
+    fn test_html_and_meta_tags() {
+        let metadata = parse_html_metadata(
+            r#"<!doctype html>
+            <html lang="de">
+            <head>
+                <title>  Ein Artikel |
+                    Beispiel  </title>
+                <meta name="author" content="Erika Mustermann">
+                <meta property="og:site_name" content="Beispiel">
+                <meta property="og:image" content="https://example.com/lead.jpg">
+                <meta name="description" content="">
+                <meta property="article:published_time" content="2024-02-29">
+                <link rel="stylesheet" href="/style.css">
+                <link rel="canonical" href="https://example.com/artikel">
+            </head>
+            <body></body>
+            </html>"#,
+        );
+
+        assert_eq!(
+            metadata,
+            DocumentMetadata {
+                title: Some("Ein Artikel | Beispiel".to_string()),
+                byline: Some("Erika Mustermann".to_string()),
+                published: Some("2024-02-29".to_string()),
+                site_name: Some("Beispiel".to_string()),
+                description: None,
+                language: Some("de".to_string()),
+                canonical_url: Some("https://example.com/artikel".to_string()),
+                lead_image_url: Some("https://example.com/lead.jpg".to_string()),
+                base_href: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_base_href() {
+        let metadata = parse_html_metadata(
+            r#"<html><head>
+            <base target="_blank">
+            <base href=" /docs/v2/ ">
+            <base href="/ignored/">
+            </head><body></body></html>"#,
+        );
+
+        assert_eq!(metadata.base_href, Some("/docs/v2/".to_string()));
+    }
+
+    #[test]
+    fn test_json_ld_takes_priority() {
+        let metadata = parse_html_metadata(
+            r#"<!doctype html>
+            <html>
+            <head>
+                <title>Headline - Site</title>
+                <meta property="og:title" content="Headline">
+                <meta property="og:locale" content="en_GB">
+                <meta name="author" content="Site Staff">
+                <script type="application/ld+json">{ not json }</script>
+                <script type="application/ld+json">
+                    [
+                        { "@type": "Organization", "name": "Site" },
+                        {
+                            "@type": ["NewsArticle"],
+                            "headline": "The Real Headline",
+                            "author": [{ "name": "Ann" }, "Bob"],
+                            "image": { "@type": "ImageObject", "url": "/lead.png" },
+                            "publisher": { "name": "Site Inc." }
+                        }
+                    ]
+                </script>
+            </head>
+            <body></body>
+            </html>"#,
+        );
+
+        assert_eq!(metadata.title, Some("The Real Headline".to_string()));
+        assert_eq!(metadata.byline, Some("Ann, Bob".to_string()));
+        assert_eq!(metadata.site_name, Some("Site Inc.".to_string()));
+        assert_eq!(metadata.language, Some("en-GB".to_string()));
+        assert_eq!(metadata.lead_image_url, Some("/lead.png".to_string()));
+        assert_eq!(metadata.published, None);
+    }
+}
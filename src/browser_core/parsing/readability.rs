@@ -0,0 +1,447 @@
+//! Reading mode: finds the main article on a page and leaves out the site around it, like
+//! navigation bars, cookie banners, share buttons and comment sections.
+//!
+//! This is a simplified version of the scoring in Mozilla's Readability. Paragraphs score
+//! points for their length and commas, and hand those points to the elements around them.
+//! The best-scoring element (and any siblings that score nearly as well) is the article.
+
+use log::{debug, info};
+use markup5ever_rcdom::{Handle, NodeData};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::{collapse_whitespace, contains_block, element_name, get_attribute, is_skipped};
+
+/// Elements that are never part of the article
+const UNLIKELY_ELEMENTS: &[&str] = &["nav", "aside", "footer", "form", "dialog"];
+
+/// ARIA roles of elements that are never part of the article
+const UNLIKELY_ROLES: &[&str] = &[
+    "navigation",
+    "banner",
+    "contentinfo",
+    "complementary",
+    "menu",
+    "menubar",
+    "alert",
+    "alertdialog",
+    "dialog",
+];
+
+/// Class and id fragments of elements that are probably site chrome...
+const UNLIKELY_CANDIDATES: &[&str] = &[
+    "-ad-",
+    "banner",
+    "breadcrumb",
+    "comment",
+    "community",
+    "consent",
+    "cookie",
+    "disqus",
+    "footer",
+    "gdpr",
+    "header",
+    "menu",
+    "modal",
+    "newsletter",
+    "pagination",
+    "pager",
+    "popup",
+    "related",
+    "replies",
+    "share",
+    "sidebar",
+    "social",
+    "sponsor",
+    "subscribe",
+];
+
+/// ...unless they also have one of these, like `article-header`
+const MAYBE_CANDIDATES: &[&str] = &["article", "body", "column", "content", "main", "story"];
+
+/// Class and id fragments that make an element more likely to be the article
+const POSITIVE_HINTS: &[&str] = &[
+    "article", "blog", "body", "content", "entry", "main", "page", "post", "story", "text",
+];
+
+/// Class and id fragments that make an element less likely to be the article
+const NEGATIVE_HINTS: &[&str] = &[
+    "banner", "comment", "contact", "foot", "hidden", "masthead", "media", "meta", "promo",
+    "related", "scroll", "share", "shopping", "sidebar", "sponsor", "tags", "tool", "widget",
+];
+
+/// Elements whose text counts as a paragraph when scoring
+const SCORED_ELEMENTS: &[&str] = &["p", "pre", "td"];
+
+/// Containers only count as a paragraph when they hold nothing but inline content
+const SCORED_CONTAINERS: &[&str] = &["div", "section", "article"];
+
+/// Parts of a table pass points on, but are never the article themselves. Cells of layout
+/// tables still can be.
+const NON_CANDIDATES: &[&str] = &["table", "thead", "tbody", "tfoot", "tr"];
+
+/// Paragraphs shorter than this are captions, bylines and buttons, not content
+const MIN_PARAGRAPH_LENGTH: usize = 25;
+
+/// Less text than this, and we probably picked the wrong element
+const MIN_ARTICLE_LENGTH: usize = 140;
+
+/// Elements inside the article that are dropped when they're mostly links, like a list of
+/// share buttons or related articles. Lists of links into the article itself, like its
+/// table of contents, are kept.
+const LINK_LIST_ELEMENTS: &[&str] = &["div", "section", "ul", "ol", "table"];
+const MAX_LINK_LIST_DENSITY: f32 = 0.5;
+
+/// Finds the elements that make up the main article, in document order. The body is
+/// changed along the way, so it should not be parsed again if this gives up.
+pub fn extract_article(node_body: &Handle) -> Option<Vec<Handle>> {
+    remove_unlikely_nodes(node_body);
+
+    let scores = score_candidates(node_body);
+    let node_top = find_top_candidate(&scores)?;
+    let top_score = get_score(&scores, &node_top);
+    debug!(
+        "Top candidate is <{}> with a score of {:.1}",
+        element_name(&node_top).unwrap_or_default(),
+        top_score
+    );
+
+    // A candidate that is the only thing in its parent is as good as the parent
+    let mut node_top = node_top;
+    while let Some(node_parent) = get_parent(&node_top) {
+        if Rc::ptr_eq(&node_parent, node_body) || element_children(&node_parent).len() != 1 {
+            break;
+        }
+        node_top = node_parent;
+    }
+
+    let nodes = match get_parent(&node_top) {
+        Some(node_parent) => find_article_siblings(&node_parent, &node_top, top_score, &scores),
+        None => vec![node_top],
+    };
+
+    for node in nodes.iter() {
+        remove_link_lists(node);
+    }
+
+    let article_length: usize = nodes.iter().map(text_length).sum();
+    if article_length < MIN_ARTICLE_LENGTH {
+        info!(
+            "Article is too short ({} characters), so it's probably not the article",
+            article_length
+        );
+        return None;
+    }
+
+    Some(nodes)
+}
+
+/// Identifies a node in the DOM, so it can be a key in a map
+#[derive(PartialEq, Eq, Hash)]
+struct NodeKey(*const markup5ever_rcdom::Node);
+
+/// The candidates that were given points, and their scores
+type Scores = HashMap<NodeKey, (Handle, f32)>;
+
+fn get_score(scores: &Scores, node: &Handle) -> f32 {
+    scores
+        .get(&NodeKey(Rc::as_ptr(node)))
+        .map_or(0.0, |(_, score)| *score)
+}
+
+fn remove_unlikely_nodes(node: &Handle) {
+    node.children.borrow_mut().retain(|node_child| {
+        if is_unlikely(node_child) {
+            debug!(
+                "Removing unlikely <{}>",
+                element_name(node_child).unwrap_or_default()
+            );
+            false
+        } else {
+            true
+        }
+    });
+
+    for node_child in node.children.borrow().iter() {
+        remove_unlikely_nodes(node_child);
+    }
+}
+
+fn is_unlikely(node: &Handle) -> bool {
+    let Some(tag_name) = element_name(node) else {
+        return false;
+    };
+
+    if matches!(tag_name.as_str(), "body" | "article" | "main" | "a") {
+        return false;
+    }
+
+    if UNLIKELY_ELEMENTS.contains(&tag_name.as_str()) || is_hidden(node) {
+        return true;
+    }
+
+    if let Some(role) = get_attribute(node, "role") {
+        if UNLIKELY_ROLES.contains(&role.trim()) {
+            return true;
+        }
+    }
+
+    let hints = class_and_id(node);
+    UNLIKELY_CANDIDATES
+        .iter()
+        .any(|candidate| hints.contains(candidate))
+        && !MAYBE_CANDIDATES
+            .iter()
+            .any(|candidate| hints.contains(candidate))
+}
+
+fn is_hidden(node: &Handle) -> bool {
+    if get_attribute(node, "hidden").is_some()
+        || get_attribute(node, "aria-hidden").as_deref() == Some("true")
+    {
+        return true;
+    }
+
+    let style = get_attribute(node, "style")
+        .unwrap_or_default()
+        .to_lowercase()
+        .replace(' ', "");
+    style.contains("display:none") || style.contains("visibility:hidden")
+}
+
+/// Gives every paragraph-like element points, and shares them out with its ancestors
+fn score_candidates(node_body: &Handle) -> Scores {
+    let mut scores = Scores::new();
+
+    let mut paragraphs = vec![];
+    find_paragraphs(node_body, &mut paragraphs);
+
+    for node_paragraph in paragraphs {
+        let text = inner_text(&node_paragraph);
+        let length = text.chars().count();
+        if length < MIN_PARAGRAPH_LENGTH {
+            continue;
+        }
+
+        let score = 1.0 + text.matches(',').count() as f32 + (length / 100).min(3) as f32;
+
+        let mut node_ancestor = get_parent(&node_paragraph);
+        let mut level = 0;
+        while let Some(node) = node_ancestor {
+            if element_name(&node).is_none() || level >= 5 {
+                break;
+            }
+
+            let divider = match level {
+                0 => 1.0,
+                1 => 2.0,
+                _ => level as f32 * 3.0,
+            };
+            let entry = scores
+                .entry(NodeKey(Rc::as_ptr(&node)))
+                .or_insert_with(|| (node.clone(), initial_score(&node)));
+            entry.1 += score / divider;
+
+            node_ancestor = get_parent(&node);
+            level += 1;
+        }
+    }
+
+    // Links are usually somewhere else, not the article
+    for (node, score) in scores.values_mut() {
+        *score *= 1.0 - link_density(node);
+    }
+
+    scores
+}
+
+fn find_paragraphs(node: &Handle, paragraphs: &mut Vec<Handle>) {
+    if let Some(tag_name) = element_name(node) {
+        if SCORED_ELEMENTS.contains(&tag_name.as_str())
+            || (SCORED_CONTAINERS.contains(&tag_name.as_str()) && !contains_block(node))
+        {
+            paragraphs.push(node.clone());
+            return;
+        }
+    }
+
+    for node_child in node.children.borrow().iter() {
+        find_paragraphs(node_child, paragraphs);
+    }
+}
+
+fn initial_score(node: &Handle) -> f32 {
+    let tag_score = match element_name(node).as_deref() {
+        Some("div" | "article" | "main" | "section") => 5.0,
+        Some("pre" | "td" | "blockquote") => 3.0,
+        Some("address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li") => -3.0,
+        Some("h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th") => -5.0,
+        _ => 0.0,
+    };
+
+    tag_score + class_weight(node)
+}
+
+fn class_weight(node: &Handle) -> f32 {
+    let hints = class_and_id(node);
+    let mut weight = 0.0;
+
+    if POSITIVE_HINTS.iter().any(|hint| hints.contains(hint)) {
+        weight += 25.0;
+    }
+    if NEGATIVE_HINTS.iter().any(|hint| hints.contains(hint)) {
+        weight -= 25.0;
+    }
+
+    weight
+}
+
+fn find_top_candidate(scores: &Scores) -> Option<Handle> {
+    scores
+        .values()
+        .filter(|(node, score)| {
+            *score > 0.0
+                && !NON_CANDIDATES.contains(&element_name(node).unwrap_or_default().as_str())
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(node, _)| node.clone())
+}
+
+/// Articles are often split across several containers, so siblings of the best candidate
+/// that score nearly as well come along with it
+fn find_article_siblings(
+    node_parent: &Handle,
+    node_top: &Handle,
+    top_score: f32,
+    scores: &Scores,
+) -> Vec<Handle> {
+    let threshold = (top_score * 0.2).max(10.0);
+    let top_class = get_attribute(node_top, "class").filter(|class| !class.is_empty());
+
+    element_children(node_parent)
+        .into_iter()
+        .filter(|node_sibling| {
+            if Rc::ptr_eq(node_sibling, node_top) {
+                return true;
+            }
+
+            let mut score = get_score(scores, node_sibling);
+            if top_class.is_some() && get_attribute(node_sibling, "class") == top_class {
+                score += top_score * 0.2;
+            }
+            if score >= threshold {
+                return true;
+            }
+
+            element_name(node_sibling).as_deref() == Some("p")
+                && text_length(node_sibling) > 80
+                && link_density(node_sibling) < 0.25
+        })
+        .collect()
+}
+
+fn remove_link_lists(node: &Handle) {
+    node.children.borrow_mut().retain(|node_child| {
+        let is_link_list = element_name(node_child)
+            .is_some_and(|tag_name| LINK_LIST_ELEMENTS.contains(&tag_name.as_str()))
+            && (class_weight(node_child) < 0.0 || link_density(node_child) > MAX_LINK_LIST_DENSITY)
+            && !links_within_page(node_child);
+
+        if is_link_list {
+            debug!(
+                "Removing link list <{}>",
+                element_name(node_child).unwrap_or_default()
+            );
+        }
+
+        !is_link_list
+    });
+
+    for node_child in node.children.borrow().iter() {
+        remove_link_lists(node_child);
+    }
+}
+
+/// How much of the text inside a node is link text, from 0 to 1
+fn link_density(node: &Handle) -> f32 {
+    let length = text_length(node);
+    if length == 0 {
+        return 0.0;
+    }
+
+    let mut links = vec![];
+    find_links(node, &mut links);
+    let link_length: usize = links.iter().map(text_length).sum();
+
+    link_length as f32 / length as f32
+}
+
+/// Whether the node has links, and they all jump somewhere else on the same page
+fn links_within_page(node: &Handle) -> bool {
+    let mut links = vec![];
+    find_links(node, &mut links);
+
+    !links.is_empty()
+        && links.iter().all(|node_link| {
+            get_attribute(node_link, "href").is_some_and(|href| href.trim().starts_with('#'))
+        })
+}
+
+fn find_links(node: &Handle, links: &mut Vec<Handle>) {
+    if element_name(node).as_deref() == Some("a") {
+        links.push(node.clone());
+        return;
+    }
+
+    for node_child in node.children.borrow().iter() {
+        find_links(node_child, links);
+    }
+}
+
+fn inner_text(node: &Handle) -> String {
+    let mut text = String::new();
+    collect_visible_text(node, &mut text);
+
+    collapse_whitespace(&text).trim().to_string()
+}
+
+/// Like `collect_text`, but leaves out scripts and styles, which would otherwise count
+fn collect_visible_text(node: &Handle, text: &mut String) {
+    match &node.data {
+        NodeData::Text { contents } => text.push_str(&contents.borrow()),
+        _ if is_skipped(node) => {}
+        _ => {
+            for node_child in node.children.borrow().iter() {
+                collect_visible_text(node_child, text);
+            }
+        }
+    }
+}
+
+fn text_length(node: &Handle) -> usize {
+    inner_text(node).chars().count()
+}
+
+fn class_and_id(node: &Handle) -> String {
+    let class = get_attribute(node, "class").unwrap_or_default();
+    let id = get_attribute(node, "id").unwrap_or_default();
+
+    format!("{} {}", class, id).to_lowercase()
+}
+
+fn element_children(node: &Handle) -> Vec<Handle> {
+    node.children
+        .borrow()
+        .iter()
+        .filter(|node_child| matches!(node_child.data, NodeData::Element { .. }))
+        .cloned()
+        .collect()
+}
+
+fn get_parent(node: &Handle) -> Option<Handle> {
+    let weak_parent = node.parent.take();
+    let parent = weak_parent.as_ref().and_then(|weak| weak.upgrade());
+    node.parent.set(weak_parent);
+
+    parent
+}
@@ -1,34 +1,40 @@
 use cgmath::Point2;
 use cosmic_text::{
-    Attrs, Buffer, Color, Family, FontSystem, LayoutRun, Metrics, Shaping, Style, SwashCache,
-    Weight,
+    Align, Attrs, Buffer, Color, Family, FamilyOwned, FontSystem, LayoutRun, Metrics, Shaping,
+    Style, SwashCache, Weight, Wrap,
 };
 use image::{Pixel, Rgba, RgbaImage};
 use log::{debug, info, warn};
 use std::fmt;
+use std::ops::Range;
 
+mod article_header;
 mod images;
 
-use crate::browser_core::network::resolve_url;
+use crate::browser_core::network::try_resolve_url;
 use crate::browser_core::parsing::{
-    Block, Document, ListItem, Span, SpanStyle, TableCell, TableRow,
+    Block, Document, ListItem, ListMarker, Span, SpanStyle, TableCell, TableRow,
 };
-use crate::browser_core::ImagesByUrl;
-use crate::settings::RenderingSettings;
+use crate::browser_core::{ImagesByUrl, PreparedImages};
+use crate::fonts::find_font_file_family;
+use crate::settings::{FontFamily, ParagraphStyle, RenderingSettings, TextAlign};
 
 use crate::{CANVAS_HEIGHT, CANVAS_MARGIN_BOTTOM, CANVAS_MARGIN_TOP, CANVAS_WIDTH, DEBUG_LAYOUT};
 
 use crate::ui::helpers::{
-    create_blank_canvas, draw_box_border, draw_horizontal_line, draw_vertical_line,
+    create_blank_canvas, draw_box_border, draw_filled_rectangle, draw_horizontal_line,
+    draw_vertical_line,
 };
 use crate::ui::progress::add_progress_overlay;
-use images::{render_placeholder_image_block, rescale_image};
+use article_header::ArticleHeader;
+use images::{prepare_image_for_display, render_placeholder_image_block, rescale_image};
 
 const COLOR_BACKGROUND: Rgba<u8> = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
 const COLOR_DEBUG_LAYOUT: Rgba<u8> = Rgba([0x00, 0xFF, 0xFF, 0xFF]);
 const COLOR_TABLE_ROW_BORDER: Rgba<u8> = Rgba([0x33, 0x33, 0x33, 0xFF]);
 const COLOR_TABLE_CELL_BORDER: Rgba<u8> = Rgba([0x99, 0x99, 0x99, 0xFF]);
 const COLOR_BLOCKQUOTE_BORDER: Rgba<u8> = Rgba([0x00, 0x00, 0x00, 0xFF]);
+const COLOR_THEMATIC_BREAK: Rgba<u8> = Rgba([0x99, 0x99, 0x99, 0xFF]);
 
 // Using cosmic_text Colors here
 const COLOR_TEXT: Color = Color::rgba(0x00, 0x00, 0x00, 0xFF);
@@ -37,16 +43,274 @@ const COLOR_LINK: Color = Color::rgba(0x00, 0x00, 0xFF, 0xFF);
 const LINK_UNDERLINE_OFFSET_Y: i32 = 2;
 const LINK_UNDERLINE_THICKNESS: i32 = 2;
 
+const DISPLAY_SCALE: f32 = 2.0;
+
+// Font sizes of h1 through h6, relative to body text, at a heading scale of 1.0
+const HEADING_SIZES: [f32; 6] = [2.0, 1.5, 1.25, 1.0, 1.0, 1.0];
+// An em space and an en space, since cosmic-text has no text-indent of its own
+const FIRST_LINE_INDENT: &str = "\u{2003}\u{2002}";
+
 const INDENT_MARGIN_LEFT_EMS: u32 = 2;
+const LIST_MARKER_GAP_EMS: f32 = 0.5;
+const LIST_BULLETS: [&str; 3] = ["•", "◦", "▪"];
 
 const BLOCKQUOTE_BORDER_WIDTH: u32 = 5;
+const THEMATIC_BREAK_THICKNESS: u32 = 2;
 
 const TABLE_CELL_PADDING_X: u32 = 20;
+const TABLE_CELL_PADDING_Y: u32 = 12;
+
+// Images in a paragraph up to this many lines tall are shrunk to sit on a line, like icons.
+// Bigger ones get a place of their own between the paragraph's lines.
+const INLINE_IMAGE_MAX_LINES: f32 = 3.0;
+// Glyphs holding an inline image's place carry this plus the image's index as metadata,
+// far above any link's index
+const INLINE_IMAGE_METADATA: usize = 1 << 24;
+const INLINE_IMAGE_PLACEHOLDER: &str = "\u{a0}";
 
 pub struct RenderedBlock {
     pub height: u32,
     pub canvas: RgbaImage,
-    pub breakpoints: Vec<u32>,
+    pub breakpoints: Vec<Breakpoint>,
+    pub links: Vec<LinkRegion>,
+    pub anchors: Vec<AnchorPosition>,
+    // The baseline of the block's first line of text, if it has any
+    pub first_baseline: Option<u32>,
+    // How much text the block has, so blocks around it can count past it
+    pub text_len: usize,
+}
+
+/// A place where a block can be split between pages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Breakpoint {
+    pub y: u32,
+    // How far into the block's text the split is
+    pub text_offset: usize,
+}
+
+impl Breakpoint {
+    pub fn new(y: u32, text_offset: usize) -> Self {
+        Self { y, text_offset }
+    }
+
+    fn offset(&self, offset_y: u32, offset_text: usize) -> Breakpoint {
+        Breakpoint::new(self.y + offset_y, self.text_offset + offset_text)
+    }
+}
+
+/// Where something is in the document, which stays the same when it's laid out differently
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourcePosition {
+    // The index of one of the document's top-level blocks
+    pub block_idx: usize,
+    pub text_offset: usize,
+}
+
+pub struct RenderedPage {
+    pub canvas: RgbaImage,
+    pub links: Vec<LinkRegion>,
+    // The ids of the elements on the page, so links to them know where to go
+    pub anchors: Vec<String>,
+    // What's at the top of the page
+    pub start_position: SourcePosition,
+}
+
+/// Finds the page that shows what was at the given position, like the top of a page
+/// before the document was laid out again
+pub fn find_page_for_position(pages: &[RenderedPage], position: &SourcePosition) -> usize {
+    pages
+        .iter()
+        .rposition(|page| page.start_position <= *position)
+        .unwrap_or(0)
+}
+
+/// How far laying out a document has got, so it can be laid out a page at a time, and laid
+/// out again from the page an image first shows up on once the image is in
+pub struct DocumentLayout {
+    // The page being filled, which isn't in the pages yet
+    current_page: RenderedPage,
+    page_offset_y: u32,
+    // Anchors outside of any content land on the page of whatever comes next
+    pending_anchors: Vec<String>,
+    // The next of the header's and the document's blocks to lay out
+    next_block: usize,
+    // How many breakpoints of the next block are on the pages before, when it starts there
+    skip_breakpoints: usize,
+    previous_block_was_paragraph: bool,
+    // The furthest of the document's blocks that's been laid out
+    last_block_idx: Option<usize>,
+    // Where each page starts, up to the current one
+    page_starts: Vec<PageStart>,
+    is_complete: bool,
+}
+
+/// Everything needed to lay out the pages again from the start of a page
+#[derive(Clone)]
+struct PageStart {
+    block: usize,
+    breakpoint_idx: usize,
+    previous_block_was_paragraph: bool,
+    pending_anchors: Vec<String>,
+    position: SourcePosition,
+}
+
+impl DocumentLayout {
+    pub fn new() -> Self {
+        let mut layout = DocumentLayout {
+            current_page: blank_page(SourcePosition::default()),
+            page_offset_y: CANVAS_MARGIN_TOP,
+            pending_anchors: vec![],
+            next_block: 0,
+            skip_breakpoints: 0,
+            previous_block_was_paragraph: false,
+            last_block_idx: None,
+            page_starts: vec![],
+            is_complete: false,
+        };
+        layout.start_page(PageStart {
+            block: 0,
+            breakpoint_idx: 0,
+            previous_block_was_paragraph: false,
+            pending_anchors: vec![],
+            position: SourcePosition::default(),
+        });
+
+        layout
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.is_complete
+    }
+
+    /// Where the page being laid out starts. Everything before it is on the pages already,
+    /// and once they're all laid out, everything is.
+    pub fn laid_out_until(&self) -> Option<SourcePosition> {
+        if self.is_complete {
+            return None;
+        }
+
+        self.page_starts
+            .last()
+            .map(|page_start| page_start.position)
+    }
+
+    /// Goes back to the page the document block starts on, if it's laid out already, so
+    /// the pages from there are laid out again. Returns whether it went back.
+    pub fn rewind_to_block(&mut self, block_idx: usize) -> bool {
+        if self
+            .last_block_idx
+            .is_none_or(|last_block_idx| block_idx > last_block_idx)
+        {
+            return false;
+        }
+
+        // A page starting partway through an earlier block lays that block out the same
+        // way again, but one partway through this block may not
+        let block_start = SourcePosition {
+            block_idx,
+            text_offset: 0,
+        };
+        let page_idx = self
+            .page_starts
+            .iter()
+            .rposition(|page_start| {
+                page_start.position.block_idx < block_idx
+                    || (page_start.position <= block_start && page_start.breakpoint_idx == 0)
+            })
+            .unwrap_or(0);
+
+        info!("Laying out the pages again from page {}", page_idx);
+
+        let page_start = self.page_starts[page_idx].clone();
+        self.page_starts.truncate(page_idx);
+        self.is_complete = false;
+        self.start_page(page_start);
+
+        true
+    }
+
+    fn current_page_idx(&self) -> usize {
+        self.page_starts.len() - 1
+    }
+
+    fn start_page(&mut self, page_start: PageStart) {
+        self.current_page = blank_page(page_start.position);
+        self.page_offset_y = CANVAS_MARGIN_TOP;
+        self.pending_anchors = page_start.pending_anchors.clone();
+        self.next_block = page_start.block;
+        self.skip_breakpoints = page_start.breakpoint_idx;
+        self.previous_block_was_paragraph = page_start.previous_block_was_paragraph;
+        self.page_starts.push(page_start);
+    }
+
+    /// Puts the current page in its place in the pages
+    fn finish_page(&mut self, pages: &mut Vec<RenderedPage>) {
+        let start_position = self.current_page.start_position;
+        let page = std::mem::replace(&mut self.current_page, blank_page(start_position));
+
+        let page_idx = self.current_page_idx();
+        if page_idx < pages.len() {
+            pages[page_idx] = page;
+        } else {
+            pages.push(page);
+        }
+    }
+}
+
+fn blank_page(start_position: SourcePosition) -> RenderedPage {
+    RenderedPage {
+        canvas: create_blank_canvas(CANVAS_WIDTH, CANVAS_HEIGHT, COLOR_BACKGROUND),
+        links: vec![],
+        anchors: vec![],
+        start_position,
+    }
+}
+
+/// A tappable area of a link, in the coordinates of the canvas it was rendered on
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkRegion {
+    pub top_left: Point2<u32>,
+    pub bottom_right: Point2<u32>,
+    pub destination: String,
+}
+
+impl LinkRegion {
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.top_left.x
+            && x <= self.bottom_right.x
+            && y >= self.top_left.y
+            && y <= self.bottom_right.y
+    }
+
+    fn offset_y(&self, offset_y: i32) -> LinkRegion {
+        LinkRegion {
+            top_left: Point2::new(
+                self.top_left.x,
+                (self.top_left.y as i32 + offset_y).max(0) as u32,
+            ),
+            bottom_right: Point2::new(
+                self.bottom_right.x,
+                (self.bottom_right.y as i32 + offset_y).max(0) as u32,
+            ),
+            destination: self.destination.clone(),
+        }
+    }
+}
+
+/// Where an element with an id ended up, in the coordinates of the canvas it was rendered on
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnchorPosition {
+    pub id: String,
+    pub y: u32,
+}
+
+impl AnchorPosition {
+    fn offset_y(&self, offset_y: u32) -> AnchorPosition {
+        AnchorPosition {
+            id: self.id.clone(),
+            y: self.y + offset_y,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -54,6 +318,24 @@ struct BlockRenderSettings {
     pub canvas_width: u32,
     pub margin_left: u32,
     pub margin_right: u32,
+    // How many lists this block is nested inside
+    pub list_depth: usize,
+}
+
+/// How narrow and how wide a table column can usefully be, including the cell padding
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ColumnMeasure {
+    // Wide enough for the longest word
+    pub min_width: u32,
+    // Wide enough for the longest cell on one line
+    pub max_width: u32,
+}
+
+impl ColumnMeasure {
+    fn include(&mut self, other: &ColumnMeasure) {
+        self.min_width = self.min_width.max(other.min_width);
+        self.max_width = self.max_width.max(other.max_width);
+    }
 }
 
 impl fmt::Debug for RenderedBlock {
@@ -61,28 +343,48 @@ impl fmt::Debug for RenderedBlock {
         f.debug_struct("RenderedBlock")
             .field("height", &self.height)
             .field("breakpoints", &self.breakpoints.len())
+            .field("links", &self.links.len())
+            .field("anchors", &self.anchors.len())
+            .field("first_baseline", &self.first_baseline)
+            .field("text_len", &self.text_len)
             .finish()
     }
 }
 
+/// An image sitting on a line of text, in place of a run of non-breaking spaces as wide as it
+struct InlineImage {
+    image: RgbaImage,
+    placeholder: String,
+}
+
 pub struct Renderer<'a> {
     rendering_settings: &'a RenderingSettings,
-    webpage_url: String,
-    images: ImagesByUrl,
+    // What links and images are relative to, which is the page's URL unless it has a
+    // `<base href>`
+    base_url: String,
+    images: &'a ImagesByUrl,
+    // Images already shrunk and dithered, kept between layouts so they're only done once
+    prepared_images: &'a mut PreparedImages,
     buffer: Buffer,
-    font_system: FontSystem,
-    swash_cache: SwashCache,
+    font_system: &'a mut FontSystem,
+    swash_cache: &'a mut SwashCache,
+    // The family for body text and headings, from the typography settings
+    body_family: FamilyOwned,
+    // Whether the block we just rendered was a paragraph, for paragraphs set like in a book
+    previous_block_was_paragraph: bool,
+    // The images in the buffer's text, by the index in their placeholders' metadata
+    buffer_images: Vec<Option<InlineImage>>,
 }
 
 impl<'a> Renderer<'a> {
     pub fn new(
         rendering_settings: &'a RenderingSettings,
-        webpage_url: &str,
-        images: ImagesByUrl,
+        base_url: &str,
+        images: &'a ImagesByUrl,
+        prepared_images: &'a mut PreparedImages,
+        font_system: &'a mut FontSystem,
+        swash_cache: &'a mut SwashCache,
     ) -> Self {
-        let mut font_system = FontSystem::new();
-        let swash_cache = SwashCache::new();
-
         let display_scale: f32 = 2.0;
         let metrics = Metrics::relative(
             rendering_settings.font_size as f32,
@@ -92,139 +394,304 @@ impl<'a> Renderer<'a> {
 
         let buffer_width = CANVAS_WIDTH - rendering_settings.screen_margin_x * 2;
 
-        buffer.set_size(&mut font_system, Some(buffer_width as f32), None);
+        buffer.set_size(font_system, Some(buffer_width as f32), None);
+
+        let body_family = match &rendering_settings.typography.font_family {
+            FontFamily::Sans => FamilyOwned::SansSerif,
+            FontFamily::Serif => FamilyOwned::Serif,
+            FontFamily::Custom(path) => match find_font_file_family(font_system, path) {
+                Some(family) => FamilyOwned::Name(family),
+                None => {
+                    warn!("Custom font {} isn't loaded, using the default", path);
+                    FamilyOwned::SansSerif
+                }
+            },
+        };
 
         Renderer {
             rendering_settings,
-            webpage_url: webpage_url.to_string(),
+            base_url: base_url.to_string(),
             images,
+            prepared_images,
             buffer,
             font_system,
             swash_cache,
+            body_family,
+            previous_block_was_paragraph: false,
+            buffer_images: vec![],
         }
     }
 
-    pub fn render_document(&mut self, document: &Document) -> Vec<RgbaImage> {
-        let mut finished_page_canvases = vec![];
-        let mut current_page_canvas =
-            create_blank_canvas(CANVAS_WIDTH, CANVAS_HEIGHT, COLOR_BACKGROUND);
-
-        let mut page_offset_y = CANVAS_MARGIN_TOP;
-
+    /// Lays out blocks until at least one more page is finished, or the whole document is.
+    /// Each page replaces the one laid out before at its index, if any. Returns the indexes
+    /// of the pages that changed, which is all of them at the end, once their overlays show
+    /// how many pages there are.
+    pub fn render_next_pages(
+        &mut self,
+        document: &Document,
+        layout: &mut DocumentLayout,
+        pages: &mut Vec<RenderedPage>,
+    ) -> Range<usize> {
+        let first_page_idx = layout.current_page_idx();
         let max_y = CANVAS_HEIGHT - CANVAS_MARGIN_BOTTOM;
 
         let default_render_settings = BlockRenderSettings {
             canvas_width: CANVAS_WIDTH,
             margin_left: self.rendering_settings.screen_margin_x,
             margin_right: self.rendering_settings.screen_margin_x,
+            list_depth: 0,
         };
 
-        for (block_idx, block) in document.blocks.iter().enumerate() {
-            info!("Rendering block {}...", block_idx);
-
+        // The header comes before the first block, so a page starting there starts with
+        // the document
+        let header = ArticleHeader::new(document);
+        let mut blocks = header
+            .blocks
+            .iter()
+            .map(|(block_idx, block)| (*block_idx, block))
+            .chain(
+                document
+                    .blocks
+                    .iter()
+                    .enumerate()
+                    .skip(header.document_blocks_used)
+                    .map(|(block_idx, block)| (Some(block_idx), block)),
+            )
+            .skip(layout.next_block);
+
+        self.previous_block_was_paragraph = layout.previous_block_was_paragraph;
+
+        while layout.current_page_idx() == first_page_idx {
+            let Some((block_idx, block)) = blocks.next() else {
+                layout
+                    .current_page
+                    .anchors
+                    .append(&mut layout.pending_anchors);
+                layout.finish_page(pages);
+                layout.is_complete = true;
+                pages.truncate(layout.page_starts.len());
+                info!("Rendered {} total pages", pages.len());
+
+                self.add_page_overlays(pages);
+
+                return 0..pages.len();
+            };
+
+            info!("Rendering block {:?}...", block_idx);
+
+            let follows_paragraph = self.previous_block_was_paragraph;
             let rendered_block = self.render_block(block, &default_render_settings);
 
             info!("Rendered block: {:?}", rendered_block);
 
-            for (breakpoint_idx, breakpoint_y) in rendered_block.breakpoints.iter().enumerate() {
-                debug!(
-                    "Breakpoint index {}, position {}",
-                    breakpoint_idx, breakpoint_y
-                );
+            let mut block_anchors: Vec<&AnchorPosition> = rendered_block.anchors.iter().collect();
+            block_anchors.sort_by_key(|anchor| anchor.y);
+            let mut block_anchors = block_anchors.into_iter().peekable();
+
+            // Laying out a page again from partway through a block, whose start is already
+            // on the pages before
+            let skip_breakpoints = std::mem::take(&mut layout.skip_breakpoints);
+            if let Some(breakpoint) = rendered_block.breakpoints.get(skip_breakpoints) {
+                while block_anchors
+                    .next_if(|anchor| anchor.y < breakpoint.y)
+                    .is_some()
+                {}
+            }
+
+            for (breakpoint_idx, breakpoint) in rendered_block
+                .breakpoints
+                .iter()
+                .enumerate()
+                .skip(skip_breakpoints)
+            {
+                debug!("Breakpoint index {}, {:?}", breakpoint_idx, breakpoint);
 
+                let breakpoint_y = &breakpoint.y;
                 let block_segment_height = match rendered_block.breakpoints.get(breakpoint_idx + 1)
                 {
-                    Some(next_breakpoint_y) => next_breakpoint_y - breakpoint_y,
+                    Some(next_breakpoint) => next_breakpoint.y - breakpoint_y,
                     None => rendered_block.height - breakpoint_y,
                 };
 
-                if page_offset_y + block_segment_height >= max_y {
+                if layout.page_offset_y + block_segment_height >= max_y {
                     info!("Starting a new page");
 
-                    finished_page_canvases.push(current_page_canvas);
-                    current_page_canvas =
-                        create_blank_canvas(CANVAS_WIDTH, CANVAS_HEIGHT, COLOR_BACKGROUND);
-                    page_offset_y = CANVAS_MARGIN_TOP;
+                    // Shown before the rest are laid out, so with its number but no total
+                    let page_idx = layout.current_page_idx();
+                    self.add_page_overlay(page_idx, None, &mut layout.current_page);
+                    layout.finish_page(pages);
+                    layout.start_page(PageStart {
+                        block: layout.next_block,
+                        breakpoint_idx,
+                        previous_block_was_paragraph: follows_paragraph,
+                        pending_anchors: layout.pending_anchors.clone(),
+                        position: match block_idx {
+                            Some(block_idx) => SourcePosition {
+                                block_idx,
+                                text_offset: breakpoint.text_offset,
+                            },
+                            None => SourcePosition::default(),
+                        },
+                    });
                 }
 
                 debug!(
                     "Adding block segment {} (block offset {}, height {}) to current page at page offset {}",
-                    breakpoint_idx, breakpoint_y, block_segment_height, page_offset_y
+                    breakpoint_idx, breakpoint_y, block_segment_height, layout.page_offset_y
                 );
 
                 let block_top_left = Point2::new(0, *breakpoint_y);
                 let block_bottom_right =
                     Point2::new(CANVAS_WIDTH - 1, breakpoint_y + block_segment_height);
 
-                let copy_offset_y = (page_offset_y as i32) - (*breakpoint_y as i32);
+                let copy_offset_y = (layout.page_offset_y as i32) - (*breakpoint_y as i32);
 
                 copy_block_to_page_canvas(
                     &rendered_block.canvas,
-                    &mut current_page_canvas,
+                    &mut layout.current_page.canvas,
                     block_top_left,
                     block_bottom_right,
                     copy_offset_y,
                 );
 
-                page_offset_y += block_segment_height;
+                layout.current_page.links.extend(links_in_segment(
+                    &rendered_block.links,
+                    *breakpoint_y,
+                    breakpoint_y + block_segment_height,
+                    copy_offset_y,
+                ));
+
+                let segment_end_y = breakpoint_y + block_segment_height;
+                let is_last_segment = breakpoint_idx + 1 == rendered_block.breakpoints.len();
+                layout
+                    .current_page
+                    .anchors
+                    .append(&mut layout.pending_anchors);
+                while let Some(anchor) =
+                    block_anchors.next_if(|anchor| is_last_segment || anchor.y < segment_end_y)
+                {
+                    layout.current_page.anchors.push(anchor.id.clone());
+                }
+
+                layout.page_offset_y += block_segment_height;
+            }
+
+            layout
+                .pending_anchors
+                .extend(block_anchors.map(|anchor| anchor.id.clone()));
+            layout.next_block += 1;
+            layout.skip_breakpoints = 0;
+            if let Some(block_idx) = block_idx {
+                layout.last_block_idx = Some(block_idx);
             }
         }
 
-        finished_page_canvases.push(current_page_canvas);
+        layout.previous_block_was_paragraph = self.previous_block_was_paragraph;
 
-        let total_pages = finished_page_canvases.len();
-        info!("Rendered {} total pages", total_pages);
+        first_page_idx..layout.current_page_idx()
+    }
 
-        for (page_idx, page_canvas) in finished_page_canvases.iter_mut().enumerate() {
-            info!("Adding overlays to page {}", page_idx);
+    /// Draws the progress overlays again, now that it's known how many pages there are
+    fn add_page_overlays(&mut self, pages: &mut [RenderedPage]) {
+        let total_pages = pages.len();
 
-            if DEBUG_LAYOUT {
-                let box_top_left = Point2::<u32> {
-                    x: self.rendering_settings.screen_margin_x,
-                    y: CANVAS_MARGIN_TOP,
-                };
-                let box_bottom_right = Point2::<u32> {
-                    x: CANVAS_WIDTH - self.rendering_settings.screen_margin_x,
-                    y: CANVAS_HEIGHT - CANVAS_MARGIN_BOTTOM,
-                };
-                draw_box_border(
-                    box_top_left,
-                    box_bottom_right,
-                    Rgba([0xFF, 0x00, 0x00, 0xFF]),
-                    page_canvas,
-                );
-            }
+        for (page_idx, page) in pages.iter_mut().enumerate() {
+            self.add_page_overlay(page_idx, Some(total_pages), page);
+        }
+    }
+
+    /// Draws the progress overlay on a page, clearing the one drawn before, if any
+    fn add_page_overlay(
+        &mut self,
+        page_idx: usize,
+        total_pages: Option<usize>,
+        page: &mut RenderedPage,
+    ) {
+        info!("Adding overlays to page {}", page_idx);
+
+        let page_canvas = &mut page.canvas;
+
+        draw_filled_rectangle(
+            Point2::new(0, CANVAS_HEIGHT - CANVAS_MARGIN_BOTTOM),
+            Point2::new(CANVAS_WIDTH - 1, CANVAS_HEIGHT - 1),
+            COLOR_BACKGROUND,
+            page_canvas,
+        );
 
-            add_progress_overlay(
-                page_idx,
-                total_pages,
+        if DEBUG_LAYOUT {
+            let box_top_left = Point2::<u32> {
+                x: self.rendering_settings.screen_margin_x,
+                y: CANVAS_MARGIN_TOP,
+            };
+            let box_bottom_right = Point2::<u32> {
+                x: CANVAS_WIDTH - self.rendering_settings.screen_margin_x,
+                y: CANVAS_HEIGHT - CANVAS_MARGIN_BOTTOM,
+            };
+            draw_box_border(
+                box_top_left,
+                box_bottom_right,
+                Rgba([0xFF, 0x00, 0x00, 0xFF]),
                 page_canvas,
-                &mut self.font_system,
-                &mut self.swash_cache,
             );
         }
 
-        finished_page_canvases
+        add_progress_overlay(
+            page_idx,
+            total_pages,
+            page_canvas,
+            self.font_system,
+            self.swash_cache,
+        );
     }
 
     fn render_block(&mut self, block: &Block, settings: &BlockRenderSettings) -> RenderedBlock {
-        match block {
+        // Anchors take no space, and aren't a block between two paragraphs either
+        if let Block::Anchor { id } = block {
+            return render_anchor_block(id);
+        }
+
+        // Taken before rendering, so the first paragraph inside a blockquote or list isn't
+        // treated as following this block's previous sibling
+        let follows_paragraph = std::mem::take(&mut self.previous_block_was_paragraph);
+
+        let rendered_block = match block {
             Block::Image { url, alt_text } => {
-                return self.render_image_block(&url.clone(), alt_text.clone(), settings);
-            }
-            Block::BlockQuote { content } => {
-                return self.render_blockquote_block(content, settings);
+                self.render_image_block(&url.clone(), alt_text.clone(), settings)
             }
-            Block::List { items } => {
-                return self.render_list_block(items, settings);
-            }
-            Block::Table { rows } => {
-                return self.render_table_block(rows, settings);
-            }
-            _ => {
-                return self.render_text_based_block(block, settings);
+            Block::BlockQuote { content } => self.render_blockquote_block(content, settings),
+            Block::List { items } => self.render_list_block(items, settings),
+            Block::Table { rows } => self.render_table_block(rows, settings),
+            Block::ThematicBreak => self.render_thematic_break_block(settings),
+            Block::Paragraph { content } if self.has_block_sized_image(content, settings) => {
+                self.render_paragraph_with_block_images(content, settings, follows_paragraph)
             }
+            _ => self.render_text_based_block(block, settings, follows_paragraph),
+        };
+
+        let is_paragraph = matches!(block, Block::Paragraph { .. });
+        let ends_with_paragraph = self.previous_block_was_paragraph;
+        self.previous_block_was_paragraph = is_paragraph;
+
+        if self.rendering_settings.typography.paragraph_style != ParagraphStyle::FirstLineIndent
+            || is_paragraph
+        {
+            return rendered_block;
         }
+
+        // Paragraphs set like in a book have no gap after them, so anything else that comes
+        // after one needs its own
+        let spacing_before = if follows_paragraph {
+            self.block_spacing()
+        } else {
+            0
+        };
+        let spacing_after = if ends_with_paragraph {
+            self.block_spacing()
+        } else {
+            0
+        };
+
+        add_block_spacing(rendered_block, spacing_before, spacing_after)
     }
 
     fn render_image_block(
@@ -233,33 +700,18 @@ impl<'a> Renderer<'a> {
         _alt_text: Option<String>,
         settings: &BlockRenderSettings,
     ) -> RenderedBlock {
-        let resolved_url = resolve_url(&self.webpage_url, url);
-
-        let image_find_result = self.images.get(&resolved_url);
-
-        if let None = image_find_result {
-            return render_placeholder_image_block(settings.canvas_width, settings.margin_left);
-        }
-
-        let image_load_result = image_find_result.unwrap();
-
-        if let None = image_load_result {
+        let Some(image) = self.find_image(url) else {
             return render_placeholder_image_block(settings.canvas_width, settings.margin_left);
-        }
-        let image: &RgbaImage = image_load_result.as_ref().unwrap();
+        };
 
-        let image_width = image.width();
         let available_content_width =
             settings.canvas_width - settings.margin_left - settings.margin_right;
 
         info!("Available content width: {}", available_content_width);
 
-        let image: &RgbaImage = {
-            if image_width <= available_content_width {
-                image
-            } else {
-                &rescale_image(image, available_content_width)
-            }
+        let target_width = image.width().min(available_content_width);
+        let Some(image) = self.prepare_image(url, target_width) else {
+            return render_placeholder_image_block(settings.canvas_width, settings.margin_left);
         };
 
         let image_width = image.width();
@@ -287,36 +739,96 @@ impl<'a> Renderer<'a> {
             *canvas_pixel = *image.get_pixel(image_x, image_y);
         }
 
-        let breakpoints = vec![0];
+        let breakpoints = vec![Breakpoint::new(0, 0)];
 
         RenderedBlock {
             height: image_height,
             canvas,
             breakpoints,
+            links: vec![],
+            anchors: vec![],
+            first_baseline: None,
+            text_len: 0,
         }
     }
 
     fn render_blockquote_block(
         &mut self,
-        content: &Vec<Block>,
+        content: &[Block],
         settings: &BlockRenderSettings,
     ) -> RenderedBlock {
         let mut child_settings: BlockRenderSettings = settings.clone();
         let indent_left = INDENT_MARGIN_LEFT_EMS * self.rendering_settings.font_size;
         child_settings.margin_left += indent_left;
 
+        let mut rendered_block = self.render_block_stack(content, &child_settings);
+
+        for y in 0..rendered_block.height {
+            for x in settings.margin_left..settings.margin_left + BLOCKQUOTE_BORDER_WIDTH {
+                *rendered_block.canvas.get_pixel_mut(x, y) = COLOR_BLOCKQUOTE_BORDER;
+            }
+        }
+
+        rendered_block
+    }
+
+    /// A rule across the text column, taking up a line
+    fn render_thematic_break_block(&mut self, settings: &BlockRenderSettings) -> RenderedBlock {
+        let height = self.paragraph_metrics().line_height.ceil() as u32;
+        let mut canvas = create_blank_canvas(CANVAS_WIDTH, height, COLOR_BACKGROUND);
+
+        let line_top = (height - THEMATIC_BREAK_THICKNESS) / 2;
+        for y in line_top..line_top + THEMATIC_BREAK_THICKNESS {
+            draw_horizontal_line(
+                settings.margin_left,
+                settings.canvas_width - settings.margin_right - 1,
+                y,
+                COLOR_THEMATIC_BREAK,
+                &mut canvas,
+            );
+        }
+
+        let rendered_block = RenderedBlock {
+            height,
+            canvas,
+            breakpoints: vec![Breakpoint::new(0, 0)],
+            links: vec![],
+            anchors: vec![],
+            first_baseline: None,
+            text_len: 0,
+        };
+
+        add_block_spacing(rendered_block, 0, self.block_spacing())
+    }
+
+    /// Renders blocks one below the other, as a single block
+    fn render_block_stack(
+        &mut self,
+        content: &[Block],
+        settings: &BlockRenderSettings,
+    ) -> RenderedBlock {
         let mut offset_y = 0;
+        let mut text_offset = 0;
         let mut breakpoints = vec![];
+        let mut links = vec![];
+        let mut anchors = vec![];
         let mut rendered_children: Vec<RenderedBlock> = vec![];
 
         for child_block in content {
-            let rendered_child = self.render_block(child_block, &child_settings);
+            let rendered_child = self.render_block(child_block, settings);
 
             for breakpoint in rendered_child.breakpoints.iter() {
-                breakpoints.push(offset_y + breakpoint);
+                breakpoints.push(breakpoint.offset(offset_y, text_offset));
+            }
+            for link in rendered_child.links.iter() {
+                links.push(link.offset_y(offset_y as i32));
+            }
+            for anchor in rendered_child.anchors.iter() {
+                anchors.push(anchor.offset_y(offset_y));
             }
 
             offset_y += rendered_child.height;
+            text_offset += rendered_child.text_len;
 
             rendered_children.push(rendered_child);
         }
@@ -343,17 +855,46 @@ impl<'a> Renderer<'a> {
             offset_y += rendered_child.height;
         }
 
-        for y in 0..total_height {
-            for x in settings.margin_left..settings.margin_left + BLOCKQUOTE_BORDER_WIDTH {
-                *canvas.get_pixel_mut(x, y) = COLOR_BLOCKQUOTE_BORDER;
-            }
-        }
-
         RenderedBlock {
             height: total_height,
             canvas,
             breakpoints,
+            links,
+            anchors,
+            first_baseline: first_baseline(&rendered_children),
+            text_len: text_offset,
+        }
+    }
+
+    /// Renders a paragraph with big images in it as a stack: the text before each image,
+    /// the image, and the text after it
+    fn render_paragraph_with_block_images(
+        &mut self,
+        content: &[Span],
+        settings: &BlockRenderSettings,
+        follows_paragraph: bool,
+    ) -> RenderedBlock {
+        let mut blocks = vec![];
+        let mut text_spans = vec![];
+
+        for span in content {
+            match span {
+                Span::Image { url, alt_text } if self.is_block_sized_image(url, settings) => {
+                    push_trimmed_paragraph(&mut blocks, std::mem::take(&mut text_spans));
+                    blocks.push(Block::Image {
+                        url: url.clone(),
+                        alt_text: alt_text.clone(),
+                    });
+                }
+                _ => text_spans.push(span.clone()),
+            }
         }
+        push_trimmed_paragraph(&mut blocks, text_spans);
+
+        // The first piece follows whatever came before the whole paragraph
+        self.previous_block_was_paragraph = follows_paragraph;
+
+        self.render_block_stack(&blocks, settings)
     }
 
     fn render_list_block(
@@ -364,17 +905,27 @@ impl<'a> Renderer<'a> {
         let list_item_settings: BlockRenderSettings = settings.clone();
 
         let mut offset_y = 0;
+        let mut text_offset = 0;
         let mut breakpoints = vec![];
+        let mut links = vec![];
+        let mut anchors = vec![];
         let mut rendered_list_items: Vec<RenderedBlock> = vec![];
 
         for child_list_item in list_items {
             let rendered_list_item = self.render_list_item(child_list_item, &list_item_settings);
 
             for breakpoint in rendered_list_item.breakpoints.iter() {
-                breakpoints.push(offset_y + breakpoint);
+                breakpoints.push(breakpoint.offset(offset_y, text_offset));
+            }
+            for link in rendered_list_item.links.iter() {
+                links.push(link.offset_y(offset_y as i32));
+            }
+            for anchor in rendered_list_item.anchors.iter() {
+                anchors.push(anchor.offset_y(offset_y));
             }
 
             offset_y += rendered_list_item.height;
+            text_offset += rendered_list_item.text_len;
 
             rendered_list_items.push(rendered_list_item);
         }
@@ -405,6 +956,12 @@ impl<'a> Renderer<'a> {
             height: total_height,
             canvas,
             breakpoints,
+            links,
+            anchors,
+            first_baseline: rendered_list_items
+                .first()
+                .and_then(|item| item.first_baseline),
+            text_len: text_offset,
         }
     }
 
@@ -416,19 +973,33 @@ impl<'a> Renderer<'a> {
         let mut child_settings: BlockRenderSettings = settings.clone();
         let indent_left = INDENT_MARGIN_LEFT_EMS * self.rendering_settings.font_size;
         child_settings.margin_left += indent_left;
+        child_settings.list_depth += 1;
+
+        // Each item starts afresh, like a blockquote
+        self.previous_block_was_paragraph = false;
 
         let mut offset_y = 0;
+        let mut text_offset = 0;
         let mut breakpoints = vec![];
+        let mut links = vec![];
+        let mut anchors = vec![];
         let mut rendered_children: Vec<RenderedBlock> = vec![];
 
         for child_block in list_item.content.iter() {
             let rendered_child = self.render_block(child_block, &child_settings);
 
             for breakpoint in rendered_child.breakpoints.iter() {
-                breakpoints.push(offset_y + breakpoint);
+                breakpoints.push(breakpoint.offset(offset_y, text_offset));
+            }
+            for link in rendered_child.links.iter() {
+                links.push(link.offset_y(offset_y as i32));
+            }
+            for anchor in rendered_child.anchors.iter() {
+                anchors.push(anchor.offset_y(offset_y));
             }
 
             offset_y += rendered_child.height;
+            text_offset += rendered_child.text_len;
 
             rendered_children.push(rendered_child);
         }
@@ -436,8 +1007,8 @@ impl<'a> Renderer<'a> {
         let mut total_height = offset_y;
 
         if total_height == 0 {
-            warn!("Empty list item, setting height to a default");
-            total_height = BLOCKQUOTE_BORDER_WIDTH;
+            warn!("Empty list item, setting height to a line so the marker fits");
+            total_height = self.paragraph_metrics().line_height.ceil() as u32;
         }
 
         let mut canvas = RgbaImage::new(CANVAS_WIDTH, total_height);
@@ -460,47 +1031,134 @@ impl<'a> Renderer<'a> {
             offset_y += rendered_child.height;
         }
 
-        // TODO: draw bullet point instead of this funny thing
-        for y in 0..BLOCKQUOTE_BORDER_WIDTH {
-            for x in settings.margin_left..settings.margin_left + BLOCKQUOTE_BORDER_WIDTH {
-                *canvas.get_pixel_mut(x, y) = COLOR_BLOCKQUOTE_BORDER;
-            }
-        }
+        let marker_text = match &list_item.marker {
+            ListMarker::Bullet => LIST_BULLETS[settings.list_depth.min(LIST_BULLETS.len() - 1)],
+            ListMarker::Ordered { content } => content,
+        };
+        let first_baseline = first_baseline(&rendered_children);
+
+        // Markers sit in the indent, right-aligned against the item's content
+        let marker_gap = LIST_MARKER_GAP_EMS * self.rendering_settings.font_size as f32;
+        let marker_right_x = child_settings.margin_left as f32 - marker_gap * DISPLAY_SCALE;
+        self.draw_list_marker(marker_text, marker_right_x, first_baseline, &mut canvas);
 
         RenderedBlock {
             height: total_height,
             canvas,
             breakpoints,
+            links,
+            anchors,
+            first_baseline,
+            text_len: text_offset,
         }
     }
 
+    /// Draws a list marker ending at the given x, on the baseline of the item's first line
+    fn draw_list_marker(
+        &mut self,
+        marker_text: &str,
+        right_x: f32,
+        baseline: Option<u32>,
+        canvas: &mut RgbaImage,
+    ) {
+        let attrs = Attrs::new()
+            .family(self.body_family.as_family())
+            .metrics(self.paragraph_metrics());
+        self.buffer.set_size(self.font_system, None, None);
+        self.buffer
+            .set_text(self.font_system, marker_text, attrs, Shaping::Advanced);
+
+        let Some(layout_run) = self.buffer.layout_runs().next() else {
+            return;
+        };
+
+        let offset_x = (right_x - layout_run.line_w).round() as i32;
+        let offset_y = match baseline {
+            Some(baseline) => baseline as i32 - layout_run.line_y.round() as i32,
+            None => 0,
+        };
+
+        draw_layout_run(
+            &layout_run,
+            offset_y,
+            self.font_system,
+            self.swash_cache,
+            COLOR_TEXT,
+            |buffer_x, buffer_y, color| {
+                let canvas_x = buffer_x + offset_x;
+                let canvas_y = buffer_y;
+
+                if canvas_x < 0 || canvas_x >= canvas.width() as i32 {
+                    return;
+                }
+                if canvas_y < 0 || canvas_y >= canvas.height() as i32 {
+                    return;
+                }
+
+                let canvas_x = canvas_x as u32;
+                let canvas_y = canvas_y as u32;
+
+                let (fg_r, fg_g, fg_b, fg_a) = color.as_rgba_tuple();
+                let fg = Rgba([fg_r, fg_g, fg_b, fg_a]);
+
+                let bg = canvas.get_pixel(canvas_x, canvas_y);
+                let mut result = *bg;
+                result.blend(&fg);
+                canvas.put_pixel(canvas_x, canvas_y, result);
+            },
+        );
+    }
+
     fn render_table_block(
         &mut self,
         rows: &Vec<TableRow>,
         settings: &BlockRenderSettings,
     ) -> RenderedBlock {
-        let row_settings: BlockRenderSettings = settings.clone();
-
         info!("Rendering table with {} rows", rows.len());
 
+        let column_count = rows.iter().map(|row| row.cells.len()).max().unwrap_or(0);
+        let mut column_measures = vec![ColumnMeasure::default(); column_count];
+        for row in rows {
+            for (column_idx, cell) in row.cells.iter().enumerate() {
+                let cell_measure = self.measure_table_cell(cell);
+                column_measures[column_idx].include(&cell_measure);
+            }
+        }
+
+        let table_width = settings.canvas_width - settings.margin_left - settings.margin_right;
+        let column_widths = distribute_column_widths(&column_measures, table_width);
+
+        info!("Table column widths: {:?}", column_widths);
+
         let mut offset_y = 0;
+        let mut text_offset = 0;
         let mut breakpoints = vec![];
+        let mut links = vec![];
+        let mut anchors = vec![];
         let mut rendered_rows: Vec<RenderedBlock> = vec![];
 
         for row in rows {
-            let rendered_row = self.render_table_row(row, &row_settings);
-
-            info!("Rendered row: {:?}", rendered_row);
+            let rendered_row = self.render_table_row(row, &column_widths, settings);
 
             for breakpoint in rendered_row.breakpoints.iter() {
-                breakpoints.push(offset_y + breakpoint);
+                breakpoints.push(breakpoint.offset(offset_y, text_offset));
+            }
+            for link in rendered_row.links.iter() {
+                links.push(link.offset_y(offset_y as i32));
+            }
+            for anchor in rendered_row.anchors.iter() {
+                anchors.push(anchor.offset_y(offset_y));
             }
 
             offset_y += rendered_row.height;
+            text_offset += rendered_row.text_len;
             rendered_rows.push(rendered_row);
         }
 
-        let total_height = offset_y + 1; // Add 1 pixel for the bottom border
+        // Add 1 pixel for the bottom border, then the same gap as after a paragraph
+        let bottom_border_y = offset_y;
+        let spacing_after = self.block_spacing();
+        let total_height = bottom_border_y + 1 + spacing_after;
         let mut canvas = RgbaImage::new(CANVAS_WIDTH, total_height);
 
         for pixel in canvas.pixels_mut() {
@@ -520,12 +1178,9 @@ impl<'a> Renderer<'a> {
         }
 
         // Draw bottom border
-        let bottom_border_y = total_height - 1;
-        let bottom_border_start_x = settings.margin_left;
-        let bottom_border_end_x = settings.canvas_width - settings.margin_right;
         draw_horizontal_line(
-            bottom_border_start_x,
-            bottom_border_end_x,
+            settings.margin_left,
+            settings.margin_left + table_width,
             bottom_border_y,
             COLOR_TABLE_ROW_BORDER,
             &mut canvas,
@@ -535,153 +1190,235 @@ impl<'a> Renderer<'a> {
             height: total_height,
             canvas,
             breakpoints,
+            links,
+            anchors,
+            first_baseline: rendered_rows.first().and_then(|row| row.first_baseline),
+            text_len: text_offset,
         }
     }
 
+    /// Lays out the row's cells side by side, in the given column widths, with the grid lines
+    /// along its top and sides
     fn render_table_row(
         &mut self,
         row: &TableRow,
+        column_widths: &[u32],
         settings: &BlockRenderSettings,
     ) -> RenderedBlock {
-        let cell_settings: BlockRenderSettings = settings.clone();
-        let mut rendered_cells: Vec<RenderedBlock> = vec![];
+        let table_width: u32 = column_widths.iter().sum();
 
-        info!("Rendering table row with {} cells", row.cells.len());
+        let mut column_xs = vec![];
+        let mut rendered_cells: Vec<(u32, u32, RenderedBlock)> = vec![];
 
-        // First render all cells
-        for (cell_index, cell) in row.cells.iter().enumerate() {
-            let rendered_cell = self.render_table_cell(cell, cell_index, &cell_settings);
-            rendered_cells.push(rendered_cell);
-        }
+        let mut column_x = settings.margin_left;
+        for (column_idx, column_width) in column_widths.iter().enumerate() {
+            column_xs.push(column_x);
 
-        // Stack cells vertically
-        let mut offset_y = 0;
-        let mut breakpoints = vec![];
+            // Rows with fewer cells than the table has columns leave the rest empty
+            if let Some(cell) = row.cells.get(column_idx) {
+                let content_width = column_width.saturating_sub(TABLE_CELL_PADDING_X * 2).max(1);
 
-        // Calculate total height needed for all cells
-        let total_height: u32 = rendered_cells.iter().map(|cell| cell.height).sum();
+                let mut cell_settings = settings.clone();
+                cell_settings.margin_left = column_x + TABLE_CELL_PADDING_X;
+                cell_settings.margin_right = settings
+                    .canvas_width
+                    .saturating_sub(cell_settings.margin_left + content_width);
 
-        let mut canvas = RgbaImage::new(CANVAS_WIDTH, total_height);
+                let rendered_cell = self.render_table_cell(cell, &cell_settings);
+                rendered_cells.push((column_x, *column_width, rendered_cell));
+            }
+
+            column_x += column_width;
+        }
+
+        let content_height = rendered_cells
+            .iter()
+            .map(|(_, _, rendered_cell)| rendered_cell.height)
+            .max()
+            .unwrap_or(0);
+        let height = content_height + TABLE_CELL_PADDING_Y * 2;
+
+        let mut canvas = RgbaImage::new(CANVAS_WIDTH, height);
 
         for pixel in canvas.pixels_mut() {
             *pixel = COLOR_BACKGROUND;
         }
 
-        // Copy each cell's content into the row canvas, stacking vertically
-        for rendered_cell in rendered_cells.iter() {
+        let mut links = vec![];
+        let mut anchors = vec![];
+
+        for (column_x, column_width, rendered_cell) in rendered_cells.iter() {
+            let column_end_x = (column_x + column_width).min(settings.canvas_width);
+
             for y in 0..rendered_cell.height {
-                for x in 0..settings.canvas_width {
-                    let canvas_pixel = canvas.get_pixel_mut(x, y + offset_y);
+                for x in *column_x..column_end_x {
+                    let canvas_pixel = canvas.get_pixel_mut(x, y + TABLE_CELL_PADDING_Y);
                     let cell_pixel = rendered_cell.canvas.get_pixel(x, y);
                     *canvas_pixel = *cell_pixel;
                 }
             }
 
-            // Add breakpoint at the start of each cell
-            breakpoints.push(offset_y);
+            for link in rendered_cell.links.iter() {
+                links.push(link.offset_y(TABLE_CELL_PADDING_Y as i32));
+            }
+            for anchor in rendered_cell.anchors.iter() {
+                anchors.push(anchor.offset_y(TABLE_CELL_PADDING_Y));
+            }
+        }
+
+        // Draw the grid: a line along the top, and one at each column edge
+        draw_horizontal_line(
+            settings.margin_left,
+            settings.margin_left + table_width,
+            0,
+            COLOR_TABLE_ROW_BORDER,
+            &mut canvas,
+        );
+
+        column_xs.push(settings.margin_left + table_width);
+        for (edge_idx, edge_x) in column_xs.iter().enumerate() {
+            let is_outer_edge = edge_idx == 0 || edge_idx == column_xs.len() - 1;
+            let color = if is_outer_edge {
+                COLOR_TABLE_ROW_BORDER
+            } else {
+                COLOR_TABLE_CELL_BORDER
+            };
+
+            draw_vertical_line(*edge_x, 0, height - 1, color, &mut canvas);
+        }
 
-            offset_y += rendered_cell.height;
+        // Rows only split between each other, unless one is too tall to ever fit on a page.
+        // Then it splits between lines, which line up across cells since they share metrics.
+        let mut breakpoints = vec![Breakpoint::new(0, 0)];
+        let max_page_content_height = CANVAS_HEIGHT - CANVAS_MARGIN_TOP - CANVAS_MARGIN_BOTTOM;
+        if height > max_page_content_height {
+            let tallest_cell_idx = rendered_cells
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, (_, _, rendered_cell))| rendered_cell.height)
+                .map(|(cell_idx, _)| cell_idx);
+
+            if let Some(cell_idx) = tallest_cell_idx {
+                let (_, _, rendered_cell) = &rendered_cells[cell_idx];
+                let text_offset: usize = rendered_cells[..cell_idx]
+                    .iter()
+                    .map(|(_, _, rendered_cell)| rendered_cell.text_len)
+                    .sum();
+
+                breakpoints.extend(
+                    rendered_cell
+                        .breakpoints
+                        .iter()
+                        .filter(|breakpoint| breakpoint.y > 0)
+                        .map(|breakpoint| breakpoint.offset(TABLE_CELL_PADDING_Y, text_offset)),
+                );
+            }
         }
 
+        let first_baseline = rendered_cells
+            .iter()
+            .filter_map(|(_, _, rendered_cell)| rendered_cell.first_baseline)
+            .min()
+            .map(|baseline| baseline + TABLE_CELL_PADDING_Y);
+
         RenderedBlock {
-            height: total_height,
+            height,
             canvas,
             breakpoints,
+            links,
+            anchors,
+            first_baseline,
+            text_len: rendered_cells
+                .iter()
+                .map(|(_, _, rendered_cell)| rendered_cell.text_len)
+                .sum(),
         }
     }
 
     fn render_table_cell(
         &mut self,
         cell: &TableCell,
-        cell_index: usize,
         settings: &BlockRenderSettings,
     ) -> RenderedBlock {
-        let child_block = Block::Paragraph {
-            content: cell.content.clone(),
-        };
-        let mut child_render_settings = settings.clone();
-        child_render_settings.margin_left += TABLE_CELL_PADDING_X;
-        child_render_settings.margin_right += TABLE_CELL_PADDING_X;
-        let rendered_child = self.render_block(&child_block, &child_render_settings);
+        let link_destinations = self.set_buffer_spans(&cell.content);
 
-        let height = rendered_child.height;
-        let mut canvas = RgbaImage::new(CANVAS_WIDTH, height);
+        self.render_buffer(&link_destinations, settings)
+    }
 
-        for pixel in canvas.pixels_mut() {
-            *pixel = COLOR_BACKGROUND;
-        }
+    /// Finds how narrow the cell can get without breaking words, and how wide it is
+    /// without wrapping at all
+    fn measure_table_cell(&mut self, cell: &TableCell) -> ColumnMeasure {
+        self.set_buffer_spans(&cell.content);
 
-        for y in 0..height {
-            for x in 0..settings.canvas_width {
-                let canvas_pixel = canvas.get_pixel_mut(x, y);
-                let child_pixel = rendered_child.canvas.get_pixel(x, y);
-                *canvas_pixel = *child_pixel;
-            }
+        self.buffer.set_wrap(self.font_system, Wrap::Word);
+        self.buffer.set_size(self.font_system, Some(1.0), None);
+        let min_width = self.get_widest_layout_run();
+
+        self.buffer.set_wrap(self.font_system, Wrap::WordOrGlyph);
+        self.buffer.set_size(self.font_system, None, None);
+        let max_width = self.get_widest_layout_run();
+
+        ColumnMeasure {
+            min_width: min_width.ceil() as u32 + TABLE_CELL_PADDING_X * 2,
+            max_width: max_width.ceil() as u32 + TABLE_CELL_PADDING_X * 2,
         }
+    }
+
+    fn get_widest_layout_run(&self) -> f32 {
+        self.buffer
+            .layout_runs()
+            .map(|layout_run| layout_run.line_w)
+            .fold(0.0, f32::max)
+    }
+
+    fn render_text_based_block(
+        &mut self,
+        block: &Block,
+        settings: &BlockRenderSettings,
+        follows_paragraph: bool,
+    ) -> RenderedBlock {
+        let indent_paragraphs =
+            self.rendering_settings.typography.paragraph_style == ParagraphStyle::FirstLineIndent;
+        let is_paragraph = matches!(block, Block::Paragraph { .. });
+
+        let indent_first_line = indent_paragraphs && is_paragraph && follows_paragraph;
+        let link_destinations = self.set_buffer_text(block, indent_first_line);
 
-        // Draw horizontal line at top of cell
-        let line_y = 0;
-        let line_start_x = settings.margin_left;
-        let line_end_x = settings.canvas_width - settings.margin_right;
-        let top_line_color = if cell_index == 0 {
-            COLOR_TABLE_ROW_BORDER
+        let rendered_block = self.render_buffer(&link_destinations, settings);
+
+        let spacing_after = if indent_paragraphs && is_paragraph {
+            0
         } else {
-            COLOR_TABLE_CELL_BORDER
+            self.block_spacing()
         };
-        draw_horizontal_line(
-            line_start_x,
-            line_end_x,
-            line_y,
-            top_line_color,
-            &mut canvas,
-        );
-
-        // Draw vertical line at left of cell
-        let line_x = settings.margin_left;
-        let line_start_y = 0;
-        let line_end_y = height - 1;
-        draw_vertical_line(
-            line_x,
-            line_start_y,
-            line_end_y,
-            COLOR_TABLE_ROW_BORDER,
-            &mut canvas,
-        );
-
-        // Draw vertical line at right of cell
-        let line_x = settings.canvas_width - settings.margin_right;
-        let line_start_y = 0;
-        let line_end_y = height - 1;
-        draw_vertical_line(
-            line_x,
-            line_start_y,
-            line_end_y,
-            COLOR_TABLE_ROW_BORDER,
-            &mut canvas,
-        );
 
-        RenderedBlock {
-            height,
-            canvas,
-            breakpoints: vec![0],
-        }
+        add_block_spacing(rendered_block, 0, spacing_after)
     }
 
-    fn render_text_based_block(
+    /// Lays out whatever text is in the buffer to fit within the settings' margins, with the
+    /// buffer's inline images on their lines
+    fn render_buffer(
         &mut self,
-        block: &Block,
+        link_destinations: &[Option<String>],
         settings: &BlockRenderSettings,
     ) -> RenderedBlock {
         let mut breakpoints = vec![];
-
-        self.set_buffer_text(block);
+        let mut links = vec![];
 
         let buffer_width = settings.canvas_width - settings.margin_left - settings.margin_right;
         self.buffer
-            .set_size(&mut self.font_system, Some(buffer_width as f32), None);
+            .set_size(self.font_system, Some(buffer_width as f32), None);
 
         let layout_runs: Vec<LayoutRun> = self.buffer.layout_runs().collect();
 
+        // Where each of the buffer's lines starts in its text, counting the newlines between them
+        let mut line_text_offsets = vec![];
+        let mut text_len = 0;
+        for line in self.buffer.lines.iter() {
+            line_text_offsets.push(text_len);
+            text_len += line.text().len() + 1;
+        }
+
         let rendered_block_height = layout_runs.last().map_or(0, |layout_run| {
             (layout_run.line_top + layout_run.line_height).ceil() as u32
         });
@@ -695,8 +1432,8 @@ impl<'a> Renderer<'a> {
             draw_layout_run(
                 layout_run,
                 0,
-                &mut self.font_system,
-                &mut self.swash_cache,
+                self.font_system,
+                self.swash_cache,
                 COLOR_TEXT,
                 |buffer_x, buffer_y, color| {
                     let canvas_x = buffer_x + settings.margin_left as i32;
@@ -717,14 +1454,28 @@ impl<'a> Renderer<'a> {
                     let fg = Rgba([fg_r, fg_g, fg_b, fg_a]);
 
                     let bg = canvas.get_pixel(canvas_x, canvas_y);
-                    let mut result = bg.clone();
+                    let mut result = *bg;
                     result.blend(&fg);
                     canvas.put_pixel(canvas_x, canvas_y, result);
                 },
             );
+            draw_inline_images(
+                layout_run,
+                &self.buffer_images,
+                settings.margin_left,
+                &mut canvas,
+            );
+
+            links.extend(get_link_regions(
+                layout_run,
+                link_destinations,
+                settings.margin_left,
+            ));
 
             let run_y = layout_run.line_top.round() as u32;
-            breakpoints.push(run_y);
+            let run_text_offset = line_text_offsets[layout_run.line_i]
+                + layout_run.glyphs.first().map_or(0, |glyph| glyph.start);
+            breakpoints.push(Breakpoint::new(run_y, run_text_offset));
 
             if DEBUG_LAYOUT {
                 draw_horizontal_line(
@@ -741,110 +1492,96 @@ impl<'a> Renderer<'a> {
             height: rendered_block_height,
             canvas,
             breakpoints,
+            links,
+            anchors: vec![],
+            first_baseline: layout_runs
+                .first()
+                .map(|layout_run| layout_run.line_y.round() as u32),
+            text_len,
         }
     }
 
-    fn set_buffer_text(&mut self, block: &Block) {
-        let display_scale: f32 = 2.0;
+    /// Lays out the block's text in the buffer, and returns the resolved destinations of its
+    /// links. Link glyphs carry the destination's index + 1 as their metadata.
+    fn set_buffer_text(&mut self, block: &Block, indent_first_line: bool) -> Vec<Option<String>> {
+        let display_scale = DISPLAY_SCALE;
 
         let font_size = self.rendering_settings.font_size as f32;
         let line_height = self.rendering_settings.line_height;
+        let typography = &self.rendering_settings.typography;
 
-        let attrs_default = Attrs::new();
-        let attrs_paragraph =
-            attrs_default.metrics(Metrics::relative(font_size, line_height).scale(display_scale));
-
-        let attrs_heading = attrs_default.clone();
-        let attrs_h1 = attrs_heading
-            .metrics(Metrics::relative(font_size * 2.0, line_height).scale(display_scale));
-        let attrs_h2 = attrs_heading
-            .metrics(Metrics::relative(font_size * 1.5, line_height).scale(display_scale));
-        let attrs_h3 = attrs_heading
-            .metrics(Metrics::relative(font_size * 1.25, line_height).scale(display_scale));
-        let attrs_h4 =
-            attrs_heading.metrics(Metrics::relative(font_size, line_height).scale(display_scale));
-        let attrs_h5 =
-            attrs_heading.metrics(Metrics::relative(font_size, line_height).scale(display_scale));
-        let attrs_h6 =
-            attrs_heading.metrics(Metrics::relative(font_size, line_height).scale(display_scale));
-
-        let attrs_block_quote = attrs_default
-            .style(Style::Italic)
-            .color(Color::rgba(0x99, 0x99, 0x99, 0xFF))
-            .metrics(Metrics::relative(font_size, line_height).scale(display_scale));
+        // A copy, so the attributes don't hold on to self while its images are prepared
+        let body_family = self.body_family.clone();
+        let attrs_default = Attrs::new().family(body_family.as_family());
+        let attrs_paragraph = attrs_default.metrics(self.paragraph_metrics());
 
         let attrs_code_block = attrs_default
             .family(Family::Monospace)
             .color(Color::rgba(0x00, 0x00, 0x00, 0xFF))
             .metrics(Metrics::relative(font_size, line_height).scale(display_scale));
 
+        let paragraph_align = match typography.text_align {
+            TextAlign::Left => None,
+            TextAlign::Right => Some(Align::Right),
+            TextAlign::Center => Some(Align::Center),
+            TextAlign::Justify => Some(Align::Justified),
+        };
+        // Stretching out the few words of a heading looks odd, so they stay ragged
+        let heading_align = match typography.text_align {
+            TextAlign::Justify => None,
+            _ => paragraph_align,
+        };
+
         let mut spans: Vec<(&str, Attrs)> = Vec::new();
+        let mut link_destinations: Vec<Option<String>> = Vec::new();
+        let mut align = None;
 
-        match block {
-            Block::Heading { level, content } => {
-                let attrs_this_heading = match level {
-                    1 => attrs_h1,
-                    2 => attrs_h2,
-                    3 => attrs_h3,
-                    4 => attrs_h4,
-                    5 => attrs_h5,
-                    6 => attrs_h6,
+        let attrs_block = match block {
+            Block::Heading { level, .. } => {
+                let relative_size = match level {
+                    1..=6 => HEADING_SIZES[*level as usize - 1],
                     _ => unreachable!("Invalid heading level"),
                 };
+                let scaled_size = 1.0 + (relative_size - 1.0) * typography.heading_scale;
+                attrs_default.metrics(
+                    Metrics::relative(font_size * scaled_size, line_height).scale(display_scale),
+                )
+            }
+            _ => attrs_paragraph,
+        };
+        self.buffer_images = match block {
+            Block::Heading { content, .. } | Block::Paragraph { content } => {
+                self.prepare_inline_images(content, attrs_block)
+            }
+            _ => vec![],
+        };
+        let buffer_images = &self.buffer_images;
 
-                for span in content.iter() {
-                    // TODO: refactor
-                    match &span {
-                        &Span::Text {
-                            content,
-                            style: span_style,
-                        } => {
-                            let attrs = match span_style {
-                                SpanStyle::Normal => attrs_this_heading,
-                                SpanStyle::Bold => attrs_this_heading.weight(Weight::BOLD),
-                                SpanStyle::Italic => attrs_this_heading.style(Style::Italic),
-                                SpanStyle::BoldItalic => {
-                                    attrs_this_heading.weight(Weight::BOLD).style(Style::Italic)
-                                }
-                                SpanStyle::Code => attrs_this_heading.family(Family::Monospace),
-                            };
-
-                            spans.push((&content, attrs));
-                        }
-                        &Span::Link(link) => {
-                            spans.push((&link.text, attrs_this_heading.color(COLOR_LINK)));
-                        }
-                    }
-                }
-
-                spans.push(("\n\n", attrs_this_heading));
+        match block {
+            Block::Heading { content, .. } => {
+                push_content_spans(
+                    content,
+                    attrs_block,
+                    &self.base_url,
+                    buffer_images,
+                    &mut spans,
+                    &mut link_destinations,
+                );
+                align = heading_align;
             }
             Block::Paragraph { content } => {
-                for span in content.iter() {
-                    // TODO: refactor
-                    match &span {
-                        &Span::Text {
-                            content,
-                            style: span_style,
-                        } => {
-                            let attrs = match span_style {
-                                SpanStyle::Normal => attrs_paragraph,
-                                SpanStyle::Bold => attrs_paragraph.weight(Weight::BOLD),
-                                SpanStyle::Italic => attrs_paragraph.style(Style::Italic),
-                                SpanStyle::BoldItalic => {
-                                    attrs_paragraph.weight(Weight::BOLD).style(Style::Italic)
-                                }
-                                SpanStyle::Code => attrs_paragraph.family(Family::Monospace),
-                            };
-
-                            spans.push((&content, attrs));
-                        }
-                        &Span::Link(link) => {
-                            spans.push((&link.text, attrs_paragraph.color(COLOR_LINK)));
-                        }
-                    }
+                if indent_first_line {
+                    spans.push((FIRST_LINE_INDENT, attrs_paragraph));
                 }
-                spans.push(("\n\n", attrs_paragraph));
+                push_content_spans(
+                    content,
+                    attrs_paragraph,
+                    &self.base_url,
+                    buffer_images,
+                    &mut spans,
+                    &mut link_destinations,
+                );
+                align = paragraph_align;
             }
             Block::List { items: _ } => {
                 unreachable!();
@@ -857,14 +1594,10 @@ impl<'a> Renderer<'a> {
                     spans.push((" Alt text:", attrs_paragraph));
                     spans.push((alt_text, attrs_paragraph));
                 }
-                spans.push(("\n\n", attrs_paragraph));
             }
-            Block::BlockQuote { content: _ } => {
+            Block::BlockQuote { content: _ } | Block::ThematicBreak => {
                 unreachable!();
             }
-            Block::ThematicBreak => {
-                spans.push(("---\n\n", attrs_default));
-            }
             Block::CodeBlock { language, content } => {
                 match language {
                     Some(language) => {
@@ -879,22 +1612,479 @@ impl<'a> Renderer<'a> {
                 spans.push((content, attrs_code_block));
                 spans.push(("\n", attrs_code_block));
                 spans.push(("```", attrs_code_block));
-                spans.push(("\n\n", attrs_code_block));
             }
-            Block::Table { .. } => {
+            Block::Table { .. } | Block::Anchor { .. } => {
                 unreachable!();
             }
         }
 
         self.buffer.set_rich_text(
-            &mut self.font_system,
+            self.font_system,
+            spans.iter().copied(),
+            attrs_default,
+            Shaping::Advanced,
+        );
+        for line in self.buffer.lines.iter_mut() {
+            line.set_align(align);
+        }
+        // Changing the alignment throws away the layout set_rich_text just did
+        self.buffer.shape_until_scroll(self.font_system, false);
+
+        link_destinations
+    }
+
+    /// Lays out a run of spans in the paragraph style
+    fn set_buffer_spans(&mut self, content: &[Span]) -> Vec<Option<String>> {
+        let body_family = self.body_family.clone();
+        let attrs_default = Attrs::new().family(body_family.as_family());
+        let attrs_paragraph = attrs_default.metrics(self.paragraph_metrics());
+
+        self.buffer_images = self.prepare_inline_images(content, attrs_paragraph);
+
+        let mut spans: Vec<(&str, Attrs)> = Vec::new();
+        let mut link_destinations: Vec<Option<String>> = Vec::new();
+
+        push_content_spans(
+            content,
+            attrs_paragraph,
+            &self.base_url,
+            &self.buffer_images,
+            &mut spans,
+            &mut link_destinations,
+        );
+
+        self.buffer.set_rich_text(
+            self.font_system,
             spans.iter().copied(),
             attrs_default,
             Shaping::Advanced,
         );
+
+        link_destinations
+    }
+
+    /// Gets the images in a run of spans ready to sit on its lines: shrunk to the line height,
+    /// with a placeholder as wide as each one. Images that didn't load have no entry.
+    fn prepare_inline_images(
+        &mut self,
+        content: &[Span],
+        attrs: Attrs,
+    ) -> Vec<Option<InlineImage>> {
+        let metrics = attrs
+            .metrics_opt
+            .map(Metrics::from)
+            .unwrap_or(self.paragraph_metrics());
+        let max_height = metrics.line_height.floor().max(1.0) as u32;
+
+        let mut inline_images = vec![];
+        let mut placeholder_width = None;
+
+        for span in content {
+            let Span::Image { url, .. } = span else {
+                continue;
+            };
+            let Some(image) = self.find_image(url) else {
+                inline_images.push(None);
+                continue;
+            };
+
+            let target_width = if image.height() > max_height {
+                (image.width() * max_height).div_ceil(image.height()).max(1)
+            } else {
+                image.width()
+            };
+            let Some(image) = self.prepare_image(url, target_width) else {
+                inline_images.push(None);
+                continue;
+            };
+
+            let placeholder_width = *placeholder_width
+                .get_or_insert_with(|| self.measure_text_width(INLINE_IMAGE_PLACEHOLDER, attrs));
+            let placeholder_count = (image.width() as f32 / placeholder_width).ceil().max(1.0);
+
+            inline_images.push(Some(InlineImage {
+                image,
+                placeholder: INLINE_IMAGE_PLACEHOLDER.repeat(placeholder_count as usize),
+            }));
+        }
+
+        inline_images
+    }
+
+    /// How wide a piece of text is on a single line
+    fn measure_text_width(&mut self, text: &str, attrs: Attrs) -> f32 {
+        let metrics = attrs
+            .metrics_opt
+            .map(Metrics::from)
+            .unwrap_or(self.paragraph_metrics());
+
+        let mut buffer = Buffer::new_empty(metrics);
+        buffer.set_size(self.font_system, None, None);
+        buffer.set_text(self.font_system, text, attrs, Shaping::Advanced);
+
+        buffer
+            .layout_runs()
+            .map(|layout_run| layout_run.line_w)
+            .fold(0.0, f32::max)
+    }
+
+    /// The image at a URL in the page, if it loaded
+    fn find_image(&self, url: &str) -> Option<&'a RgbaImage> {
+        let images: &'a ImagesByUrl = self.images;
+
+        images.get(&try_resolve_url(&self.base_url, url)?)?.as_ref()
+    }
+
+    /// The image at a URL in the page, shrunk to the width it's shown at and ready for the
+    /// panel. Each image is only prepared once for each width.
+    fn prepare_image(&mut self, url: &str, target_width: u32) -> Option<RgbaImage> {
+        let key = (try_resolve_url(&self.base_url, url)?, target_width);
+        if let Some(prepared_image) = self.prepared_images.get(&key) {
+            return Some(prepared_image.clone());
+        }
+
+        let image = self.find_image(url)?;
+        let prepared_image = if image.width() > target_width {
+            prepare_image_for_display(
+                &rescale_image(image, target_width),
+                &self.rendering_settings.images,
+            )
+        } else {
+            prepare_image_for_display(image, &self.rendering_settings.images)
+        };
+
+        self.prepared_images.insert(key, prepared_image.clone());
+        Some(prepared_image)
+    }
+
+    /// Whether a loaded image in a paragraph is too big to shrink onto one of its lines
+    fn is_block_sized_image(&self, url: &str, settings: &BlockRenderSettings) -> bool {
+        let Some(image) = self.find_image(url) else {
+            return false;
+        };
+
+        let max_height = self.paragraph_metrics().line_height * INLINE_IMAGE_MAX_LINES;
+        let content_width = settings.canvas_width - settings.margin_left - settings.margin_right;
+
+        image.height() as f32 > max_height || image.width() > content_width / 2
+    }
+
+    fn has_block_sized_image(&self, content: &[Span], settings: &BlockRenderSettings) -> bool {
+        content.iter().any(|span| match span {
+            Span::Image { url, .. } => self.is_block_sized_image(url, settings),
+            _ => false,
+        })
+    }
+
+    fn paragraph_metrics(&self) -> Metrics {
+        let font_size = self.rendering_settings.font_size as f32;
+        let line_height = self.rendering_settings.line_height;
+
+        Metrics::relative(font_size, line_height).scale(DISPLAY_SCALE)
+    }
+
+    /// The gap after a paragraph, which most other blocks share
+    fn block_spacing(&self) -> u32 {
+        let paragraph_spacing = self.rendering_settings.typography.paragraph_spacing;
+
+        (self.paragraph_metrics().line_height * paragraph_spacing.max(0.0)).ceil() as u32
+    }
+}
+
+fn render_anchor_block(id: &str) -> RenderedBlock {
+    RenderedBlock {
+        height: 0,
+        canvas: RgbaImage::new(CANVAS_WIDTH, 0),
+        breakpoints: vec![],
+        links: vec![],
+        anchors: vec![AnchorPosition {
+            id: id.to_string(),
+            y: 0,
+        }],
+        first_baseline: None,
+        text_len: 0,
+    }
+}
+
+/// The first baseline of a stack of blocks, skipping anchors since they have no lines
+fn first_baseline(rendered_children: &[RenderedBlock]) -> Option<u32> {
+    rendered_children
+        .iter()
+        .find(|child| child.height > 0)
+        .and_then(|child| child.first_baseline)
+}
+
+/// Adds blank space above and below a rendered block. The space above sticks to the block's
+/// first segment, and the space below to its last, so neither ends up alone on a page.
+fn add_block_spacing(
+    block: RenderedBlock,
+    spacing_before: u32,
+    spacing_after: u32,
+) -> RenderedBlock {
+    if spacing_before == 0 && spacing_after == 0 {
+        return block;
+    }
+
+    let height = spacing_before + block.height + spacing_after;
+    let mut canvas = create_blank_canvas(CANVAS_WIDTH, height, COLOR_BACKGROUND);
+    copy_block_to_page_canvas(
+        &block.canvas,
+        &mut canvas,
+        Point2::new(0, 0),
+        Point2::new(CANVAS_WIDTH - 1, block.height),
+        spacing_before as i32,
+    );
+
+    let mut breakpoints: Vec<Breakpoint> = block
+        .breakpoints
+        .iter()
+        .map(|breakpoint| breakpoint.offset(spacing_before, 0))
+        .collect();
+    if let Some(first_breakpoint) = breakpoints.first_mut() {
+        first_breakpoint.y = 0;
+    }
+
+    RenderedBlock {
+        height,
+        canvas,
+        breakpoints,
+        links: block
+            .links
+            .iter()
+            .map(|link| link.offset_y(spacing_before as i32))
+            .collect(),
+        anchors: block
+            .anchors
+            .iter()
+            .map(|anchor| anchor.offset_y(spacing_before))
+            .collect(),
+        first_baseline: block
+            .first_baseline
+            .map(|baseline| baseline + spacing_before),
+        text_len: block.text_len,
+    }
+}
+
+/// Adds a paragraph of the spans, without the whitespace left at its edges where an image
+/// was taken out. Nothing is added if there's no text left.
+fn push_trimmed_paragraph(blocks: &mut Vec<Block>, mut content: Vec<Span>) {
+    if let Some(Span::Text { content, .. }) = content.first_mut() {
+        *content = content.trim_start().to_string();
+    }
+    if let Some(Span::Text { content, .. }) = content.last_mut() {
+        *content = content.trim_end().to_string();
+    }
+    content.retain(|span| !matches!(span, Span::Text { content, .. } if content.is_empty()));
+
+    if !content.is_empty() {
+        blocks.push(Block::Paragraph { content });
+    }
+}
+
+/// Adds the spans' text to lay out. Images take the place of their placeholder, or their alt
+/// text if they didn't load.
+fn push_content_spans<'a>(
+    content: &'a [Span],
+    base_attrs: Attrs<'a>,
+    base_url: &str,
+    inline_images: &'a [Option<InlineImage>],
+    spans: &mut Vec<(&'a str, Attrs<'a>)>,
+    link_destinations: &mut Vec<Option<String>>,
+) {
+    let mut image_idx = 0;
+
+    for span in content.iter() {
+        match span {
+            Span::Text {
+                content,
+                style: span_style,
+            } => {
+                let attrs = match span_style {
+                    SpanStyle::Normal => base_attrs,
+                    SpanStyle::Bold => base_attrs.weight(Weight::BOLD),
+                    SpanStyle::Italic => base_attrs.style(Style::Italic),
+                    SpanStyle::BoldItalic => base_attrs.weight(Weight::BOLD).style(Style::Italic),
+                    SpanStyle::Code => base_attrs.family(Family::Monospace),
+                };
+
+                spans.push((content, attrs));
+            }
+            Span::Link(link) => {
+                link_destinations.push(get_link_destination(base_url, &link.destination));
+                let attrs = base_attrs
+                    .color(COLOR_LINK)
+                    .metadata(link_destinations.len());
+
+                spans.push((&link.text, attrs));
+            }
+            Span::Image { alt_text, .. } => {
+                match inline_images.get(image_idx).and_then(Option::as_ref) {
+                    Some(inline_image) => {
+                        let attrs = base_attrs.metadata(INLINE_IMAGE_METADATA + image_idx);
+                        spans.push((&inline_image.placeholder, attrs));
+                    }
+                    None => {
+                        if let Some(alt_text) = alt_text {
+                            spans.push((alt_text, base_attrs));
+                        }
+                    }
+                }
+                image_idx += 1;
+            }
+        }
+    }
+}
+
+/// Draws the inline images on a layout run, centered on their placeholders
+fn draw_inline_images(
+    run: &LayoutRun,
+    inline_images: &[Option<InlineImage>],
+    margin_left: u32,
+    canvas: &mut RgbaImage,
+) {
+    for glyphs in run.glyphs.chunk_by(|a, b| a.metadata == b.metadata) {
+        let inline_image = glyphs[0]
+            .metadata
+            .checked_sub(INLINE_IMAGE_METADATA)
+            .and_then(|image_idx| inline_images.get(image_idx))
+            .and_then(Option::as_ref);
+        let Some(InlineImage { image, .. }) = inline_image else {
+            continue;
+        };
+
+        let left_x = glyphs.iter().map(|glyph| glyph.x).fold(f32::MAX, f32::min);
+        let right_x = glyphs
+            .iter()
+            .map(|glyph| glyph.x + glyph.w)
+            .fold(f32::MIN, f32::max);
+
+        let x = margin_left as f32 + (left_x + right_x - image.width() as f32) / 2.0;
+        let y = run.line_top + (run.line_height - image.height() as f32) / 2.0;
+
+        image::imageops::overlay(canvas, image, x.round() as i64, y.round() as i64);
+    }
+}
+
+/// Only links we can actually navigate to are kept; others (mailto:, javascript:, ...)
+/// are still drawn, but not tappable.
+fn get_link_destination(base_url: &str, href: &str) -> Option<String> {
+    let destination = try_resolve_url(base_url, href)?;
+
+    if destination.starts_with("http://") || destination.starts_with("https://") {
+        Some(destination)
+    } else {
+        None
     }
 }
 
+/// Finds the areas covered by each link within a layout run, in block canvas coordinates
+fn get_link_regions(
+    run: &LayoutRun,
+    link_destinations: &[Option<String>],
+    margin_left: u32,
+) -> Vec<LinkRegion> {
+    let mut regions: Vec<LinkRegion> = vec![];
+    let mut previous_metadata = 0;
+
+    let top_y = run.line_top.max(0.0).floor() as u32;
+    let bottom_y = (run.line_top + run.line_height).ceil() as u32;
+
+    for glyph in run.glyphs.iter() {
+        let metadata = glyph.metadata;
+        let destination = match metadata.checked_sub(1) {
+            Some(link_idx) => link_destinations.get(link_idx).cloned().flatten(),
+            None => None,
+        };
+
+        let left_x = margin_left + glyph.x.max(0.0).floor() as u32;
+        let right_x = margin_left + (glyph.x + glyph.w).max(0.0).ceil() as u32;
+
+        match (destination, regions.last_mut()) {
+            (Some(_), Some(region)) if metadata == previous_metadata => {
+                // Continuation of the same link, so extend its region
+                region.top_left.x = region.top_left.x.min(left_x);
+                region.bottom_right.x = region.bottom_right.x.max(right_x);
+            }
+            (Some(destination), _) => {
+                regions.push(LinkRegion {
+                    top_left: Point2::new(left_x, top_y),
+                    bottom_right: Point2::new(right_x, bottom_y),
+                    destination,
+                });
+            }
+            (None, _) => {}
+        }
+
+        previous_metadata = metadata;
+    }
+
+    regions
+}
+
+/// Splits the table's width between its columns. Tables always span the available width:
+/// when everything fits, the spare room is shared in proportion to each column's content.
+/// When it doesn't, each column gets its longest word plus a share of what's left.
+fn distribute_column_widths(columns: &[ColumnMeasure], available_width: u32) -> Vec<u32> {
+    if columns.is_empty() {
+        return vec![];
+    }
+
+    let total_min_width: u32 = columns.iter().map(|column| column.min_width).sum();
+    let total_max_width: u32 = columns.iter().map(|column| column.max_width).sum();
+
+    let share = |amount: u32, part: u32, total: u32| -> u32 {
+        (amount as u64 * part as u64 / total as u64) as u32
+    };
+
+    let mut widths: Vec<u32> = if total_max_width == 0 {
+        vec![available_width / columns.len() as u32; columns.len()]
+    } else if total_max_width <= available_width {
+        let spare_width = available_width - total_max_width;
+        columns
+            .iter()
+            .map(|column| column.max_width + share(spare_width, column.max_width, total_max_width))
+            .collect()
+    } else if total_min_width <= available_width {
+        let spare_width = available_width - total_min_width;
+        let total_flexible_width = total_max_width - total_min_width;
+        columns
+            .iter()
+            .map(|column| {
+                let flexible_width = column.max_width - column.min_width;
+                column.min_width + share(spare_width, flexible_width, total_flexible_width)
+            })
+            .collect()
+    } else {
+        // Not even the longest words fit, so every column shrinks and words get broken up
+        columns
+            .iter()
+            .map(|column| share(available_width, column.min_width, total_min_width))
+            .collect()
+    };
+
+    // Rounding down leaves a few pixels over, which go to the last column
+    let used_width: u32 = widths.iter().sum();
+    if let Some(last_width) = widths.last_mut() {
+        *last_width += available_width.saturating_sub(used_width);
+    }
+
+    widths
+}
+
+/// Moves the links that fall within a block segment into page coordinates
+fn links_in_segment(
+    links: &[LinkRegion],
+    segment_top_y: u32,
+    segment_bottom_y: u32,
+    offset_y: i32,
+) -> Vec<LinkRegion> {
+    links
+        .iter()
+        .filter(|link| link.top_left.y >= segment_top_y && link.top_left.y < segment_bottom_y)
+        .map(|link| link.offset_y(offset_y))
+        .collect()
+}
+
 pub fn draw_layout_run<F>(
     run: &LayoutRun,
     offset_y: i32,
@@ -969,3 +2159,532 @@ fn copy_block_to_page_canvas(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::browser_core::parsing::Link;
+    use crate::fonts::build_font_system;
+    use crate::settings::{Settings, TypographySettings};
+
+    const TEST_BLOCK_SETTINGS: BlockRenderSettings = BlockRenderSettings {
+        canvas_width: CANVAS_WIDTH,
+        margin_left: 100,
+        margin_right: 100,
+        list_depth: 0,
+    };
+
+    fn render_document(renderer: &mut Renderer, document: &Document) -> Vec<RenderedPage> {
+        let mut layout = DocumentLayout::new();
+        let mut pages = vec![];
+
+        while !layout.is_complete() {
+            renderer.render_next_pages(document, &mut layout, &mut pages);
+        }
+
+        pages
+    }
+
+    fn paragraph(text: &str) -> Block {
+        Block::Paragraph {
+            content: vec![Span::Text {
+                content: text.to_string(),
+                style: SpanStyle::Normal,
+            }],
+        }
+    }
+
+    /// The x of the leftmost and rightmost ink in the rows of a block's first line
+    fn first_line_ink_extent(rendered: &RenderedBlock) -> (u32, u32) {
+        let baseline = rendered.first_baseline.unwrap();
+        let mut extent = (u32::MAX, 0);
+        for y in baseline.saturating_sub(10)..baseline {
+            for x in 0..rendered.canvas.width() {
+                if rendered.canvas.get_pixel(x, y).0[0] < 0x80 {
+                    extent = (extent.0.min(x), extent.1.max(x));
+                }
+            }
+        }
+        extent
+    }
+
+    #[test]
+    fn test_link_regions() {
+        let settings = Settings::default();
+        let mut font_system = build_font_system(&settings.rendering);
+        let mut prepared_images = PreparedImages::new();
+        let mut swash_cache = SwashCache::new();
+        let images = ImagesByUrl::new();
+        let mut renderer = Renderer::new(
+            &settings.rendering,
+            "https://example.com/articles/",
+            &images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+
+        let document = Document {
+            blocks: vec![Block::Paragraph {
+                content: vec![
+                    Span::Text {
+                        content: "Read the ".to_string(),
+                        style: SpanStyle::Normal,
+                    },
+                    Span::Link(Link {
+                        destination: "next-article".to_string(),
+                        text: "next article".to_string(),
+                    }),
+                    Span::Text {
+                        content: " or send an ".to_string(),
+                        style: SpanStyle::Normal,
+                    },
+                    Span::Link(Link {
+                        destination: "mailto:someone@example.com".to_string(),
+                        text: "email".to_string(),
+                    }),
+                ],
+            }],
+            ..Default::default()
+        };
+
+        let pages = render_document(&mut renderer, &document);
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].links.len(), 1);
+
+        let link = &pages[0].links[0];
+        assert_eq!(
+            link.destination,
+            "https://example.com/articles/next-article"
+        );
+        assert!(link.top_left.x > settings.rendering.screen_margin_x);
+        assert!(link.top_left.y >= CANVAS_MARGIN_TOP);
+        assert!(link.bottom_right.x > link.top_left.x);
+        assert!(link.bottom_right.y > link.top_left.y);
+        assert!(!link.contains(settings.rendering.screen_margin_x, link.top_left.y));
+    }
+
+    #[test]
+    fn test_inline_images() {
+        let settings = Settings::default();
+        let mut font_system = build_font_system(&settings.rendering);
+        let mut prepared_images = PreparedImages::new();
+        let mut swash_cache = SwashCache::new();
+        let mut images = ImagesByUrl::new();
+        images.insert(
+            "https://example.com/icon.png".to_string(),
+            Some(RgbaImage::from_pixel(40, 40, Rgba([0, 0, 0, 0xFF]))),
+        );
+        images.insert(
+            "https://example.com/photo.png".to_string(),
+            Some(RgbaImage::from_pixel(600, 400, Rgba([0, 0, 0, 0xFF]))),
+        );
+        let mut renderer = Renderer::new(
+            &settings.rendering,
+            "https://example.com/",
+            &images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+
+        let text = |content: &str| Span::Text {
+            content: content.to_string(),
+            style: SpanStyle::Normal,
+        };
+        let image = |url: &str| Span::Image {
+            url: url.to_string(),
+            alt_text: None,
+        };
+
+        let text_only = renderer.render_block(&paragraph("Press to save."), &TEST_BLOCK_SETTINGS);
+
+        let with_icon = renderer.render_block(
+            &Block::Paragraph {
+                content: vec![text("Press "), image("icon.png"), text(" to save.")],
+            },
+            &TEST_BLOCK_SETTINGS,
+        );
+        assert_eq!(with_icon.height, text_only.height);
+        assert!(first_line_ink_extent(&with_icon).1 > first_line_ink_extent(&text_only).1);
+
+        let with_photo = renderer.render_block(
+            &Block::Paragraph {
+                content: vec![text("Before "), image("photo.png"), text(" after.")],
+            },
+            &TEST_BLOCK_SETTINGS,
+        );
+        assert!(with_photo.height > 400 + text_only.height);
+        assert_eq!(with_photo.text_len, "Before".len() + 1 + "after.".len() + 1);
+    }
+
+    #[test]
+    fn test_anchor_pages() {
+        let settings = Settings::default();
+        let mut font_system = build_font_system(&settings.rendering);
+        let mut prepared_images = PreparedImages::new();
+        let mut swash_cache = SwashCache::new();
+        let images = ImagesByUrl::new();
+        let mut renderer = Renderer::new(
+            &settings.rendering,
+            "https://example.com/",
+            &images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+
+        let anchor = |id: &str| Block::Anchor { id: id.to_string() };
+        let mut blocks = vec![anchor("top-of-page"), paragraph("Introduction")];
+        for _ in 0..40 {
+            blocks.push(paragraph(
+                &"Filler text to push the next anchor down. ".repeat(8),
+            ));
+        }
+        blocks.push(anchor("conclusion"));
+        blocks.push(paragraph("Conclusion"));
+        blocks.push(anchor("end"));
+
+        let pages = render_document(
+            &mut renderer,
+            &Document {
+                blocks,
+                ..Default::default()
+            },
+        );
+
+        assert!(pages.len() > 1);
+        assert_eq!(pages[0].anchors, vec!["top-of-page".to_string()]);
+        let last_page = pages.last().unwrap();
+        assert_eq!(
+            last_page.anchors,
+            vec!["conclusion".to_string(), "end".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_links_in_segment() {
+        let link_at = |y: u32| LinkRegion {
+            top_left: Point2::new(10, y),
+            bottom_right: Point2::new(50, y + 20),
+            destination: "https://example.com".to_string(),
+        };
+        let links = vec![link_at(0), link_at(40), link_at(80)];
+
+        let segment_links = links_in_segment(&links, 40, 80, 100);
+
+        assert_eq!(segment_links, vec![link_at(140)]);
+    }
+
+    #[test]
+    fn test_distribute_column_widths() {
+        let column = |min_width, max_width| ColumnMeasure {
+            min_width,
+            max_width,
+        };
+
+        // Everything fits, so the spare room is shared in proportion to the content
+        assert_eq!(
+            distribute_column_widths(&[column(50, 100), column(50, 300)], 800),
+            vec![200, 600]
+        );
+
+        // Long cells wrap, and flexible columns grow from their minimums
+        assert_eq!(
+            distribute_column_widths(&[column(100, 100), column(100, 1000)], 600),
+            vec![100, 500]
+        );
+
+        // Not even the longest words fit
+        assert_eq!(
+            distribute_column_widths(&[column(300, 400), column(600, 900)], 600),
+            vec![200, 400]
+        );
+
+        // Rounding leftovers go to the last column
+        assert_eq!(
+            distribute_column_widths(&[column(0, 1), column(0, 1), column(0, 1)], 100),
+            vec![33, 33, 34]
+        );
+
+        assert_eq!(distribute_column_widths(&[], 100), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_table_cells_side_by_side() {
+        let settings = Settings::default().rendering;
+        let mut font_system = build_font_system(&settings);
+        let mut prepared_images = PreparedImages::new();
+        let mut swash_cache = SwashCache::new();
+        let images = ImagesByUrl::new();
+        let mut renderer = Renderer::new(
+            &settings,
+            "https://example.com",
+            &images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+
+        let link_cell = |destination: &str| TableCell {
+            content: vec![Span::Link(Link {
+                destination: destination.to_string(),
+                text: "Link".to_string(),
+            })],
+        };
+        let rows = vec![TableRow {
+            cells: vec![link_cell("/left"), link_cell("/right")],
+        }];
+
+        let rendered = renderer.render_block(
+            &Block::Table { rows },
+            &BlockRenderSettings {
+                canvas_width: CANVAS_WIDTH,
+                margin_left: 100,
+                margin_right: 100,
+                list_depth: 0,
+            },
+        );
+
+        assert_eq!(rendered.breakpoints, vec![Breakpoint::new(0, 0)]);
+        assert_eq!(rendered.links.len(), 2);
+
+        let left = &rendered.links[0];
+        let right = &rendered.links[1];
+        assert_eq!(left.destination, "https://example.com/left");
+        assert_eq!(right.destination, "https://example.com/right");
+        assert_eq!(left.top_left.y, right.top_left.y);
+        assert!(right.top_left.x > left.bottom_right.x);
+    }
+
+    #[test]
+    fn test_paragraph_styles() {
+        let mut settings = Settings::default().rendering;
+        let mut font_system = build_font_system(&settings);
+        let mut prepared_images = PreparedImages::new();
+        let mut swash_cache = SwashCache::new();
+        let images = ImagesByUrl::new();
+
+        let mut renderer = Renderer::new(
+            &settings,
+            "https://example.com",
+            &images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+        let line_height = renderer.paragraph_metrics().line_height.ceil() as u32;
+        let first = renderer.render_block(&paragraph("First"), &TEST_BLOCK_SETTINGS);
+        let second = renderer.render_block(&paragraph("Second"), &TEST_BLOCK_SETTINGS);
+
+        // A line of text, then a line's worth of gap
+        assert_eq!(first.height, line_height * 2);
+        assert_eq!(
+            first_line_ink_extent(&first).0,
+            first_line_ink_extent(&second).0
+        );
+
+        settings.typography = TypographySettings {
+            paragraph_style: ParagraphStyle::FirstLineIndent,
+            ..TypographySettings::default()
+        };
+        let mut renderer = Renderer::new(
+            &settings,
+            "https://example.com",
+            &images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+        let first = renderer.render_block(&paragraph("First"), &TEST_BLOCK_SETTINGS);
+        let second = renderer.render_block(&paragraph("Second"), &TEST_BLOCK_SETTINGS);
+        let heading = renderer.render_block(
+            &Block::Heading {
+                level: 4,
+                content: vec![Span::Text {
+                    content: "Heading".to_string(),
+                    style: SpanStyle::Normal,
+                }],
+            },
+            &TEST_BLOCK_SETTINGS,
+        );
+
+        // No gap between paragraphs, but the second one is indented
+        assert_eq!(first.height, line_height);
+        assert!(first_line_ink_extent(&second).0 > first_line_ink_extent(&first).0 + 20);
+
+        // Anything else after a paragraph gets the gap above it instead
+        assert_eq!(heading.height, line_height * 3);
+        assert_eq!(heading.breakpoints, vec![Breakpoint::new(0, 0)]);
+    }
+
+    #[test]
+    fn test_justified_text() {
+        let mut settings = Settings::default().rendering;
+        settings.typography.text_align = TextAlign::Justify;
+        let mut font_system = build_font_system(&settings);
+        let mut prepared_images = PreparedImages::new();
+        let mut swash_cache = SwashCache::new();
+        let images = ImagesByUrl::new();
+        let mut renderer = Renderer::new(
+            &settings,
+            "https://example.com",
+            &images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+
+        let rendered = renderer.render_block(
+            &paragraph(&"Justified lines reach all the way across. ".repeat(10)),
+            &TEST_BLOCK_SETTINGS,
+        );
+
+        let (left_x, right_x) = first_line_ink_extent(&rendered);
+        assert!(left_x <= TEST_BLOCK_SETTINGS.margin_left + 4);
+        assert!(right_x >= CANVAS_WIDTH - TEST_BLOCK_SETTINGS.margin_right - 4);
+    }
+
+    #[test]
+    fn test_page_start_positions() {
+        let long_paragraph = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(12);
+        let document = Document {
+            blocks: (0..10).map(|_| paragraph(&long_paragraph)).collect(),
+            ..Default::default()
+        };
+
+        let mut settings = Settings::default().rendering;
+        let mut font_system = build_font_system(&settings);
+        let mut prepared_images = PreparedImages::new();
+        let mut swash_cache = SwashCache::new();
+        let images = ImagesByUrl::new();
+
+        let mut renderer = Renderer::new(
+            &settings,
+            "https://example.com",
+            &images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+        let small_pages = render_document(&mut renderer, &document);
+
+        settings.font_size = 20;
+        let mut renderer = Renderer::new(
+            &settings,
+            "https://example.com",
+            &images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+        let large_pages = render_document(&mut renderer, &document);
+
+        assert!(large_pages.len() > small_pages.len());
+        assert_eq!(small_pages[0].start_position, SourcePosition::default());
+        for pages in [&small_pages, &large_pages] {
+            assert!(pages
+                .windows(2)
+                .all(|pair| pair[0].start_position < pair[1].start_position));
+        }
+
+        // Pages split paragraphs between lines, not just between blocks
+        assert!(small_pages
+            .iter()
+            .any(|page| page.start_position.text_offset > 0));
+
+        for small_page in small_pages.iter() {
+            let position = small_page.start_position;
+            let page_idx = find_page_for_position(&large_pages, &position);
+
+            assert!(large_pages[page_idx].start_position <= position);
+            if let Some(next_page) = large_pages.get(page_idx + 1) {
+                assert!(next_page.start_position > position);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rewind_layout() {
+        let long_paragraph = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(12);
+        let mut blocks: Vec<Block> = (0..10).map(|_| paragraph(&long_paragraph)).collect();
+        blocks.insert(
+            6,
+            Block::Image {
+                url: "https://example.com/photo.png".to_string(),
+                alt_text: None,
+            },
+        );
+        blocks.insert(
+            8,
+            Block::Anchor {
+                id: "later".to_string(),
+            },
+        );
+        let document = Document {
+            blocks,
+            ..Default::default()
+        };
+
+        let settings = Settings::default().rendering;
+        let mut font_system = build_font_system(&settings);
+        let mut prepared_images = PreparedImages::new();
+        let mut swash_cache = SwashCache::new();
+        let no_images = ImagesByUrl::new();
+        let mut images = ImagesByUrl::new();
+        images.insert(
+            "https://example.com/photo.png".to_string(),
+            Some(RgbaImage::from_pixel(
+                600,
+                900,
+                Rgba([0x66, 0x66, 0x66, 0xFF]),
+            )),
+        );
+
+        let mut layout = DocumentLayout::new();
+        let mut pages = vec![];
+        let mut renderer = Renderer::new(
+            &settings,
+            "https://example.com",
+            &no_images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+        while !layout.is_complete() {
+            renderer.render_next_pages(&document, &mut layout, &mut pages);
+        }
+
+        assert!(!layout.rewind_to_block(20));
+        assert!(layout.rewind_to_block(6));
+        let mut renderer = Renderer::new(
+            &settings,
+            "https://example.com",
+            &images,
+            &mut prepared_images,
+            &mut font_system,
+            &mut swash_cache,
+        );
+        let first_page_idxs = renderer.render_next_pages(&document, &mut layout, &mut pages);
+        assert!(first_page_idxs.start > 0);
+        while !layout.is_complete() {
+            renderer.render_next_pages(&document, &mut layout, &mut pages);
+        }
+
+        // The same as laying it out with the image from the start
+        let expected_pages = render_document(&mut renderer, &document);
+        assert_eq!(pages.len(), expected_pages.len());
+        for (page, expected_page) in pages.iter().zip(expected_pages.iter()) {
+            assert_eq!(page.start_position, expected_page.start_position);
+            assert_eq!(page.anchors, expected_page.anchors);
+            assert!(page.canvas == expected_page.canvas);
+        }
+
+        // Both layouts used the one prepared copy of the image
+        assert_eq!(
+            prepared_images.keys().collect::<Vec<_>>(),
+            vec![&("https://example.com/photo.png".to_string(), 600)]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use crate::browser_core::parsing::{Block, Document, DocumentMetadata, Span, SpanStyle};
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Blocks laid out above the article: the site name, the title, and who wrote it and when.
+/// A page that starts with its own main heading keeps it as the title, so each block comes
+/// with the index of the document block it was taken from, if any. A page with the title
+/// in a heading further down keeps it there, and only gets the site name and byline.
+pub struct ArticleHeader {
+    pub blocks: Vec<(Option<usize>, Block)>,
+    // How many blocks at the start of the document are already in the header
+    pub document_blocks_used: usize,
+}
+
+impl ArticleHeader {
+    pub fn new(document: &Document) -> Self {
+        let metadata = &document.metadata;
+        let mut blocks = vec![];
+        let mut document_blocks_used = 0;
+
+        if let Some(site_name) = &metadata.site_name {
+            blocks.push((None, paragraph(site_name, SpanStyle::Bold)));
+        }
+
+        // Anchors in front of the heading, like its id, come along with it
+        let first_content_idx = document
+            .blocks
+            .iter()
+            .position(|block| !matches!(block, Block::Anchor { .. }));
+        match first_content_idx.map(|block_idx| (block_idx, &document.blocks[block_idx])) {
+            Some((heading_idx, Block::Heading { level: 1, .. })) => {
+                for block_idx in 0..=heading_idx {
+                    blocks.push((Some(block_idx), document.blocks[block_idx].clone()));
+                }
+                document_blocks_used = heading_idx + 1;
+            }
+            _ => {
+                let has_title_heading = |title: &str| {
+                    document.blocks.iter().any(|block| match block {
+                        Block::Heading { content, .. } => {
+                            is_same_title(&spans_text(content), title)
+                        }
+                        _ => false,
+                    })
+                };
+
+                if let Some(title) = metadata
+                    .title
+                    .as_ref()
+                    .filter(|title| !has_title_heading(title))
+                {
+                    blocks.push((
+                        None,
+                        Block::Heading {
+                            level: 1,
+                            content: vec![text_span(title, SpanStyle::Normal)],
+                        },
+                    ));
+                }
+            }
+        }
+
+        let byline = format_byline(metadata);
+        if let Some(byline) = &byline {
+            blocks.push((None, paragraph(byline, SpanStyle::Italic)));
+        }
+
+        // Nothing to add to what the page already shows
+        if blocks.len() == document_blocks_used {
+            return Self {
+                blocks: vec![],
+                document_blocks_used: 0,
+            };
+        }
+
+        if metadata.site_name.is_some() || byline.is_some() {
+            blocks.push((None, Block::ThematicBreak));
+        }
+
+        Self {
+            blocks,
+            document_blocks_used,
+        }
+    }
+}
+
+/// Who wrote the article and when, like "Jane Doe · May 1, 2024"
+fn format_byline(metadata: &DocumentMetadata) -> Option<String> {
+    let published = metadata.published.as_deref().map(format_date);
+    let parts: Vec<&str> = [metadata.byline.as_deref(), published.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect();
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" · "))
+    }
+}
+
+/// Spells out ISO 8601 dates, like "2024-05-01T09:30:00Z". Anything else is shown as the
+/// page wrote it.
+fn format_date(date: &str) -> String {
+    let parse_part = |range: std::ops::Range<usize>| {
+        date.get(range)
+            .filter(|part| part.chars().all(|character| character.is_ascii_digit()))
+            .and_then(|part| part.parse::<usize>().ok())
+    };
+
+    let is_iso_date = date.get(4..5) == Some("-") && date.get(7..8) == Some("-");
+    match (
+        is_iso_date,
+        parse_part(0..4),
+        parse_part(5..7),
+        parse_part(8..10),
+    ) {
+        (true, Some(year), Some(month @ 1..=12), Some(day @ 1..=31)) => {
+            format!("{} {}, {}", MONTH_NAMES[month - 1], day, year)
+        }
+        _ => date.to_string(),
+    }
+}
+
+/// Whether a heading shows the page's title. Titles often have the site name after them,
+/// like "Foo – Site Name", so a heading with just the part before that counts too.
+fn is_same_title(heading: &str, title: &str) -> bool {
+    let normalize = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let heading = normalize(heading).to_lowercase();
+    let title = normalize(title).to_lowercase();
+
+    if heading.is_empty() {
+        return false;
+    }
+
+    match title.strip_prefix(&heading) {
+        Some(rest) => {
+            let rest = rest.trim_start();
+            rest.is_empty() || rest.starts_with(['-', '–', '—', '|', '·', ':', '»'])
+        }
+        None => false,
+    }
+}
+
+fn spans_text(spans: &[Span]) -> String {
+    spans
+        .iter()
+        .map(|span| match span {
+            Span::Text { content, .. } => content.as_str(),
+            Span::Link(link) => link.text.as_str(),
+            Span::Image { .. } => "",
+        })
+        .collect()
+}
+
+fn paragraph(text: &str, style: SpanStyle) -> Block {
+    Block::Paragraph {
+        content: vec![text_span(text, style)],
+    }
+}
+
+fn text_span(text: &str, style: SpanStyle) -> Span {
+    Span::Text {
+        content: text.to_string(),
+        style,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date("2024-05-01T09:30:00+00:00"), "May 1, 2024");
+        assert_eq!(format_date("1999-12-31"), "December 31, 1999");
+        assert_eq!(format_date("2024-13-01"), "2024-13-01");
+        assert_eq!(format_date("Tuesday"), "Tuesday");
+    }
+
+    #[test]
+    fn test_leading_heading_becomes_the_title() {
+        let document = Document {
+            blocks: vec![
+                Block::Heading {
+                    level: 1,
+                    content: vec![text_span("From the page", SpanStyle::Normal)],
+                },
+                paragraph("Body text", SpanStyle::Normal),
+            ],
+            metadata: DocumentMetadata {
+                title: Some("From the metadata".to_string()),
+                byline: Some("Jane Doe".to_string()),
+                ..Default::default()
+            },
+        };
+
+        let header = ArticleHeader::new(&document);
+
+        assert_eq!(header.document_blocks_used, 1);
+        assert_eq!(
+            header.blocks,
+            vec![
+                (Some(0), document.blocks[0].clone()),
+                (None, paragraph("Jane Doe", SpanStyle::Italic)),
+                (None, Block::ThematicBreak),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_title_heading_further_down() {
+        let document = Document {
+            blocks: vec![
+                paragraph("Skip to content", SpanStyle::Normal),
+                Block::Heading {
+                    level: 1,
+                    content: vec![text_span("Foo", SpanStyle::Normal)],
+                },
+            ],
+            metadata: DocumentMetadata {
+                title: Some("Foo – Site Name".to_string()),
+                site_name: Some("Site Name".to_string()),
+                ..Default::default()
+            },
+        };
+
+        let header = ArticleHeader::new(&document);
+
+        assert_eq!(header.document_blocks_used, 0);
+        assert_eq!(
+            header.blocks,
+            vec![
+                (None, paragraph("Site Name", SpanStyle::Bold)),
+                (None, Block::ThematicBreak),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_same_title() {
+        assert!(is_same_title("Foo", "Foo"));
+        assert!(is_same_title(" foo\n bar ", "Foo Bar"));
+        assert!(is_same_title("Foo", "Foo – Site Name"));
+        assert!(is_same_title("Foo", "Foo | Site Name"));
+        assert!(!is_same_title("Foo", "Football"));
+        assert!(!is_same_title("Bar", "Foo – Bar"));
+        assert!(!is_same_title("", "Foo"));
+    }
+
+    #[test]
+    fn test_nothing_to_add() {
+        let document = Document {
+            blocks: vec![Block::Heading {
+                level: 1,
+                content: vec![text_span("Title", SpanStyle::Normal)],
+            }],
+            metadata: DocumentMetadata {
+                title: Some("Title".to_string()),
+                ..Default::default()
+            },
+        };
+
+        let header = ArticleHeader::new(&document);
+
+        assert!(header.blocks.is_empty());
+        assert_eq!(header.document_blocks_used, 0);
+    }
+}
@@ -3,7 +3,8 @@ use image::imageops::FilterType;
 use image::{DynamicImage, Rgba, RgbaImage};
 use log::info;
 
-use super::RenderedBlock;
+use super::{Breakpoint, RenderedBlock};
+use crate::settings::{DitheringMethod, ImageSettings};
 use crate::ui::helpers::{draw_box_border, draw_filled_rectangle};
 
 const COLOR_BACKGROUND: Rgba<u8> = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
@@ -13,6 +14,19 @@ const COLOR_PLACEHOLDER_BORDER: Rgba<u8> = Rgba([0x99, 0x99, 0x99, 0xFF]);
 const PLACEHOLDER_IMAGE_WIDTH: u32 = 300;
 const PLACEHOLDER_IMAGE_HEIGHT: u32 = 300;
 
+// Auto-levels ignores this fraction of the darkest and lightest pixels, so a few stray
+// specks don't stop the rest of the image from being stretched
+const AUTO_LEVELS_CLIP_FRACTION: f32 = 0.005;
+// Images that are already this flat are left alone, to avoid blowing up noise
+const AUTO_LEVELS_MIN_RANGE: f32 = 16.0;
+
+const BAYER_MATRIX_4X4: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
 pub fn rescale_image(raw_image: &RgbaImage, destination_width: u32) -> RgbaImage {
     info!(
         "Resizing image from input resolution {} x {} to destination width {}",
@@ -22,11 +36,165 @@ pub fn rescale_image(raw_image: &RgbaImage, destination_width: u32) -> RgbaImage
     );
 
     let raw_image = DynamicImage::ImageRgba8(raw_image.clone());
-    let result = raw_image.resize(destination_width, u32::MAX, FilterType::Triangle);
+    let result = raw_image.resize(destination_width, u32::MAX, FilterType::Lanczos3);
 
     result.into_rgba8()
 }
 
+/// Prepares an image for the e-ink panel: grayscale, optionally contrast-stretched, and
+/// dithered down to the number of gray levels the panel can show.
+pub fn prepare_image_for_display(image: &RgbaImage, settings: &ImageSettings) -> RgbaImage {
+    if !settings.grayscale {
+        return flatten_onto_background(image);
+    }
+
+    let width = image.width() as usize;
+    let mut luma = to_luma(image);
+
+    if settings.auto_levels {
+        auto_levels(&mut luma);
+    }
+
+    let gray_levels = settings.gray_levels.max(2);
+    match settings.dithering {
+        DitheringMethod::None => quantize(&mut luma, gray_levels),
+        DitheringMethod::FloydSteinberg => dither_floyd_steinberg(&mut luma, width, gray_levels),
+        DitheringMethod::Ordered => dither_ordered(&mut luma, width, gray_levels),
+    }
+
+    let mut result = RgbaImage::new(image.width(), image.height());
+    for (pixel, value) in result.pixels_mut().zip(luma.iter()) {
+        let value = value.round().clamp(0.0, 255.0) as u8;
+        *pixel = Rgba([value, value, value, 0xFF]);
+    }
+
+    result
+}
+
+/// Blends transparent pixels onto the page background, since the panel has no alpha
+fn flatten_onto_background(image: &RgbaImage) -> RgbaImage {
+    let mut result = image.clone();
+    for pixel in result.pixels_mut() {
+        let alpha = pixel[3] as f32 / 255.0;
+        for channel in 0..3 {
+            let blended =
+                pixel[channel] as f32 * alpha + COLOR_BACKGROUND[channel] as f32 * (1.0 - alpha);
+            pixel[channel] = blended.round() as u8;
+        }
+        pixel[3] = 0xFF;
+    }
+
+    result
+}
+
+fn to_luma(image: &RgbaImage) -> Vec<f32> {
+    flatten_onto_background(image)
+        .pixels()
+        .map(|pixel| 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32)
+        .collect()
+}
+
+fn auto_levels(luma: &mut [f32]) {
+    if luma.is_empty() {
+        return;
+    }
+
+    let mut histogram = [0usize; 256];
+    for value in luma.iter() {
+        histogram[value.round().clamp(0.0, 255.0) as usize] += 1;
+    }
+
+    let clip_count = (luma.len() as f32 * AUTO_LEVELS_CLIP_FRACTION) as usize;
+    let low = find_percentile_level(histogram.iter().enumerate(), clip_count);
+    let high = find_percentile_level(histogram.iter().enumerate().rev(), clip_count);
+
+    let range = high - low;
+    if range < AUTO_LEVELS_MIN_RANGE {
+        return;
+    }
+
+    for value in luma.iter_mut() {
+        *value = ((*value - low) * 255.0 / range).clamp(0.0, 255.0);
+    }
+}
+
+/// Walks the histogram until more than `clip_count` pixels have been seen
+fn find_percentile_level<'a, I>(histogram: I, clip_count: usize) -> f32
+where
+    I: Iterator<Item = (usize, &'a usize)>,
+{
+    let mut seen = 0;
+    let mut level = 0;
+    for (histogram_level, count) in histogram {
+        level = histogram_level;
+        seen += count;
+        if seen > clip_count {
+            break;
+        }
+    }
+
+    level as f32
+}
+
+fn nearest_level(value: f32, gray_levels: u8) -> f32 {
+    let step = 255.0 / (gray_levels - 1) as f32;
+
+    ((value / step).round() * step).clamp(0.0, 255.0)
+}
+
+fn quantize(luma: &mut [f32], gray_levels: u8) {
+    for value in luma.iter_mut() {
+        *value = nearest_level(*value, gray_levels);
+    }
+}
+
+fn dither_floyd_steinberg(luma: &mut [f32], width: usize, gray_levels: u8) {
+    if width == 0 {
+        return;
+    }
+    let height = luma.len() / width;
+
+    for y in 0..height {
+        for x in 0..width {
+            let idx = y * width + x;
+            let old_value = luma[idx];
+            let new_value = nearest_level(old_value, gray_levels);
+            luma[idx] = new_value;
+
+            let error = old_value - new_value;
+            if x + 1 < width {
+                luma[idx + 1] += error * 7.0 / 16.0;
+            }
+            if y + 1 < height {
+                if x > 0 {
+                    luma[idx + width - 1] += error * 3.0 / 16.0;
+                }
+                luma[idx + width] += error * 5.0 / 16.0;
+                if x + 1 < width {
+                    luma[idx + width + 1] += error * 1.0 / 16.0;
+                }
+            }
+        }
+    }
+}
+
+fn dither_ordered(luma: &mut [f32], width: usize, gray_levels: u8) {
+    if width == 0 {
+        return;
+    }
+
+    let step = 255.0 / (gray_levels - 1) as f32;
+
+    for (idx, value) in luma.iter_mut().enumerate() {
+        let x = idx % width;
+        let y = idx / width;
+
+        // Shift each pixel by up to half a step, in a pattern that averages out to the original
+        let threshold = (BAYER_MATRIX_4X4[y % 4][x % 4] + 0.5) / 16.0 - 0.5;
+        *value = nearest_level(*value + threshold * step, gray_levels);
+    }
+}
+
 pub fn render_placeholder_image_block(canvas_width: u32, screen_margin_left: u32) -> RenderedBlock {
     let mut canvas = RgbaImage::new(canvas_width, PLACEHOLDER_IMAGE_HEIGHT);
     let box_top_left = Point2::<u32> {
@@ -60,6 +228,105 @@ pub fn render_placeholder_image_block(canvas_width: u32, screen_margin_left: u32
     RenderedBlock {
         height: PLACEHOLDER_IMAGE_HEIGHT,
         canvas,
-        breakpoints: vec![0],
+        breakpoints: vec![Breakpoint::new(0, 0)],
+        links: vec![],
+        anchors: vec![],
+        first_baseline: None,
+        text_len: 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn settings(dithering: DitheringMethod, gray_levels: u8) -> ImageSettings {
+        ImageSettings {
+            grayscale: true,
+            auto_levels: false,
+            dithering,
+            gray_levels,
+        }
+    }
+
+    fn average_value(image: &RgbaImage) -> f32 {
+        let total: f32 = image.pixels().map(|pixel| pixel[0] as f32).sum();
+
+        total / (image.width() * image.height()) as f32
+    }
+
+    #[test]
+    fn grayscale_with_limited_levels() {
+        let image = RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, 0x80, 0xFF])
+        });
+
+        for dithering in [
+            DitheringMethod::None,
+            DitheringMethod::FloydSteinberg,
+            DitheringMethod::Ordered,
+        ] {
+            let result = prepare_image_for_display(&image, &settings(dithering, 4));
+
+            for pixel in result.pixels() {
+                assert_eq!(pixel[0], pixel[1]);
+                assert_eq!(pixel[0], pixel[2]);
+                assert!(
+                    [0, 85, 170, 255].contains(&pixel[0]),
+                    "{:?} produced level {}",
+                    dithering,
+                    pixel[0]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dithering_preserves_tone() {
+        let image = RgbaImage::from_pixel(32, 32, Rgba([0x60, 0x60, 0x60, 0xFF]));
+
+        // Without dithering, mid gray snaps to black or white on a two-level panel
+        let quantized = prepare_image_for_display(&image, &settings(DitheringMethod::None, 2));
+        assert_eq!(average_value(&quantized), 0.0);
+
+        for dithering in [DitheringMethod::FloydSteinberg, DitheringMethod::Ordered] {
+            let result = prepare_image_for_display(&image, &settings(dithering, 2));
+            let average = average_value(&result);
+
+            assert!(
+                (average - 96.0).abs() < 16.0,
+                "{:?} averaged {}",
+                dithering,
+                average
+            );
+        }
+    }
+
+    #[test]
+    fn auto_levels_stretches_contrast() {
+        let image = RgbaImage::from_fn(16, 16, |x, _| {
+            let value = if x < 8 { 100 } else { 150 };
+            Rgba([value, value, value, 0xFF])
+        });
+
+        let mut image_settings = settings(DitheringMethod::None, 255);
+        image_settings.auto_levels = true;
+        let result = prepare_image_for_display(&image, &image_settings);
+
+        assert_eq!(result.get_pixel(0, 0)[0], 0);
+        assert_eq!(result.get_pixel(15, 0)[0], 255);
+    }
+
+    #[test]
+    fn transparent_pixels_become_background() {
+        let image = RgbaImage::from_pixel(2, 2, Rgba([0x00, 0x00, 0x00, 0x00]));
+
+        let result = prepare_image_for_display(&image, &settings(DitheringMethod::None, 16));
+        assert_eq!(*result.get_pixel(0, 0), Rgba([0xFF, 0xFF, 0xFF, 0xFF]));
+
+        let mut color_settings = settings(DitheringMethod::None, 16);
+        color_settings.grayscale = false;
+        let result = prepare_image_for_display(&image, &color_settings);
+        assert_eq!(*result.get_pixel(0, 0), Rgba([0xFF, 0xFF, 0xFF, 0xFF]));
     }
 }
@@ -0,0 +1,167 @@
+//! Golden-image tests: every fixture page in `assets/test_pages` is rendered and compared
+//! against the reference PNGs in `assets/test_pages/snapshots`.
+//!
+//! After an intended rendering change, regenerate the references with:
+//!
+//! ```sh
+//! UPDATE_SNAPSHOTS=1 cargo test snapshot
+//! ```
+
+use image::{DynamicImage, RgbaImage};
+use std::path::{Path, PathBuf};
+
+use crate::backend::headless_backend::compare_images;
+use crate::browser_core::{BrowserCore, BrowserState};
+use crate::settings::Settings;
+
+const TEST_PAGES_DIR: &str = "assets/test_pages";
+const SNAPSHOTS_DIR: &str = "assets/test_pages/snapshots";
+const DIFFS_DIR: &str = "target/snapshot-diffs";
+
+// images.html is left out, since its images come from the network
+const FIXTURES: [&str; 10] = [
+    "blockquotes",
+    "cms-generated-page",
+    "code",
+    "lists",
+    "longer-blocks",
+    "nested-styles",
+    "simple",
+    "special-chars",
+    "tables",
+    "thai",
+];
+
+// Fixtures that are rendered with reading mode on
+const READING_MODE_FIXTURES: [&str; 1] = ["article-with-chrome"];
+
+// How far apart each color channel can be and still count as the same
+const CHANNEL_TOLERANCE: u8 = 8;
+// How many pixels of a page can differ, to absorb small antialiasing changes
+const MAX_DIFFERING_PIXELS: u32 = 200;
+
+fn render_fixture(name: &str, settings: Settings) -> Vec<RgbaImage> {
+    let html_path = Path::new(TEST_PAGES_DIR).join(format!("{}.html", name));
+    let html = std::fs::read_to_string(&html_path).unwrap();
+
+    let mut browser = BrowserCore::new(settings);
+    browser.render(&html, &format!("https://example.com/{}.html", name));
+    browser.finish_loading();
+
+    if let BrowserState::PageError { error, .. } = &browser.state {
+        panic!("Failed to render {}: {}", name, error);
+    }
+
+    browser
+        .get_pages()
+        .iter()
+        .map(|page| page.canvas.clone())
+        .collect()
+}
+
+fn snapshot_path(name: &str, page_idx: usize) -> PathBuf {
+    Path::new(SNAPSHOTS_DIR).join(format!("{}-{}.png", name, page_idx))
+}
+
+fn save_snapshot(image: &RgbaImage, path: &Path) {
+    DynamicImage::ImageRgba8(image.clone())
+        .to_rgb8()
+        .save(path)
+        .unwrap();
+}
+
+fn update_snapshots(name: &str, pages: &[RgbaImage]) {
+    std::fs::create_dir_all(SNAPSHOTS_DIR).unwrap();
+
+    for (page_idx, page) in pages.iter().enumerate() {
+        save_snapshot(page, &snapshot_path(name, page_idx));
+    }
+
+    // Drop references for pages that no longer exist
+    let mut page_idx = pages.len();
+    while snapshot_path(name, page_idx).exists() {
+        std::fs::remove_file(snapshot_path(name, page_idx)).unwrap();
+        page_idx += 1;
+    }
+}
+
+/// Compares the pages against their references, returning a description of each mismatch
+fn check_snapshots(name: &str, pages: &[RgbaImage]) -> Vec<String> {
+    let mut failures = vec![];
+
+    let expected_page_count = (0..)
+        .take_while(|page_idx| snapshot_path(name, *page_idx).exists())
+        .count();
+    if expected_page_count != pages.len() {
+        failures.push(format!(
+            "{}: expected {} pages, rendered {}",
+            name,
+            expected_page_count,
+            pages.len()
+        ));
+    }
+
+    for (page_idx, page) in pages.iter().enumerate() {
+        let Ok(expected) = image::open(snapshot_path(name, page_idx)) else {
+            continue;
+        };
+
+        let comparison = compare_images(&expected.to_rgba8(), page, CHANNEL_TOLERANCE);
+        if comparison.differing_pixels <= MAX_DIFFERING_PIXELS {
+            continue;
+        }
+
+        std::fs::create_dir_all(DIFFS_DIR).unwrap();
+        let actual_path = Path::new(DIFFS_DIR).join(format!("{}-{}-actual.png", name, page_idx));
+        let diff_path = Path::new(DIFFS_DIR).join(format!("{}-{}-diff.png", name, page_idx));
+        save_snapshot(page, &actual_path);
+        comparison.diff_image.save(&diff_path).unwrap();
+
+        failures.push(format!(
+            "{} page {}: {} pixels differ, see {}",
+            name,
+            page_idx,
+            comparison.differing_pixels,
+            diff_path.display()
+        ));
+    }
+
+    failures
+}
+
+#[test]
+fn snapshot_test_pages() {
+    let is_updating = std::env::var("UPDATE_SNAPSHOTS").is_ok_and(|value| value == "1");
+
+    let mut failures = vec![];
+
+    let reading_mode_settings = || {
+        let mut settings = Settings::default();
+        settings.rendering.reading_mode = true;
+        settings
+    };
+    let fixtures = FIXTURES
+        .iter()
+        .map(|name| (*name, Settings::default()))
+        .chain(
+            READING_MODE_FIXTURES
+                .iter()
+                .map(|name| (*name, reading_mode_settings())),
+        );
+
+    for (name, settings) in fixtures {
+        let pages = render_fixture(name, settings);
+
+        if is_updating {
+            update_snapshots(name, &pages);
+        } else {
+            failures.extend(check_snapshots(name, &pages));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Snapshots don't match:\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,106 @@
+use image::{Rgba, RgbaImage};
+use once_cell::sync::Lazy;
+use resvg::{tiny_skia, usvg};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::fonts::{build_svg_font_database, SANS_SERIF_FONT_FAMILY};
+use crate::CANVAS_WIDTH;
+
+/// SVG sizes are in CSS pixels, which are drawn this many pixels wide, like the text is
+const DISPLAY_SCALE: f32 = 2.0;
+
+static SVG_FONT_DATABASE: Lazy<Arc<usvg::fontdb::Database>> =
+    Lazy::new(|| Arc::new(build_svg_font_database()));
+
+#[derive(Error, Debug)]
+pub enum SvgError {
+    #[error("Failed to parse SVG: {0}")]
+    FailedToParse(String),
+    #[error("SVG has no size")]
+    EmptySize,
+    #[error("SVG is {0}x{1}, which is too many pixels")]
+    TooManyPixels(u32, u32),
+}
+
+pub fn is_svg_content_type(content_type: &str) -> bool {
+    content_type == "image/svg+xml"
+}
+
+/// Draws an SVG image at its own size, scaled like the rest of the page but never wider
+/// than the canvas
+pub fn rasterize_svg(data: &[u8], max_pixels: u64) -> Result<RgbaImage, SvgError> {
+    let options = usvg::Options {
+        font_family: SANS_SERIF_FONT_FAMILY.to_string(),
+        fontdb: SVG_FONT_DATABASE.clone(),
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_data(data, &options)
+        .map_err(|err| SvgError::FailedToParse(err.to_string()))?;
+
+    let size = tree.size();
+    let scale = DISPLAY_SCALE.min(CANVAS_WIDTH as f32 / size.width());
+    let width = (size.width() * scale).round() as u32;
+    let height = (size.height() * scale).round() as u32;
+    if width as u64 * height as u64 > max_pixels {
+        return Err(SvgError::TooManyPixels(width, height));
+    }
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or(SvgError::EmptySize)?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    // The pixmap's colors are premultiplied by their alpha, unlike the image's
+    let pixels = pixmap.pixels();
+    Ok(RgbaImage::from_fn(width, height, |x, y| {
+        let color = pixels[(y * width + x) as usize].demultiply();
+        Rgba([color.red(), color.green(), color.blue(), color.alpha()])
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MAX_PIXELS: u64 = 1_000_000;
+
+    #[test]
+    fn test_rasterize_svg() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50">
+            <rect x="50" width="50" height="50" fill="black"/>
+        </svg>"#;
+
+        let image = rasterize_svg(svg.as_bytes(), MAX_PIXELS).unwrap();
+
+        assert_eq!(image.dimensions(), (200, 100));
+        assert_eq!(image.get_pixel(50, 50).0[3], 0);
+        assert_eq!(*image.get_pixel(150, 50), Rgba([0, 0, 0, 0xFF]));
+    }
+
+    #[test]
+    fn test_rasterize_wide_svg() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 2000 100"></svg>"#;
+
+        let image = rasterize_svg(svg.as_bytes(), MAX_PIXELS).unwrap();
+
+        assert_eq!(image.dimensions(), (CANVAS_WIDTH, 70));
+    }
+
+    #[test]
+    fn test_too_many_pixels() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="5000"></svg>"#;
+
+        assert!(matches!(
+            rasterize_svg(svg.as_bytes(), MAX_PIXELS),
+            Err(SvgError::TooManyPixels(200, 10000))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_svg() {
+        assert!(rasterize_svg(b"<svg", MAX_PIXELS).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::browser_core::parsing::{Block, Document, Span};
+use crate::browser_core::rendering::{find_page_for_position, RenderedPage, SourcePosition};
+
+/// A heading in the document, and the page it's on
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableOfContentsEntry {
+    pub level: u8,
+    pub title: String,
+    pub page_idx: usize,
+}
+
+/// Lists the document's headings, for jumping around long pages. Only headings between
+/// other blocks count, not ones tucked inside lists or quotes. While the document is still
+/// being laid out, only the headings before `laid_out_until` are listed, since the rest
+/// aren't on a page yet.
+pub fn build_table_of_contents(
+    document: &Document,
+    pages: &[RenderedPage],
+    laid_out_until: Option<SourcePosition>,
+) -> Vec<TableOfContentsEntry> {
+    document
+        .blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(block_idx, block)| {
+            let Block::Heading { level, content } = block else {
+                return None;
+            };
+
+            let title = heading_title(content);
+            if title.is_empty() {
+                return None;
+            }
+
+            let position = SourcePosition {
+                block_idx,
+                text_offset: 0,
+            };
+            if laid_out_until.is_some_and(|laid_out_until| position >= laid_out_until) {
+                return None;
+            }
+
+            Some(TableOfContentsEntry {
+                level: *level,
+                title,
+                page_idx: find_page_for_position(pages, &position),
+            })
+        })
+        .collect()
+}
+
+fn heading_title(content: &[Span]) -> String {
+    let text: String = content
+        .iter()
+        .map(|span| match span {
+            Span::Text { content, .. } => content.as_str(),
+            Span::Link(link) => link.text.as_str(),
+            Span::Image { .. } => "",
+        })
+        .collect();
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use image::RgbaImage;
+
+    use super::*;
+    use crate::browser_core::parsing::{Link, SpanStyle};
+
+    fn page_starting_at(block_idx: usize) -> RenderedPage {
+        RenderedPage {
+            canvas: RgbaImage::new(1, 1),
+            links: vec![],
+            anchors: vec![],
+            start_position: SourcePosition {
+                block_idx,
+                text_offset: 0,
+            },
+        }
+    }
+
+    fn heading(level: u8, content: Vec<Span>) -> Block {
+        Block::Heading { level, content }
+    }
+
+    fn text(content: &str) -> Span {
+        Span::Text {
+            content: content.to_string(),
+            style: SpanStyle::Normal,
+        }
+    }
+
+    #[test]
+    fn test_build_table_of_contents() {
+        let document = Document {
+            blocks: vec![
+                heading(1, vec![text("Guide")]),
+                Block::Paragraph {
+                    content: vec![text("Intro")],
+                },
+                heading(2, vec![text("Getting "), text("\n started")]),
+                heading(2, vec![]),
+                Block::Paragraph {
+                    content: vec![text("Details")],
+                },
+                heading(
+                    3,
+                    vec![Span::Link(Link {
+                        destination: "#faq".to_string(),
+                        text: "FAQ".to_string(),
+                    })],
+                ),
+            ],
+            ..Default::default()
+        };
+        let pages = vec![
+            page_starting_at(0),
+            page_starting_at(2),
+            page_starting_at(4),
+        ];
+
+        let entry = |level, title: &str, page_idx| TableOfContentsEntry {
+            level,
+            title: title.to_string(),
+            page_idx,
+        };
+        assert_eq!(
+            build_table_of_contents(&document, &pages, None),
+            vec![
+                entry(1, "Guide", 0),
+                entry(2, "Getting started", 1),
+                entry(3, "FAQ", 2),
+            ]
+        );
+
+        // Only the first two pages are laid out so far
+        let laid_out_until = SourcePosition {
+            block_idx: 4,
+            text_offset: 0,
+        };
+        assert_eq!(
+            build_table_of_contents(&document, &pages[..2], Some(laid_out_until)),
+            vec![entry(1, "Guide", 0), entry(2, "Getting started", 1)]
+        );
+    }
+}
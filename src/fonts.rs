@@ -0,0 +1,178 @@
+use cosmic_text::{fontdb, FontSystem};
+use log::{info, warn};
+use resvg::usvg;
+use std::path::Path;
+
+use crate::settings::{FontFamily, RenderingSettings};
+
+/// Used for web page content, unless the user's settings pick something else
+pub const SANS_SERIF_FONT_FAMILY: &str = "DejaVu Sans";
+pub const SERIF_FONT_FAMILY: &str = "DejaVu Serif";
+pub const MONOSPACE_FONT_FAMILY: &str = "DejaVu Sans Mono";
+/// Used for the browser's own UI, like the topbar and keyboard
+pub const UI_FONT_FAMILY: &str = "Roboto";
+
+// Always the same locale, so font fallback doesn't depend on the machine we run on
+const FONT_LOCALE: &str = "en-US";
+
+const BUNDLED_FONTS: &[&[u8]] = &[
+    include_bytes!("../assets/fonts/DejaVuSans.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSans-Oblique.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSans-BoldOblique.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSerif.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSerif-Bold.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSerif-Italic.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSerif-BoldItalic.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSansMono.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSansMono-Bold.ttf"),
+    include_bytes!("../assets/fonts/Roboto-Regular.ttf"),
+];
+
+/// Builds the font database from the fonts bundled into the binary, plus any fonts
+/// the user has installed. System fonts are never loaded, so pages render the same
+/// on the device as they do on a laptop.
+///
+/// This scans font files, so it should be done once and shared.
+pub fn build_font_system(rendering_settings: &RenderingSettings) -> FontSystem {
+    let mut db = fontdb::Database::new();
+
+    for font_data in BUNDLED_FONTS {
+        db.load_font_data(font_data.to_vec());
+    }
+
+    if let FontFamily::Custom(path) = &rendering_settings.typography.font_family {
+        info!("Loading custom font from {}", path);
+        if let Err(err) = db.load_font_file(path) {
+            warn!("Failed to load custom font {}: {}", path, err);
+        }
+    }
+
+    for dir in &rendering_settings.user_font_dirs {
+        if !Path::new(dir).is_dir() {
+            warn!("User font directory does not exist: {}", dir);
+            continue;
+        }
+
+        info!("Loading user fonts from {}", dir);
+        db.load_fonts_dir(dir);
+    }
+
+    db.set_sans_serif_family(SANS_SERIF_FONT_FAMILY);
+    db.set_serif_family(SERIF_FONT_FAMILY);
+    db.set_monospace_family(MONOSPACE_FONT_FAMILY);
+
+    info!("Loaded {} font faces", db.len());
+
+    FontSystem::new_with_locale_and_db(FONT_LOCALE.to_string(), db)
+}
+
+/// Builds the font database for text inside SVG images, from the same bundled fonts. The SVG
+/// renderer comes with its own version of fontdb, so it can't share the one above.
+pub fn build_svg_font_database() -> usvg::fontdb::Database {
+    let mut db = usvg::fontdb::Database::new();
+
+    for font_data in BUNDLED_FONTS {
+        db.load_font_data(font_data.to_vec());
+    }
+
+    db.set_sans_serif_family(SANS_SERIF_FONT_FAMILY);
+    db.set_serif_family(SERIF_FONT_FAMILY);
+    db.set_monospace_family(MONOSPACE_FONT_FAMILY);
+
+    db
+}
+
+/// Finds the family name of a font that was loaded from a file, so text can be set in it
+pub fn find_font_file_family(font_system: &FontSystem, path: &str) -> Option<String> {
+    let path = Path::new(path);
+
+    font_system
+        .db()
+        .faces()
+        .find(|face| match &face.source {
+            fontdb::Source::File(face_path) | fontdb::Source::SharedFile(face_path, _) => {
+                face_path == path
+            }
+            fontdb::Source::Binary(_) => false,
+        })
+        .and_then(|face| face.families.first())
+        .map(|(family, _)| family.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::settings::Settings;
+    use cosmic_text::fontdb::{Family, Query, Style, Weight};
+
+    fn find_face(font_system: &FontSystem, family: Family, weight: Weight, style: Style) -> String {
+        let db = font_system.db();
+        let id = db
+            .query(&Query {
+                families: &[family],
+                weight,
+                style,
+                ..Default::default()
+            })
+            .expect("No matching font face");
+
+        db.face(id).unwrap().post_script_name.clone()
+    }
+
+    #[test]
+    fn bundled_fonts_only() {
+        let font_system = build_font_system(&Settings::default().rendering);
+
+        assert_eq!(font_system.db().len(), BUNDLED_FONTS.len());
+        assert_eq!(
+            find_face(
+                &font_system,
+                Family::SansSerif,
+                Weight::NORMAL,
+                Style::Normal
+            ),
+            "DejaVuSans"
+        );
+        assert_eq!(
+            find_face(&font_system, Family::SansSerif, Weight::BOLD, Style::Italic),
+            "DejaVuSans-BoldOblique"
+        );
+        assert_eq!(
+            find_face(&font_system, Family::Serif, Weight::NORMAL, Style::Italic),
+            "DejaVuSerif-Italic"
+        );
+        assert_eq!(
+            find_face(
+                &font_system,
+                Family::Monospace,
+                Weight::NORMAL,
+                Style::Normal
+            ),
+            "DejaVuSansMono"
+        );
+        assert_eq!(
+            find_face(
+                &font_system,
+                Family::Name(UI_FONT_FAMILY),
+                Weight::NORMAL,
+                Style::Normal
+            ),
+            "Roboto-Regular"
+        );
+    }
+
+    #[test]
+    fn missing_user_font_dir() {
+        let mut settings = Settings::default().rendering;
+        settings.user_font_dirs = vec!["/does/not/exist".to_string()];
+        settings.typography.font_family = FontFamily::Custom("/does/not/exist.ttf".to_string());
+        let font_system = build_font_system(&settings);
+
+        assert_eq!(font_system.db().len(), BUNDLED_FONTS.len());
+        assert_eq!(
+            find_font_file_family(&font_system, "/does/not/exist.ttf"),
+            None
+        );
+    }
+}
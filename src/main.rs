@@ -8,6 +8,7 @@ use std::sync::mpsc::channel;
 mod application;
 mod backend;
 mod browser_core;
+mod fonts;
 mod settings;
 mod ui;
 mod web_server;
@@ -46,10 +47,7 @@ fn main() {
         let app_handle = std::thread::spawn(move || {
             app.run()
                 .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> {
-                    Box::new(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        e.to_string(),
-                    ))
+                    Box::new(std::io::Error::other(e.to_string()))
                 })
         });
 
@@ -99,6 +97,7 @@ fn main_static() {
 
     let mut browser = BrowserCore::new(settings.clone());
     browser.navigate_to(&url);
+    browser.finish_loading();
 
     if let BrowserState::PageError { url: _, error } = browser.state {
         error!(
@@ -110,8 +109,8 @@ fn main_static() {
 
     info!("Saving pages to PNG files...");
     let mut handles = vec![];
-    for (page_idx, page_canvas) in browser.get_pages().iter().enumerate() {
-        let page_canvas = page_canvas.clone();
+    for (page_idx, page) in browser.get_pages().iter().enumerate() {
+        let page_canvas = page.canvas.clone();
         let file_path = format!("./output/page-{}.png", page_idx);
         let handle = std::thread::spawn(move || {
             save_page_canvas(page_canvas, &file_path).unwrap();
@@ -6,6 +6,8 @@ use std::io::Read;
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub rendering: RenderingSettings,
+    #[serde(default)]
+    pub network: NetworkSettings,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -13,6 +15,142 @@ pub struct RenderingSettings {
     pub font_size: u32,
     pub screen_margin_x: u32,
     pub line_height: f32,
+    #[serde(default)]
+    pub images: ImageSettings,
+    // Extra directories of font files, on top of the fonts bundled with the browser
+    #[serde(default)]
+    pub user_font_dirs: Vec<String>,
+    #[serde(default)]
+    pub typography: TypographySettings,
+    // Only show the main article of each page, leaving out navigation, footers, comments
+    // and the like. Pages where the article can't be found are shown whole.
+    #[serde(default)]
+    pub reading_mode: bool,
+}
+
+/// How the text of a page is set, for readers with their own preferences
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TypographySettings {
+    pub font_family: FontFamily,
+    // How much bigger than body text headings are: 1.0 is the usual sizes, 0.0 makes them all
+    // the same size as body text
+    pub heading_scale: f32,
+    // The gap between paragraphs and other blocks, in lines of body text
+    pub paragraph_spacing: f32,
+    pub paragraph_style: ParagraphStyle,
+    pub text_align: TextAlign,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FontFamily {
+    Sans,
+    Serif,
+    // The path to a font file, like "/home/root/.config/skelly/fonts/Literata.ttf"
+    Custom(String),
+}
+
+/// How one paragraph is set apart from the next
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParagraphStyle {
+    // A blank gap between paragraphs, like on most websites
+    BlockSpacing,
+    // No gap, but each paragraph after the first starts indented, like in a book
+    FirstLineIndent,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextAlign {
+    Left,
+    Right,
+    Center,
+    Justify,
+}
+
+impl Default for TypographySettings {
+    fn default() -> Self {
+        TypographySettings {
+            font_family: FontFamily::Sans,
+            heading_scale: 1.0,
+            paragraph_spacing: 1.0,
+            paragraph_style: ParagraphStyle::BlockSpacing,
+            text_align: TextAlign::Left,
+        }
+    }
+}
+
+/// How images are prepared for the e-ink panel, which only shows a few levels of gray
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ImageSettings {
+    pub grayscale: bool,
+    // Stretch the darkest and lightest parts of the image to black and white
+    pub auto_levels: bool,
+    pub dithering: DitheringMethod,
+    // Only used when converting to grayscale
+    pub gray_levels: u8,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DitheringMethod {
+    None,
+    FloydSteinberg,
+    Ordered,
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        ImageSettings {
+            grayscale: true,
+            auto_levels: true,
+            dithering: DitheringMethod::FloydSteinberg,
+            gray_levels: 16,
+        }
+    }
+}
+
+/// How pages and their images are fetched
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct NetworkSettings {
+    // Some sites turn away requests without one
+    pub user_agent: String,
+    pub connect_timeout_secs: u64,
+    // A total for each request, from connecting until the last byte of the response is in,
+    // however steadily it's arriving
+    pub request_timeout_secs: u64,
+    pub max_redirects: usize,
+    // Like "http://proxy.lan:3128", for every request
+    pub proxy: Option<String>,
+    pub accept_encodings: Vec<ContentEncoding>,
+}
+
+/// A compression a server may use for its responses
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentEncoding {
+    Gzip,
+    Brotli,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        NetworkSettings {
+            user_agent: format!(
+                "Mozilla/5.0 (Linux; reMarkable) Skelly/{}",
+                env!("CARGO_PKG_VERSION")
+            ),
+            connect_timeout_secs: 10,
+            request_timeout_secs: 30,
+            max_redirects: 10,
+            proxy: None,
+            accept_encodings: vec![ContentEncoding::Gzip, ContentEncoding::Brotli],
+        }
+    }
 }
 
 impl Default for Settings {
@@ -22,7 +160,12 @@ impl Default for Settings {
                 font_size: 12,
                 screen_margin_x: 100,
                 line_height: 1.2,
+                images: ImageSettings::default(),
+                user_font_dirs: vec![],
+                typography: TypographySettings::default(),
+                reading_mode: false,
             },
+            network: NetworkSettings::default(),
         }
     }
 }
@@ -47,3 +190,64 @@ fn load_settings(file_path: &str) -> Result<Settings, Box<dyn std::error::Error>
 
     Ok(settings)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn typography_settings_from_json() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "rendering": {
+                    "font_size": 12,
+                    "screen_margin_x": 100,
+                    "line_height": 1.2,
+                    "typography": {
+                        "font_family": { "custom": "/home/root/fonts/Literata.ttf" },
+                        "paragraph_style": "first_line_indent",
+                        "text_align": "justify"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let typography = settings.rendering.typography;
+        assert_eq!(
+            typography.font_family,
+            FontFamily::Custom("/home/root/fonts/Literata.ttf".to_string())
+        );
+        assert_eq!(typography.paragraph_style, ParagraphStyle::FirstLineIndent);
+        assert_eq!(typography.text_align, TextAlign::Justify);
+        assert_eq!(typography.heading_scale, 1.0);
+        assert_eq!(typography.paragraph_spacing, 1.0);
+        assert!(!settings.rendering.reading_mode);
+    }
+
+    #[test]
+    fn network_settings_from_json() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "rendering": {
+                    "font_size": 12,
+                    "screen_margin_x": 100,
+                    "line_height": 1.2
+                },
+                "network": {
+                    "user_agent": "Mozilla/5.0 (X11; Linux armv7l)",
+                    "proxy": "http://proxy.lan:3128",
+                    "accept_encodings": ["gzip"]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let network = settings.network;
+        assert_eq!(network.user_agent, "Mozilla/5.0 (X11; Linux armv7l)");
+        assert_eq!(network.proxy.as_deref(), Some("http://proxy.lan:3128"));
+        assert_eq!(network.accept_encodings, vec![ContentEncoding::Gzip]);
+        assert_eq!(network.connect_timeout_secs, 10);
+        assert_eq!(network.max_redirects, 10);
+    }
+}
@@ -1,4 +1,7 @@
+pub mod go_to_page;
 pub mod helpers;
 pub mod keyboard;
+pub mod loading;
 pub mod progress;
+pub mod table_of_contents;
 pub mod topbar;
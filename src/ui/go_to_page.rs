@@ -0,0 +1,154 @@
+use cgmath::Point2;
+use cosmic_text::{FontSystem, SwashCache};
+use image::{Rgba, RgbaImage};
+
+use super::helpers::{
+    draw_box_border, draw_filled_rectangle, draw_text, measure_text_width, ui_text_attrs,
+};
+
+const DIALOG_WIDTH: u32 = 420;
+const DIALOG_PADDING: u32 = 24;
+const TITLE_ROW_HEIGHT: u32 = 64;
+const INPUT_ROW_HEIGHT: u32 = 72;
+const INPUT_WIDTH: u32 = 200;
+const INPUT_TEXT_MARGIN: u32 = 12;
+
+const TITLE_TEXT_SIZE: f32 = 32.0;
+const INPUT_TEXT_SIZE: f32 = 36.0;
+
+const COLOR_DIALOG_BACKGROUND: Rgba<u8> = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
+const COLOR_DIALOG_BORDER: Rgba<u8> = Rgba([0x66, 0x66, 0x66, 0xFF]);
+const COLOR_INPUT_BORDER: Rgba<u8> = Rgba([0x99, 0x99, 0x99, 0xFF]);
+const COLOR_TEXT: Rgba<u8> = Rgba([0x00, 0x00, 0x00, 0xFF]);
+const COLOR_TEXT_SECONDARY: Rgba<u8> = Rgba([0x66, 0x66, 0x66, 0xFF]);
+
+const TITLE: &str = "Go to page";
+const INPUT_CURSOR: &str = "|";
+
+/// Draws the dialog for typing a page number, while one is being typed. It sits in the
+/// middle of the screen, above where the keyboard opens.
+pub fn add_go_to_page_overlay(
+    screen: &mut RgbaImage,
+    font_system: &mut FontSystem,
+    cache: &mut SwashCache,
+    page_number_input: Option<&str>,
+    total_pages: usize,
+) {
+    let Some(page_number_input) = page_number_input else {
+        return;
+    };
+
+    let dialog_height = DIALOG_PADDING * 2 + TITLE_ROW_HEIGHT + INPUT_ROW_HEIGHT;
+    let dialog_left_x = (screen.width() - DIALOG_WIDTH) / 2;
+    let dialog_top_y = screen.height() / 2 - dialog_height;
+    let dialog_bottom_right = Point2::new(
+        dialog_left_x + DIALOG_WIDTH - 1,
+        dialog_top_y + dialog_height - 1,
+    );
+
+    draw_filled_rectangle(
+        Point2::new(dialog_left_x, dialog_top_y),
+        dialog_bottom_right,
+        COLOR_DIALOG_BACKGROUND,
+        screen,
+    );
+    draw_box_border(
+        Point2::new(dialog_left_x, dialog_top_y),
+        dialog_bottom_right,
+        COLOR_DIALOG_BORDER,
+        screen,
+    );
+
+    let content_left_x = dialog_left_x + DIALOG_PADDING;
+    let title_top_y = dialog_top_y + DIALOG_PADDING;
+    draw_text(
+        screen,
+        font_system,
+        cache,
+        TITLE,
+        ui_text_attrs(TITLE_TEXT_SIZE, TITLE_ROW_HEIGHT),
+        content_left_x,
+        title_top_y,
+        COLOR_TEXT,
+    );
+
+    let input_top_y = title_top_y + TITLE_ROW_HEIGHT;
+    let input_bottom_right = Point2::new(
+        content_left_x + INPUT_WIDTH - 1,
+        input_top_y + INPUT_ROW_HEIGHT - 1,
+    );
+    draw_box_border(
+        Point2::new(content_left_x, input_top_y),
+        input_bottom_right,
+        COLOR_INPUT_BORDER,
+        screen,
+    );
+
+    let input_attrs = ui_text_attrs(INPUT_TEXT_SIZE, INPUT_ROW_HEIGHT);
+    draw_text(
+        screen,
+        font_system,
+        cache,
+        &format!("{}{}", page_number_input, INPUT_CURSOR),
+        input_attrs,
+        content_left_x + INPUT_TEXT_MARGIN,
+        input_top_y,
+        COLOR_TEXT,
+    );
+
+    let total_text = format!("of {}", total_pages);
+    let total_text_width = measure_text_width(font_system, &total_text, input_attrs);
+    let total_text_x = (content_left_x + INPUT_WIDTH + DIALOG_PADDING)
+        .min(dialog_left_x + DIALOG_WIDTH - DIALOG_PADDING - total_text_width);
+    draw_text(
+        screen,
+        font_system,
+        cache,
+        &total_text,
+        input_attrs,
+        total_text_x,
+        input_top_y,
+        COLOR_TEXT_SECONDARY,
+    );
+}
+
+/// Whether another digit fits in the page number, which never needs more digits than
+/// the last page does
+pub fn can_add_digit(page_number_input: &str, total_pages: usize) -> bool {
+    page_number_input.len() < total_pages.to_string().len()
+}
+
+/// The index of the page that was typed, if it's a number. Numbers past the end go to
+/// the last page.
+pub fn parse_page_number(page_number_input: &str, total_pages: usize) -> Option<usize> {
+    let page_number = page_number_input.parse::<usize>().ok()?;
+
+    if total_pages == 0 {
+        return None;
+    }
+
+    Some(page_number.clamp(1, total_pages) - 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_page_number() {
+        assert_eq!(parse_page_number("1", 60), Some(0));
+        assert_eq!(parse_page_number("30", 60), Some(29));
+        assert_eq!(parse_page_number("99", 60), Some(59));
+        assert_eq!(parse_page_number("0", 60), Some(0));
+        assert_eq!(parse_page_number("", 60), None);
+        assert_eq!(parse_page_number("3", 0), None);
+    }
+
+    #[test]
+    fn test_can_add_digit() {
+        assert!(can_add_digit("", 60));
+        assert!(can_add_digit("6", 60));
+        assert!(!can_add_digit("60", 60));
+        assert!(!can_add_digit("1", 9));
+    }
+}
@@ -1,5 +1,10 @@
 use cgmath::Point2;
-use image::{Rgba, RgbaImage};
+use cosmic_text::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, SwashCache, Wrap};
+use image::{Pixel, Rgba, RgbaImage};
+
+use crate::fonts::UI_FONT_FAMILY;
+
+const ELLIPSIS: &str = "…";
 
 pub fn draw_box_border(
     box_top_left: Point2<u32>,
@@ -53,3 +58,187 @@ pub fn create_blank_canvas(width: u32, height: u32, background_color: Rgba<u8>)
 
     canvas
 }
+
+/// Text in the UI font, in lines of the given height
+pub fn ui_text_attrs(text_size: f32, line_height: u32) -> Attrs<'static> {
+    Attrs::new()
+        .family(Family::Name(UI_FONT_FAMILY))
+        .metrics(Metrics::new(text_size, line_height as f32))
+}
+
+fn layout_text(font_system: &mut FontSystem, text: &str, attrs: Attrs) -> Buffer {
+    let metrics = attrs
+        .metrics_opt
+        .map(Metrics::from)
+        .expect("UI text needs metrics, from ui_text_attrs");
+    let mut buffer = Buffer::new_empty(metrics);
+
+    buffer.set_size(font_system, None, None);
+    buffer.set_wrap(font_system, Wrap::None);
+    buffer.set_text(font_system, text, attrs, Shaping::Advanced);
+    buffer.shape_until_scroll(font_system, false);
+
+    buffer
+}
+
+pub fn measure_text_width(font_system: &mut FontSystem, text: &str, attrs: Attrs) -> u32 {
+    let buffer = layout_text(font_system, text, attrs);
+
+    buffer
+        .layout_runs()
+        .map(|run| run.line_w)
+        .fold(0.0, f32::max)
+        .ceil() as u32
+}
+
+/// Shortens the text with a trailing ellipsis until it fits within the max width
+pub fn ellipsize_end(
+    font_system: &mut FontSystem,
+    text: &str,
+    attrs: Attrs,
+    max_width: u32,
+) -> String {
+    let chars: Vec<char> = text.chars().collect();
+
+    fit_text(font_system, attrs, max_width, chars.len(), |len| {
+        if len == chars.len() {
+            text.to_string()
+        } else {
+            format!("{}{}", chars[..len].iter().collect::<String>(), ELLIPSIS)
+        }
+    })
+}
+
+/// Shortens the text with a leading ellipsis until it fits within the max width
+pub fn ellipsize_start(
+    font_system: &mut FontSystem,
+    text: &str,
+    attrs: Attrs,
+    max_width: u32,
+) -> String {
+    let chars: Vec<char> = text.chars().collect();
+
+    fit_text(font_system, attrs, max_width, chars.len(), |len| {
+        if len == chars.len() {
+            text.to_string()
+        } else {
+            let tail: String = chars[chars.len() - len..].iter().collect();
+            format!("{}{}", ELLIPSIS, tail)
+        }
+    })
+}
+
+/// Finds the longest candidate that fits, searching over how many characters to keep
+fn fit_text<F>(
+    font_system: &mut FontSystem,
+    attrs: Attrs,
+    max_width: u32,
+    max_len: usize,
+    candidate: F,
+) -> String
+where
+    F: Fn(usize) -> String,
+{
+    if measure_text_width(font_system, &candidate(max_len), attrs) <= max_width {
+        return candidate(max_len);
+    }
+
+    let mut low = 0;
+    let mut high = max_len;
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if measure_text_width(font_system, &candidate(mid), attrs) <= max_width {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    candidate(low)
+}
+
+/// Draws one line of text with its top left at the offset. The text is centered in the
+/// line height of the attrs, so a row's height centers text in the row.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_text(
+    screen: &mut RgbaImage,
+    font_system: &mut FontSystem,
+    cache: &mut SwashCache,
+    text: &str,
+    attrs: Attrs,
+    offset_x: u32,
+    offset_y: u32,
+    text_rgba: Rgba<u8>,
+) {
+    let buffer = layout_text(font_system, text, attrs);
+    let text_color = Color::rgba(text_rgba[0], text_rgba[1], text_rgba[2], text_rgba[3]);
+
+    buffer.draw(
+        font_system,
+        cache,
+        text_color,
+        |buffer_x, buffer_y, _, _, color| {
+            let canvas_x = buffer_x + offset_x as i32;
+            let canvas_y = buffer_y + offset_y as i32;
+
+            if canvas_x < 0 || canvas_x >= screen.width() as i32 {
+                return;
+            }
+
+            if canvas_y < 0 || canvas_y >= screen.height() as i32 {
+                return;
+            }
+
+            let canvas_x = canvas_x as u32;
+            let canvas_y = canvas_y as u32;
+
+            let (fg_r, fg_g, fg_b, fg_a) = color.as_rgba_tuple();
+            let fg = Rgba([fg_r, fg_g, fg_b, fg_a]);
+
+            let bg = screen.get_pixel(canvas_x, canvas_y);
+            let mut result = *bg;
+            result.blend(&fg);
+            screen.put_pixel(canvas_x, canvas_y, result);
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fonts::build_font_system;
+    use crate::settings::Settings;
+
+    #[test]
+    fn ellipsize_long_text() {
+        let mut font_system = build_font_system(&Settings::default().rendering);
+        let attrs = ui_text_attrs(32.0, 48);
+        let url = "https://www.example.com/a/very/long/path/that/does/not/fit";
+        let max_width = measure_text_width(&mut font_system, "https://www.example.com", attrs);
+
+        let end = ellipsize_end(&mut font_system, url, attrs, max_width);
+        assert!(end.starts_with("https://www."));
+        assert!(end.ends_with(ELLIPSIS));
+        assert!(measure_text_width(&mut font_system, &end, attrs) <= max_width);
+
+        let start = ellipsize_start(&mut font_system, url, attrs, max_width);
+        assert!(start.starts_with(ELLIPSIS));
+        assert!(start.ends_with("/not/fit"));
+        assert!(measure_text_width(&mut font_system, &start, attrs) <= max_width);
+    }
+
+    #[test]
+    fn ellipsize_short_text() {
+        let mut font_system = build_font_system(&Settings::default().rendering);
+        let attrs = ui_text_attrs(32.0, 48);
+
+        assert_eq!(
+            ellipsize_end(&mut font_system, "example.com", attrs, 1000),
+            "example.com"
+        );
+        assert_eq!(
+            ellipsize_start(&mut font_system, "example.com", attrs, 1000),
+            "example.com"
+        );
+    }
+}
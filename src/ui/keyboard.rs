@@ -1,6 +1,10 @@
-use cosmic_text::{Attrs, Buffer, Color, FontSystem, Metrics, Shaping, SwashCache, Wrap};
+use cgmath::Point2;
+use cosmic_text::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, SwashCache, Wrap};
 use image::{Pixel, Rgba, RgbaImage};
 
+use crate::fonts::UI_FONT_FAMILY;
+
+#[derive(Debug, PartialEq)]
 pub enum KeyboardState {
     Hidden,
     Normal,
@@ -14,7 +18,7 @@ pub struct PositionedKey {
     pub size: (u32, u32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum KeyCode {
     // Lowercase
     LowercaseA,
@@ -129,13 +133,7 @@ pub fn add_keyboard_overlay(
 
     let keys = get_keys(keyboard_state);
 
-    let keyboard_keys_width =
-        KEYBOARD_COLUMNS * KEY_UNIT_WIDTH + (KEYBOARD_COLUMNS - 1) * KEY_GUTTER;
-    let keyboard_keys_height = KEYBOARD_ROWS * KEY_UNIT_HEIGHT + (KEYBOARD_ROWS - 1) * KEY_GUTTER;
-    let keyboard_total_height = keyboard_keys_height + (KEYBOARD_MARGIN_Y * 2);
-
-    let keyboard_offset_x = (screen.width() - keyboard_keys_width) / 2;
-    // let keyboard_offset_x = 5;
+    let keyboard_total_height = get_keyboard_total_height();
     let keyboard_offset_y = screen.height() - keyboard_total_height;
 
     // Render the background
@@ -146,7 +144,9 @@ pub fn add_keyboard_overlay(
     }
 
     let metrics = Metrics::new(40.0, 48.0);
-    let attrs = Attrs::new().metrics(metrics);
+    let attrs = Attrs::new()
+        .family(Family::Name(UI_FONT_FAMILY))
+        .metrics(metrics);
     let text_color = Color::rgba(
         COLOR_KEY_FOREGROUND[0],
         COLOR_KEY_FOREGROUND[1],
@@ -161,17 +161,12 @@ pub fn add_keyboard_overlay(
 
     // Render each key
     for positioned_key in keys.iter() {
-        let key_grid_x = positioned_key.position.0;
-        let key_grid_y = positioned_key.position.1;
-        let key_grid_width = positioned_key.size.0;
-        let key_grid_height = positioned_key.size.1;
-
-        let top_left_x = keyboard_offset_x + (KEY_UNIT_WIDTH + KEY_GUTTER) * key_grid_x;
-        let top_left_y =
-            keyboard_offset_y + KEYBOARD_MARGIN_Y + (KEY_UNIT_HEIGHT + KEY_GUTTER) * key_grid_y;
-
-        let key_width = KEY_UNIT_WIDTH * key_grid_width + KEY_GUTTER * (key_grid_width - 1);
-        let key_height = KEY_UNIT_HEIGHT * key_grid_height + KEY_GUTTER * (key_grid_height - 1);
+        let (top_left, bottom_right) =
+            get_key_bounds(positioned_key, screen.width(), screen.height());
+        let top_left_x = top_left.x;
+        let top_left_y = top_left.y;
+        let key_width = bottom_right.x - top_left.x + 1;
+        let key_height = bottom_right.y - top_left.y + 1;
 
         // Render key background
         for key_x in top_left_x..top_left_x + key_width {
@@ -215,7 +210,7 @@ pub fn add_keyboard_overlay(
                 let fg = Rgba([fg_r, fg_g, fg_b, fg_a]);
 
                 let bg = screen.get_pixel(canvas_x, canvas_y);
-                let mut result = bg.clone();
+                let mut result = *bg;
                 result.blend(&fg);
                 screen.put_pixel(canvas_x, canvas_y, result);
             },
@@ -223,6 +218,78 @@ pub fn add_keyboard_overlay(
     }
 }
 
+/// Whether a tap lands on the keyboard overlay, including the gaps between keys
+pub fn is_within_keyboard(y: u32, screen_height: u32, keyboard_state: &KeyboardState) -> bool {
+    if let KeyboardState::Hidden = keyboard_state {
+        return false;
+    }
+
+    y >= screen_height - get_keyboard_total_height()
+}
+
+pub fn get_key_at(
+    x: u32,
+    y: u32,
+    screen_width: u32,
+    screen_height: u32,
+    keyboard_state: &KeyboardState,
+) -> Option<KeyCode> {
+    get_keys(keyboard_state)
+        .into_iter()
+        .find(|positioned_key| {
+            let (top_left, bottom_right) =
+                get_key_bounds(positioned_key, screen_width, screen_height);
+
+            x >= top_left.x && x <= bottom_right.x && y >= top_left.y && y <= bottom_right.y
+        })
+        .map(|positioned_key| positioned_key.key)
+}
+
+/// The character a key types, if it types one at all
+pub fn get_key_char(key_code: &KeyCode) -> Option<char> {
+    match key_code {
+        KeyCode::Space => Some(' '),
+        KeyCode::Shift | KeyCode::Backspace | KeyCode::Return => None,
+        other => get_key_text(other).and_then(|text| text.chars().next()),
+    }
+}
+
+fn get_keyboard_total_height() -> u32 {
+    let keyboard_keys_height = KEYBOARD_ROWS * KEY_UNIT_HEIGHT + (KEYBOARD_ROWS - 1) * KEY_GUTTER;
+
+    keyboard_keys_height + (KEYBOARD_MARGIN_Y * 2)
+}
+
+/// The top left and bottom right corners of a key on the screen, inclusive
+fn get_key_bounds(
+    positioned_key: &PositionedKey,
+    screen_width: u32,
+    screen_height: u32,
+) -> (Point2<u32>, Point2<u32>) {
+    let keyboard_keys_width =
+        KEYBOARD_COLUMNS * KEY_UNIT_WIDTH + (KEYBOARD_COLUMNS - 1) * KEY_GUTTER;
+
+    let keyboard_offset_x = (screen_width - keyboard_keys_width) / 2;
+    let keyboard_offset_y = screen_height - get_keyboard_total_height();
+
+    let key_grid_x = positioned_key.position.0;
+    let key_grid_y = positioned_key.position.1;
+    let key_grid_width = positioned_key.size.0;
+    let key_grid_height = positioned_key.size.1;
+
+    let top_left_x = keyboard_offset_x + (KEY_UNIT_WIDTH + KEY_GUTTER) * key_grid_x;
+    let top_left_y =
+        keyboard_offset_y + KEYBOARD_MARGIN_Y + (KEY_UNIT_HEIGHT + KEY_GUTTER) * key_grid_y;
+
+    let key_width = KEY_UNIT_WIDTH * key_grid_width + KEY_GUTTER * (key_grid_width - 1);
+    let key_height = KEY_UNIT_HEIGHT * key_grid_height + KEY_GUTTER * (key_grid_height - 1);
+
+    (
+        Point2::new(top_left_x, top_left_y),
+        Point2::new(top_left_x + key_width - 1, top_left_y + key_height - 1),
+    )
+}
+
 fn get_keys(state: &KeyboardState) -> Vec<PositionedKey> {
     match state {
         KeyboardState::Hidden => vec![],
@@ -783,3 +850,64 @@ fn get_key_text(key_code: &KeyCode) -> Option<&'static str> {
         KeyCode::Return => Some("return"),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CANVAS_HEIGHT, CANVAS_WIDTH};
+
+    fn key_center(key: KeyCode, state: &KeyboardState) -> (u32, u32) {
+        let positioned_key = get_keys(state)
+            .into_iter()
+            .find(|positioned_key| positioned_key.key == key)
+            .unwrap();
+        let (top_left, bottom_right) = get_key_bounds(&positioned_key, CANVAS_WIDTH, CANVAS_HEIGHT);
+
+        (
+            (top_left.x + bottom_right.x) / 2,
+            (top_left.y + bottom_right.y) / 2,
+        )
+    }
+
+    #[test]
+    fn hit_test_keys() {
+        for state in [KeyboardState::Normal, KeyboardState::Shift] {
+            for positioned_key in get_keys(&state) {
+                let (x, y) = key_center(positioned_key.key, &state);
+                let found_key = get_key_at(x, y, CANVAS_WIDTH, CANVAS_HEIGHT, &state);
+
+                assert_eq!(found_key, Some(positioned_key.key));
+            }
+        }
+    }
+
+    #[test]
+    fn hit_test_outside_keys() {
+        let (x, y) = key_center(KeyCode::LowercaseQ, &KeyboardState::Normal);
+
+        assert_eq!(
+            get_key_at(x, y, CANVAS_WIDTH, CANVAS_HEIGHT, &KeyboardState::Hidden),
+            None
+        );
+        assert_eq!(
+            get_key_at(x, 0, CANVAS_WIDTH, CANVAS_HEIGHT, &KeyboardState::Normal),
+            None
+        );
+        assert!(is_within_keyboard(y, CANVAS_HEIGHT, &KeyboardState::Normal));
+        assert!(!is_within_keyboard(
+            y,
+            CANVAS_HEIGHT,
+            &KeyboardState::Hidden
+        ));
+    }
+
+    #[test]
+    fn key_chars() {
+        assert_eq!(get_key_char(&KeyCode::LowercaseA), Some('a'));
+        assert_eq!(get_key_char(&KeyCode::UppercaseZ), Some('Z'));
+        assert_eq!(get_key_char(&KeyCode::Slash), Some('/'));
+        assert_eq!(get_key_char(&KeyCode::Space), Some(' '));
+        assert_eq!(get_key_char(&KeyCode::Shift), None);
+        assert_eq!(get_key_char(&KeyCode::Return), None);
+    }
+}
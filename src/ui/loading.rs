@@ -0,0 +1,35 @@
+use cosmic_text::{FontSystem, SwashCache};
+use image::{Rgba, RgbaImage};
+
+use super::helpers::{draw_text, measure_text_width, ui_text_attrs};
+
+// Below the "Loading view" text of the placeholder
+const PROGRESS_TOP_Y: u32 = 1720;
+const PROGRESS_ROW_HEIGHT: u32 = 64;
+const PROGRESS_TEXT_SIZE: f32 = 36.0;
+
+const COLOR_TEXT: Rgba<u8> = Rgba([0x33, 0x33, 0x33, 0xFF]);
+
+/// Draws how many of the page's images are in so far, centered under the loading view
+pub fn add_loading_progress_overlay(
+    screen: &mut RgbaImage,
+    font_system: &mut FontSystem,
+    cache: &mut SwashCache,
+    loaded: usize,
+    total: usize,
+) {
+    let text = format!("Loading images: {} of {}", loaded, total);
+    let attrs = ui_text_attrs(PROGRESS_TEXT_SIZE, PROGRESS_ROW_HEIGHT);
+    let text_width = measure_text_width(font_system, &text, attrs);
+
+    draw_text(
+        screen,
+        font_system,
+        cache,
+        &text,
+        attrs,
+        screen.width().saturating_sub(text_width) / 2,
+        PROGRESS_TOP_Y,
+        COLOR_TEXT,
+    );
+}
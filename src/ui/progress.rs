@@ -1,7 +1,9 @@
 use cgmath::Point2;
-use cosmic_text::{Attrs, Buffer, Color, FontSystem, Metrics, Shaping, SwashCache, Wrap};
+use cosmic_text::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, SwashCache, Wrap};
 use image::{Pixel, Rgba, RgbaImage};
 
+use crate::fonts::UI_FONT_FAMILY;
+
 use crate::ui::helpers::{draw_box_border, draw_filled_rectangle};
 
 const BAR_WIDTH: u32 = 320;
@@ -17,16 +19,78 @@ const TEXT_COLOR: Rgba<u8> = Rgba([0x99, 0x99, 0x99, 0xFF]);
 const TEXT_FONT_SIZE: f32 = 20.0;
 const TEXT_OFFSET_Y: u32 = 8;
 
+// In place of the total, while the rest of the pages are still being laid out
+const UNKNOWN_TOTAL: &str = "…";
+
+// How far past the bar and the page numbers a tap still counts, since they're small
+const TAP_MARGIN: u32 = 24;
+
+/// The parts of the progress overlay that respond to taps
+#[derive(Debug, PartialEq)]
+pub enum ProgressTarget {
+    /// A spot along the bar, for the page that far through the document
+    Page(usize),
+    /// The page numbers, which open the go-to-page dialog
+    PageNumber,
+}
+
+/// Draws the progress bar and page numbers. Until it's known how many pages there are,
+/// only the page number is drawn.
 pub fn add_progress_overlay(
     page_idx: usize,
-    total_pages: usize,
+    total_pages: Option<usize>,
     screen: &mut RgbaImage,
     font_system: &mut FontSystem,
     cache: &mut SwashCache,
 ) {
-    let progress_percent = (page_idx + 1) as f32 / total_pages as f32;
-    draw_progress_bar(progress_percent, screen);
-    draw_text(page_idx, total_pages, screen, font_system, cache);
+    let text = match total_pages {
+        Some(total_pages) => {
+            let progress_percent = (page_idx + 1) as f32 / total_pages as f32;
+            draw_progress_bar(progress_percent, screen);
+            format!("{} / {}", page_idx + 1, total_pages)
+        }
+        None => format!("{} / {}", page_idx + 1, UNKNOWN_TOTAL),
+    };
+    draw_text(&text, screen, font_system, cache);
+}
+
+/// Finds what part of the progress overlay was tapped, if any. Each spot along the bar
+/// stands for the page whose progress fills the bar up to there.
+pub fn get_progress_target(
+    x: u32,
+    y: u32,
+    screen_width: u32,
+    screen_height: u32,
+    total_pages: usize,
+) -> Option<ProgressTarget> {
+    let bar_left_x = (screen_width - BAR_WIDTH) / 2;
+    let bar_right_x = bar_left_x + BAR_WIDTH;
+    let bar_top_y = screen_height - OVERLAY_MARGIN_Y - BAR_HEIGHT;
+    let bar_bottom_y = bar_top_y + BAR_HEIGHT;
+    let text_top_y = screen_height - OVERLAY_MARGIN_Y + TEXT_OFFSET_Y;
+    let text_bottom_y = text_top_y + TEXT_FONT_SIZE as u32;
+
+    if total_pages == 0
+        || x + TAP_MARGIN < bar_left_x
+        || x > bar_right_x + TAP_MARGIN
+        || y + TAP_MARGIN < bar_top_y
+        || y > text_bottom_y + TAP_MARGIN
+    {
+        return None;
+    }
+
+    // The gap between the bar and the text is split between them
+    if y > bar_bottom_y + TEXT_OFFSET_Y / 2 {
+        return Some(ProgressTarget::PageNumber);
+    }
+
+    let inner_bar_left_x = bar_left_x + BAR_INNER_OFFSET;
+    let inner_bar_width = BAR_WIDTH - BAR_INNER_OFFSET * 2;
+    let progress_percent =
+        (x.saturating_sub(inner_bar_left_x) as f32 / inner_bar_width as f32).min(1.0);
+    let page_number = ((progress_percent * total_pages as f32).ceil() as usize).max(1);
+
+    Some(ProgressTarget::Page(page_number - 1))
 }
 
 fn draw_progress_bar(progress_percent: f32, screen: &mut RgbaImage) {
@@ -70,16 +134,15 @@ fn draw_progress_bar(progress_percent: f32, screen: &mut RgbaImage) {
 }
 
 fn draw_text(
-    page_idx: usize,
-    total_pages: usize,
+    text: &str,
     screen: &mut RgbaImage,
     font_system: &mut FontSystem,
     cache: &mut SwashCache,
 ) {
-    let text = format!("{} / {}", page_idx + 1, total_pages);
-
     let metrics = Metrics::relative(TEXT_FONT_SIZE, 1.0);
-    let attrs = Attrs::new().metrics(metrics);
+    let attrs = Attrs::new()
+        .family(Family::Name(UI_FONT_FAMILY))
+        .metrics(metrics);
     let text_color = Color::rgba(TEXT_COLOR[0], TEXT_COLOR[1], TEXT_COLOR[2], TEXT_COLOR[3]);
 
     let mut buffer = Buffer::new_empty(metrics);
@@ -87,7 +150,7 @@ fn draw_text(
     buffer.set_size(font_system, None, None);
     buffer.set_wrap(font_system, Wrap::None);
 
-    buffer.set_text(font_system, &text, attrs, Shaping::Basic);
+    buffer.set_text(font_system, text, attrs, Shaping::Basic);
     buffer.shape_until_scroll(font_system, false);
     let layout_run = buffer.layout_runs().next().unwrap();
     let text_width = layout_run.line_w;
@@ -116,9 +179,52 @@ fn draw_text(
             let fg = Rgba([fg_r, fg_g, fg_b, fg_a]);
 
             let bg = screen.get_pixel(canvas_x, canvas_y);
-            let mut result = bg.clone();
+            let mut result = *bg;
             result.blend(&fg);
             screen.put_pixel(canvas_x, canvas_y, result);
         },
     );
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CANVAS_HEIGHT, CANVAS_WIDTH};
+
+    #[test]
+    fn test_progress_targets() {
+        let bar_left_x = (CANVAS_WIDTH - BAR_WIDTH) / 2 + BAR_INNER_OFFSET;
+        let bar_width = BAR_WIDTH - BAR_INNER_OFFSET * 2;
+        let bar_y = CANVAS_HEIGHT - OVERLAY_MARGIN_Y - BAR_HEIGHT / 2;
+        let text_y = CANVAS_HEIGHT - OVERLAY_MARGIN_Y + TEXT_OFFSET_Y + 10;
+        let target = |x, y| get_progress_target(x, y, CANVAS_WIDTH, CANVAS_HEIGHT, 60);
+
+        assert_eq!(
+            target(bar_left_x - 10, bar_y),
+            Some(ProgressTarget::Page(0))
+        );
+        assert_eq!(
+            target(bar_left_x + bar_width / 2, bar_y),
+            Some(ProgressTarget::Page(29))
+        );
+        assert_eq!(
+            target(bar_left_x + bar_width / 2 + 1, bar_y - 20),
+            Some(ProgressTarget::Page(30))
+        );
+        assert_eq!(
+            target(bar_left_x + bar_width + 10, bar_y),
+            Some(ProgressTarget::Page(59))
+        );
+        assert_eq!(
+            target(CANVAS_WIDTH / 2, text_y),
+            Some(ProgressTarget::PageNumber)
+        );
+
+        assert_eq!(target(CANVAS_WIDTH / 2, bar_y - 100), None);
+        assert_eq!(target(bar_left_x - 100, bar_y), None);
+        assert_eq!(
+            get_progress_target(CANVAS_WIDTH / 2, bar_y, CANVAS_WIDTH, CANVAS_HEIGHT, 0),
+            None
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use cgmath::Point2;
+use cosmic_text::{FontSystem, SwashCache};
+use image::{Rgba, RgbaImage};
+
+use crate::browser_core::TableOfContentsEntry;
+
+use super::helpers::{
+    draw_box_border, draw_filled_rectangle, draw_horizontal_line, draw_text, ellipsize_end,
+    measure_text_width, ui_text_attrs,
+};
+use super::topbar::TOPBAR_HEIGHT;
+
+#[derive(Debug, PartialEq)]
+pub enum TableOfContentsState {
+    Hidden,
+    /// Showing one page of the entries, when there are too many to fit at once
+    Open {
+        page: usize,
+    },
+}
+
+/// The parts of the table of contents that respond to taps
+#[derive(Debug, PartialEq)]
+pub enum TableOfContentsTarget {
+    /// One of the entries, by its index in the whole list
+    Entry(usize),
+    PreviousPage,
+    NextPage,
+    /// Somewhere else in the panel, which does nothing
+    Panel,
+}
+
+const PANEL_MARGIN_X: u32 = 40;
+const PANEL_MARGIN_BOTTOM: u32 = 40;
+const PANEL_PADDING_X: u32 = 24;
+const TITLE_ROW_HEIGHT: u32 = 80;
+const ENTRY_ROW_HEIGHT: u32 = 64;
+const FOOTER_ROW_HEIGHT: u32 = 80;
+const LEVEL_INDENT: u32 = 40;
+const PAGE_NUMBER_WIDTH: u32 = 120;
+
+const TITLE_TEXT_SIZE: f32 = 36.0;
+const ENTRY_TEXT_SIZE: f32 = 32.0;
+
+const COLOR_PANEL_BACKGROUND: Rgba<u8> = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
+const COLOR_PANEL_BORDER: Rgba<u8> = Rgba([0x66, 0x66, 0x66, 0xFF]);
+const COLOR_DIVIDER: Rgba<u8> = Rgba([0xAA, 0xAA, 0xAA, 0xFF]);
+const COLOR_CURRENT_ENTRY: Rgba<u8> = Rgba([0xDD, 0xDD, 0xDD, 0xFF]);
+const COLOR_TEXT: Rgba<u8> = Rgba([0x00, 0x00, 0x00, 0xFF]);
+const COLOR_TEXT_SECONDARY: Rgba<u8> = Rgba([0x66, 0x66, 0x66, 0xFF]);
+
+const TITLE: &str = "Contents";
+const NO_HEADINGS: &str = "This page has no headings";
+const LOADING_HEADINGS: &str = "Still loading the page…";
+const PREVIOUS_LABEL: &str = "‹ Previous";
+const NEXT_LABEL: &str = "Next ›";
+
+struct PanelLayout {
+    left_x: u32,
+    right_x: u32,
+    top_y: u32,
+    bottom_y: u32,
+    entries_top_y: u32,
+    footer_top_y: u32,
+    entries_per_page: usize,
+}
+
+impl TableOfContentsState {
+    /// Opens on the page with the section being read: the last heading before the end of
+    /// the page we're on
+    pub fn open_at(
+        entries: &[TableOfContentsEntry],
+        current_page_idx: usize,
+        screen_height: u32,
+    ) -> Self {
+        let entries_per_page = get_panel_layout(1, screen_height).entries_per_page;
+        let page = find_current_entry(entries, current_page_idx)
+            .map_or(0, |entry_idx| entry_idx / entries_per_page);
+
+        Self::Open { page }
+    }
+}
+
+pub fn add_table_of_contents_overlay(
+    screen: &mut RgbaImage,
+    font_system: &mut FontSystem,
+    cache: &mut SwashCache,
+    state: &TableOfContentsState,
+    entries: &[TableOfContentsEntry],
+    current_page_idx: usize,
+    is_laid_out: bool,
+) {
+    let TableOfContentsState::Open { page } = state else {
+        return;
+    };
+
+    let layout = get_panel_layout(screen.width(), screen.height());
+
+    draw_filled_rectangle(
+        Point2::new(layout.left_x, layout.top_y),
+        Point2::new(layout.right_x, layout.bottom_y),
+        COLOR_PANEL_BACKGROUND,
+        screen,
+    );
+    draw_box_border(
+        Point2::new(layout.left_x, layout.top_y),
+        Point2::new(layout.right_x, layout.bottom_y),
+        COLOR_PANEL_BORDER,
+        screen,
+    );
+
+    let text_left_x = layout.left_x + PANEL_PADDING_X;
+    let text_right_x = layout.right_x - PANEL_PADDING_X;
+
+    let title_attrs = ui_text_attrs(TITLE_TEXT_SIZE, TITLE_ROW_HEIGHT);
+    draw_text(
+        screen,
+        font_system,
+        cache,
+        TITLE,
+        title_attrs,
+        text_left_x,
+        layout.top_y,
+        COLOR_TEXT,
+    );
+    draw_horizontal_line(
+        layout.left_x,
+        layout.right_x,
+        layout.entries_top_y,
+        COLOR_DIVIDER,
+        screen,
+    );
+
+    let entry_attrs = ui_text_attrs(ENTRY_TEXT_SIZE, ENTRY_ROW_HEIGHT);
+
+    if entries.is_empty() {
+        draw_text(
+            screen,
+            font_system,
+            cache,
+            if is_laid_out {
+                NO_HEADINGS
+            } else {
+                LOADING_HEADINGS
+            },
+            entry_attrs,
+            text_left_x,
+            layout.entries_top_y,
+            COLOR_TEXT_SECONDARY,
+        );
+        return;
+    }
+
+    // Headings are indented by how much deeper they are than the top level in this page
+    let top_level = entries.iter().map(|entry| entry.level).min().unwrap_or(1);
+    let current_entry_idx = find_current_entry(entries, current_page_idx);
+
+    let first_entry_idx = page * layout.entries_per_page;
+    let page_entries = entries
+        .iter()
+        .enumerate()
+        .skip(first_entry_idx)
+        .take(layout.entries_per_page);
+
+    for (row_idx, (entry_idx, entry)) in page_entries.enumerate() {
+        let row_top_y = layout.entries_top_y + row_idx as u32 * ENTRY_ROW_HEIGHT;
+
+        if current_entry_idx == Some(entry_idx) {
+            draw_filled_rectangle(
+                Point2::new(layout.left_x + 1, row_top_y + 1),
+                Point2::new(layout.right_x - 1, row_top_y + ENTRY_ROW_HEIGHT - 1),
+                COLOR_CURRENT_ENTRY,
+                screen,
+            );
+        }
+
+        let indent = (entry.level - top_level) as u32 * LEVEL_INDENT;
+        let title_left_x = text_left_x + indent;
+        let title_max_width = text_right_x - PAGE_NUMBER_WIDTH - title_left_x;
+        let title = ellipsize_end(font_system, &entry.title, entry_attrs, title_max_width);
+        draw_text(
+            screen,
+            font_system,
+            cache,
+            &title,
+            entry_attrs,
+            title_left_x,
+            row_top_y,
+            COLOR_TEXT,
+        );
+
+        let page_number = (entry.page_idx + 1).to_string();
+        let page_number_width = measure_text_width(font_system, &page_number, entry_attrs);
+        draw_text(
+            screen,
+            font_system,
+            cache,
+            &page_number,
+            entry_attrs,
+            text_right_x - page_number_width,
+            row_top_y,
+            COLOR_TEXT_SECONDARY,
+        );
+    }
+
+    let total_pages = count_pages(entries.len(), layout.entries_per_page);
+    if total_pages > 1 {
+        draw_horizontal_line(
+            layout.left_x,
+            layout.right_x,
+            layout.footer_top_y,
+            COLOR_DIVIDER,
+            screen,
+        );
+
+        let footer_attrs = ui_text_attrs(ENTRY_TEXT_SIZE, FOOTER_ROW_HEIGHT);
+        let previous_color = if *page > 0 { COLOR_TEXT } else { COLOR_DIVIDER };
+        let next_color = if page + 1 < total_pages {
+            COLOR_TEXT
+        } else {
+            COLOR_DIVIDER
+        };
+
+        draw_text(
+            screen,
+            font_system,
+            cache,
+            PREVIOUS_LABEL,
+            footer_attrs,
+            text_left_x,
+            layout.footer_top_y,
+            previous_color,
+        );
+
+        let page_label = format!("{} / {}", page + 1, total_pages);
+        let page_label_width = measure_text_width(font_system, &page_label, footer_attrs);
+        draw_text(
+            screen,
+            font_system,
+            cache,
+            &page_label,
+            footer_attrs,
+            (layout.left_x + layout.right_x - page_label_width) / 2,
+            layout.footer_top_y,
+            COLOR_TEXT_SECONDARY,
+        );
+
+        let next_width = measure_text_width(font_system, NEXT_LABEL, footer_attrs);
+        draw_text(
+            screen,
+            font_system,
+            cache,
+            NEXT_LABEL,
+            footer_attrs,
+            text_right_x - next_width,
+            layout.footer_top_y,
+            next_color,
+        );
+    }
+}
+
+/// Finds what part of the table of contents was tapped. Taps outside the panel return
+/// None, which closes it.
+pub fn get_table_of_contents_target(
+    x: u32,
+    y: u32,
+    screen_width: u32,
+    screen_height: u32,
+    state: &TableOfContentsState,
+    entries: &[TableOfContentsEntry],
+) -> Option<TableOfContentsTarget> {
+    let TableOfContentsState::Open { page } = state else {
+        return None;
+    };
+
+    let layout = get_panel_layout(screen_width, screen_height);
+
+    if x < layout.left_x || x > layout.right_x || y < layout.top_y || y > layout.bottom_y {
+        return None;
+    }
+
+    if y >= layout.footer_top_y {
+        let total_pages = count_pages(entries.len(), layout.entries_per_page);
+        let target = if total_pages <= 1 {
+            TableOfContentsTarget::Panel
+        } else if x < (layout.left_x + layout.right_x) / 2 {
+            if *page > 0 {
+                TableOfContentsTarget::PreviousPage
+            } else {
+                TableOfContentsTarget::Panel
+            }
+        } else if page + 1 < total_pages {
+            TableOfContentsTarget::NextPage
+        } else {
+            TableOfContentsTarget::Panel
+        };
+        return Some(target);
+    }
+
+    if y < layout.entries_top_y {
+        return Some(TableOfContentsTarget::Panel);
+    }
+
+    let row_idx = ((y - layout.entries_top_y) / ENTRY_ROW_HEIGHT) as usize;
+    let entry_idx = page * layout.entries_per_page + row_idx;
+    if row_idx < layout.entries_per_page && entry_idx < entries.len() {
+        Some(TableOfContentsTarget::Entry(entry_idx))
+    } else {
+        Some(TableOfContentsTarget::Panel)
+    }
+}
+
+/// The page of entries after or before this one, staying within the list
+pub fn turn_page(
+    state: &TableOfContentsState,
+    delta: isize,
+    entries: &[TableOfContentsEntry],
+    screen_height: u32,
+) -> TableOfContentsState {
+    let TableOfContentsState::Open { page } = state else {
+        return TableOfContentsState::Hidden;
+    };
+
+    let entries_per_page = get_panel_layout(1, screen_height).entries_per_page;
+    let last_page = count_pages(entries.len(), entries_per_page).saturating_sub(1);
+    let page = page.saturating_add_signed(delta).min(last_page);
+
+    TableOfContentsState::Open { page }
+}
+
+/// The entry for the section we're in, which is the last one that starts at or before
+/// the current page
+fn find_current_entry(entries: &[TableOfContentsEntry], current_page_idx: usize) -> Option<usize> {
+    entries
+        .iter()
+        .rposition(|entry| entry.page_idx <= current_page_idx)
+}
+
+fn count_pages(entry_count: usize, entries_per_page: usize) -> usize {
+    entry_count.div_ceil(entries_per_page).max(1)
+}
+
+/// The panel sits below the topbar, so the menu icon can close it again
+fn get_panel_layout(screen_width: u32, screen_height: u32) -> PanelLayout {
+    let left_x = PANEL_MARGIN_X.min(screen_width / 2);
+    let right_x = screen_width.saturating_sub(PANEL_MARGIN_X + 1).max(left_x);
+    let top_y = TOPBAR_HEIGHT;
+    let bottom_y = screen_height - PANEL_MARGIN_BOTTOM - 1;
+    let entries_top_y = top_y + TITLE_ROW_HEIGHT;
+    let footer_top_y = bottom_y + 1 - FOOTER_ROW_HEIGHT;
+    let entries_per_page = ((footer_top_y - entries_top_y) / ENTRY_ROW_HEIGHT) as usize;
+
+    PanelLayout {
+        left_x,
+        right_x,
+        top_y,
+        bottom_y,
+        entries_top_y,
+        footer_top_y,
+        entries_per_page,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CANVAS_HEIGHT, CANVAS_WIDTH};
+
+    fn entries(count: usize) -> Vec<TableOfContentsEntry> {
+        (0..count)
+            .map(|entry_idx| TableOfContentsEntry {
+                level: 2,
+                title: format!("Section {}", entry_idx + 1),
+                page_idx: entry_idx,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_open_at_the_current_section() {
+        let entries = entries(60);
+        let entries_per_page = get_panel_layout(CANVAS_WIDTH, CANVAS_HEIGHT).entries_per_page;
+
+        assert_eq!(
+            TableOfContentsState::open_at(&entries, 0, CANVAS_HEIGHT),
+            TableOfContentsState::Open { page: 0 }
+        );
+        assert_eq!(
+            TableOfContentsState::open_at(&entries, entries_per_page + 1, CANVAS_HEIGHT),
+            TableOfContentsState::Open { page: 1 }
+        );
+        assert_eq!(
+            TableOfContentsState::open_at(&[], 5, CANVAS_HEIGHT),
+            TableOfContentsState::Open { page: 0 }
+        );
+    }
+
+    #[test]
+    fn test_targets() {
+        let entries = entries(60);
+        let layout = get_panel_layout(CANVAS_WIDTH, CANVAS_HEIGHT);
+        let target = |x, y, page| {
+            get_table_of_contents_target(
+                x,
+                y,
+                CANVAS_WIDTH,
+                CANVAS_HEIGHT,
+                &TableOfContentsState::Open { page },
+                &entries,
+            )
+        };
+
+        let second_row_y = layout.entries_top_y + ENTRY_ROW_HEIGHT + 10;
+        assert_eq!(
+            target(CANVAS_WIDTH / 2, second_row_y, 0),
+            Some(TableOfContentsTarget::Entry(1))
+        );
+        assert_eq!(
+            target(CANVAS_WIDTH / 2, second_row_y, 1),
+            Some(TableOfContentsTarget::Entry(layout.entries_per_page + 1))
+        );
+        assert_eq!(
+            target(100, layout.top_y + 10, 0),
+            Some(TableOfContentsTarget::Panel)
+        );
+        assert_eq!(target(10, second_row_y, 0), None);
+
+        let footer_y = layout.footer_top_y + 10;
+        assert_eq!(target(100, footer_y, 0), Some(TableOfContentsTarget::Panel));
+        assert_eq!(
+            target(CANVAS_WIDTH - 100, footer_y, 0),
+            Some(TableOfContentsTarget::NextPage)
+        );
+        assert_eq!(
+            target(100, footer_y, 2),
+            Some(TableOfContentsTarget::PreviousPage)
+        );
+        assert_eq!(
+            target(CANVAS_WIDTH - 100, footer_y, 2),
+            Some(TableOfContentsTarget::Panel)
+        );
+    }
+
+    #[test]
+    fn test_turn_page() {
+        let entries = entries(60);
+        let open = |page| TableOfContentsState::Open { page };
+
+        assert_eq!(turn_page(&open(0), 1, &entries, CANVAS_HEIGHT), open(1));
+        assert_eq!(turn_page(&open(0), -1, &entries, CANVAS_HEIGHT), open(0));
+        assert_eq!(turn_page(&open(2), 1, &entries, CANVAS_HEIGHT), open(2));
+    }
+}
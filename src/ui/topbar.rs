@@ -1,16 +1,46 @@
 use cgmath::Point2;
-use cosmic_text::{Attrs, Buffer, Color, FontSystem, Metrics, Shaping, SwashCache, Wrap};
+use cosmic_text::{Attrs, Buffer, Color, Family, FontSystem, Metrics, Shaping, SwashCache, Wrap};
 use image::{load_from_memory, Pixel, Rgba, RgbaImage};
 
-use super::helpers::{draw_box_border, draw_filled_rectangle};
+use crate::fonts::UI_FONT_FAMILY;
+
+use super::helpers::{draw_box_border, draw_filled_rectangle, ellipsize_end, ellipsize_start};
 
 pub enum TopbarState {
+    // Not used yet, but drawn and tappable already
+    #[allow(dead_code)]
     Minimized,
     Normal,
 }
 
+/// What the URL bar shows
+pub enum UrlBarContent<'a> {
+    /// The URL of the current page, if there is one
+    Url(Option<&'a str>),
+    /// Text being typed on the keyboard
+    Editing(&'a str),
+}
+
+/// The parts of the topbar that respond to taps
+#[derive(Debug, PartialEq)]
+pub enum TopbarTarget {
+    Back,
+    Forward,
+    UrlBar,
+    Menu,
+}
+
+struct TopbarLayout {
+    back_icon_x: u32,
+    forward_icon_x: u32,
+    url_bar_x: u32,
+    url_bar_width: u32,
+    menu_icon_x: u32,
+}
+
 const MENU_ICON_MARGIN: u32 = 12;
-const TOPBAR_HEIGHT: u32 = 72;
+const ICON_SIZE: u32 = 48;
+pub const TOPBAR_HEIGHT: u32 = 72;
 
 const URL_BAR_TEXT_SIZE: f32 = 32.0;
 const URL_BAR_TEXT_MARGIN: u32 = 12;
@@ -19,23 +49,31 @@ const COLOR_TOPBAR_BACKGROUND: Rgba<u8> = Rgba([0xAA, 0xAA, 0xAA, 0xFF]);
 const COLOR_URL_BAR_FILL: Rgba<u8> = Rgba([0xDD, 0xDD, 0xDD, 0xFF]);
 const COLOR_URL_BAR_BORDER: Rgba<u8> = Rgba([0x99, 0x99, 0x99, 0xFF]);
 const COLOR_URL_BAR_TEXT: Rgba<u8> = Rgba([0x66, 0x66, 0x66, 0xFF]);
+const COLOR_URL_BAR_FILL_EDITING: Rgba<u8> = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
+const COLOR_URL_BAR_TEXT_EDITING: Rgba<u8> = Rgba([0x00, 0x00, 0x00, 0xFF]);
 
 const ICON_DISABLED_OPACITY: f32 = 0.3;
 
-const MOCK_URL: &str = "https://www.example.com";
+const URL_BAR_PLACEHOLDER: &str = "Tap to enter a URL";
+const URL_BAR_CURSOR: &str = "|";
 
 pub fn add_topbar_overlay(
     screen: &mut RgbaImage,
     font_system: &mut FontSystem,
     cache: &mut SwashCache,
     topbar_state: &TopbarState,
+    can_go_back: bool,
+    can_go_forward: bool,
+    url_bar_content: &UrlBarContent,
 ) {
     let icon_menu = load_from_memory(include_bytes!("../../assets/icons/menu-regular-24.png"))
         .unwrap()
         .to_rgba8();
 
+    let layout = get_topbar_layout(screen.width());
+
     let menu_icon_offset_y = MENU_ICON_MARGIN;
-    let menu_icon_offset_x = screen.width() - icon_menu.width() - MENU_ICON_MARGIN;
+    let menu_icon_offset_x = layout.menu_icon_x;
 
     match topbar_state {
         TopbarState::Minimized => {
@@ -70,13 +108,12 @@ pub fn add_topbar_overlay(
             ))
             .unwrap()
             .to_rgba8();
-            let icon_arrow_left_offset_x = MENU_ICON_MARGIN;
             draw_icon(
                 screen,
                 &icon_arrow_left,
-                icon_arrow_left_offset_x,
+                layout.back_icon_x,
                 menu_icon_offset_y,
-                true,
+                !can_go_back,
             );
 
             let icon_arrow_right = load_from_memory(include_bytes!(
@@ -84,29 +121,33 @@ pub fn add_topbar_overlay(
             ))
             .unwrap()
             .to_rgba8();
-            let icon_arrow_right_offset_x = MENU_ICON_MARGIN * 2 + icon_arrow_left.width();
             draw_icon(
                 screen,
                 &icon_arrow_right,
-                icon_arrow_right_offset_x,
+                layout.forward_icon_x,
                 menu_icon_offset_y,
-                true,
+                !can_go_forward,
             );
 
-            let url_bar_offset_x =
-                MENU_ICON_MARGIN * 3 + icon_arrow_left.width() + icon_arrow_right.width();
+            let url_bar_offset_x = layout.url_bar_x;
             let url_bar_offset_y = MENU_ICON_MARGIN;
-            let url_bar_width =
-                screen.width() - url_bar_offset_x - MENU_ICON_MARGIN * 2 - icon_menu.width();
+            let url_bar_width = layout.url_bar_width;
             let url_bar_height = TOPBAR_HEIGHT - MENU_ICON_MARGIN * 2;
 
+            let is_editing = matches!(url_bar_content, UrlBarContent::Editing(_));
+            let (fill_color, text_rgba) = if is_editing {
+                (COLOR_URL_BAR_FILL_EDITING, COLOR_URL_BAR_TEXT_EDITING)
+            } else {
+                (COLOR_URL_BAR_FILL, COLOR_URL_BAR_TEXT)
+            };
+
             draw_filled_rectangle(
                 Point2::new(url_bar_offset_x, url_bar_offset_y),
                 Point2::new(
                     url_bar_offset_x + url_bar_width - 1,
                     url_bar_offset_y + url_bar_height - 1,
                 ),
-                COLOR_URL_BAR_FILL,
+                fill_color,
                 screen,
             );
 
@@ -121,24 +162,32 @@ pub fn add_topbar_overlay(
             );
 
             let metrics = Metrics::new(URL_BAR_TEXT_SIZE, url_bar_height as f32);
-            let attrs = Attrs::new().metrics(metrics);
-            let text_color = Color::rgba(
-                COLOR_URL_BAR_TEXT[0],
-                COLOR_URL_BAR_TEXT[1],
-                COLOR_URL_BAR_TEXT[2],
-                COLOR_URL_BAR_TEXT[3],
-            );
+            let attrs = Attrs::new()
+                .family(Family::Name(UI_FONT_FAMILY))
+                .metrics(metrics);
+            let text_color = Color::rgba(text_rgba[0], text_rgba[1], text_rgba[2], text_rgba[3]);
+
+            let max_text_width = url_bar_width - URL_BAR_TEXT_MARGIN * 2;
+            let text = match url_bar_content {
+                UrlBarContent::Url(Some(url)) => {
+                    ellipsize_end(font_system, url, attrs, max_text_width)
+                }
+                UrlBarContent::Url(None) => URL_BAR_PLACEHOLDER.to_string(),
+                UrlBarContent::Editing(text) => {
+                    // Keep the end visible, since that's where the cursor is
+                    let text_with_cursor = format!("{}{}", text, URL_BAR_CURSOR);
+                    ellipsize_start(font_system, &text_with_cursor, attrs, max_text_width)
+                }
+            };
 
             let mut buffer = Buffer::new_empty(metrics);
 
             buffer.set_size(font_system, None, None);
             buffer.set_wrap(font_system, Wrap::None);
             buffer.lines.clear();
-            buffer.set_text(font_system, MOCK_URL, attrs, Shaping::Basic);
+            buffer.set_text(font_system, &text, attrs, Shaping::Advanced);
             buffer.shape_until_scroll(font_system, false);
 
-            let layout_run = buffer.layout_runs().next().unwrap();
-            let text_width = layout_run.line_w;
             let text_height = 48.0;
 
             buffer.draw(
@@ -168,7 +217,7 @@ pub fn add_topbar_overlay(
                     let fg = Rgba([fg_r, fg_g, fg_b, fg_a]);
 
                     let bg = screen.get_pixel(canvas_x, canvas_y);
-                    let mut result = bg.clone();
+                    let mut result = *bg;
                     result.blend(&fg);
                     screen.put_pixel(canvas_x, canvas_y, result);
                 },
@@ -193,14 +242,64 @@ fn draw_icon(
             let canvas_x = icon_x + offset_x;
             let canvas_y = icon_y + offset_y;
 
-            let mut fg = icon.get_pixel(icon_x, icon_y).clone();
+            let mut fg = *icon.get_pixel(icon_x, icon_y);
             if is_disabled {
                 fg[3] = (fg[3] as f32 * ICON_DISABLED_OPACITY).round() as u8;
             }
             let bg = screen.get_pixel(canvas_x, canvas_y);
-            let mut result = bg.clone();
+            let mut result = *bg;
             result.blend(&fg);
             screen.put_pixel(canvas_x, canvas_y, result);
         }
     }
 }
+
+/// Finds what part of the topbar was tapped, if any. Tap areas extend halfway into the
+/// margins around each icon, so the icons are easier to hit with a finger.
+pub fn get_topbar_target(
+    x: u32,
+    y: u32,
+    screen_width: u32,
+    topbar_state: &TopbarState,
+) -> Option<TopbarTarget> {
+    let layout = get_topbar_layout(screen_width);
+
+    match topbar_state {
+        TopbarState::Minimized => {
+            if x >= layout.menu_icon_x - MENU_ICON_MARGIN && y < ICON_SIZE + MENU_ICON_MARGIN * 2 {
+                Some(TopbarTarget::Menu)
+            } else {
+                None
+            }
+        }
+        TopbarState::Normal => {
+            if y >= TOPBAR_HEIGHT {
+                None
+            } else if x < layout.forward_icon_x - MENU_ICON_MARGIN / 2 {
+                Some(TopbarTarget::Back)
+            } else if x < layout.url_bar_x {
+                Some(TopbarTarget::Forward)
+            } else if x < layout.url_bar_x + layout.url_bar_width {
+                Some(TopbarTarget::UrlBar)
+            } else {
+                Some(TopbarTarget::Menu)
+            }
+        }
+    }
+}
+
+fn get_topbar_layout(screen_width: u32) -> TopbarLayout {
+    let back_icon_x = MENU_ICON_MARGIN;
+    let forward_icon_x = MENU_ICON_MARGIN * 2 + ICON_SIZE;
+    let url_bar_x = MENU_ICON_MARGIN * 3 + ICON_SIZE * 2;
+    let menu_icon_x = screen_width - ICON_SIZE - MENU_ICON_MARGIN;
+    let url_bar_width = menu_icon_x - MENU_ICON_MARGIN - url_bar_x;
+
+    TopbarLayout {
+        back_icon_x,
+        forward_icon_x,
+        url_bar_x,
+        url_bar_width,
+        menu_icon_x,
+    }
+}
@@ -44,6 +44,8 @@ pub fn run_web_server(user_input_tx: StdSender<UserInputEvent>) {
             .route("/", get(serve_web_ui))
             .route("/navigate", post(handle_navigate_command))
             .route("/render", post(handle_render_command))
+            .route("/font-size/increase", post(handle_increase_font_size))
+            .route("/font-size/decrease", post(handle_decrease_font_size))
             .layer(CorsLayer::permissive())
             .with_state(shared_server_state);
         let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
@@ -128,8 +130,34 @@ async fn handle_render_command(State(state): State<Arc<ServerState>>, req: Reque
         .await
         .unwrap();
 
-    return Response::builder()
+    Response::builder()
         .status(StatusCode::OK)
         .body(Body::empty())
+        .unwrap()
+}
+
+async fn handle_increase_font_size(State(state): State<Arc<ServerState>>) -> Response {
+    state
+        .input_internal_tx
+        .send(UserInputEvent::IncreaseFontSize)
+        .await
         .unwrap();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn handle_decrease_font_size(State(state): State<Arc<ServerState>>) -> Response {
+    state
+        .input_internal_tx
+        .send(UserInputEvent::DecreaseFontSize)
+        .await
+        .unwrap();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap()
 }